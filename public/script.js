@@ -2,8 +2,15 @@ import { initializeApp } from "https://www.gstatic.com/firebasejs/10.6.0/firebas
 import {
   getAuth,
   signInWithPopup,
+  signInWithEmailAndPassword,
+  createUserWithEmailAndPassword,
+  signInAnonymously,
+  linkWithPopup,
+  linkWithCredential,
   onAuthStateChanged,
   signOut,
+  EmailAuthProvider,
+  GithubAuthProvider,
   GoogleAuthProvider,
 } from "https://www.gstatic.com/firebasejs/10.6.0/firebase-auth.js";
 
@@ -16,67 +23,139 @@ const firebaseConfig = {
   appId: "1:137252967327:web:fb0a7df5657eb713c423aa",
 };
 
+// The names of the `sign_in_provider` claim, as shown to players.
+const PROVIDER_NAMES = {
+  "google.com": "Google",
+  "github.com": "GitHub",
+  password: "email and password",
+  anonymous: "a guest account",
+};
+
 initializeApp(firebaseConfig);
 
 const auth = getAuth();
-const signInBtn = document.getElementById("quickstart-sign-in");
 const signInMsg = document.getElementById("quickstart-message");
+const signInSection = document.getElementById("sign-in");
+const signedInSection = document.getElementById("signed-in");
+const signedInAs = document.getElementById("signed-in-as");
 
-function toggleSignIn() {
-  if (!auth.currentUser) {
-    const provider = new GoogleAuthProvider();
-
-    signInWithPopup(auth, provider).catch(function (error) {
-      const errorCode = error.code;
-
-      if (errorCode === "auth/account-exists-with-different-credential") {
-        alert(
-          "You have already signed up with a different auth provider for that email.",
-        );
-      } else {
-        console.error(error);
-      }
-    });
-  } else {
-    signOut(auth);
+function showError(error) {
+  switch (error.code) {
+    case "auth/account-exists-with-different-credential":
+      signInMsg.textContent =
+        "You have already signed up with a different provider for that email. " +
+        "Sign in with it, then link this one.";
+      break;
+    case "auth/credential-already-in-use":
+    case "auth/email-already-in-use":
+      signInMsg.textContent =
+        "That sign-in already belongs to another account. Sign in with it instead.";
+      break;
+    case "auth/popup-closed-by-user":
+      break;
+    default:
+      console.error(error);
+      signInMsg.textContent = error.message;
   }
-  signInBtn.disabled = true;
+}
+
+function sendToken(user) {
+  const params = new URLSearchParams(window.location.search);
+  const session = params.get("session");
+  const profile = params.get("mode") ?? "release";
+
+  let api;
+  if (profile === "release") {
+    api = "http://23.251.139.45:3000/v1/";
+  } else if (profile === "local") {
+    api = "http://localhost:3000/v1/";
+  }
+
+  fetch(api + session, {
+    method: "POST",
+    mode: "no-cors",
+    body: user.accessToken,
+  });
+}
+
+async function showSignedIn(user) {
+  const { signInProvider } = await user.getIdTokenResult();
+  const name = user.displayName ?? user.email ?? "Guest";
+  const provider = PROVIDER_NAMES[signInProvider] ?? signInProvider;
+  signedInAs.textContent = `Signed in as ${name} with ${provider}.`;
+
+  // Only offer what is not linked yet.
+  const linked = user.providerData.map((data) => data.providerId);
+  document.getElementById("link-google").hidden = linked.includes("google.com");
+  document.getElementById("link-github").hidden = linked.includes("github.com");
+  document.getElementById("link-email").hidden = linked.includes("password");
+}
+
+function emailAndPassword(emailId, passwordId) {
+  return [
+    document.getElementById(emailId).value,
+    document.getElementById(passwordId).value,
+  ];
 }
 
 function initApp() {
   onAuthStateChanged(auth, function (user) {
+    signInSection.hidden = !!user;
+    signedInSection.hidden = !user;
+
     if (user) {
-      signInBtn.textContent = "Sign out";
-
-      const params = new URLSearchParams(window.location.search);
-      const session = params.get("session");
-      const profile = params.get("mode") ?? "release";
-
-      let api;
-      if (profile === "release") {
-        api = "http://23.251.139.45:3000/v1/";
-      } else if (profile === "local") {
-        api = "http://localhost:3000/v1/";
-      }
-
-      fetch(api + session, {
-        method: "POST",
-        mode: "no-cors",
-        body: user.accessToken,
-      });
+      sendToken(user);
+      showSignedIn(user);
       signInMsg.textContent =
         "You can safely close this window now, and return to the app";
     } else {
-      signInBtn.textContent = "Sign in with Google";
+      signInMsg.textContent = "Choose how you want to sign in below.";
     }
-    signInBtn.disabled = false;
   });
 
-  signInBtn.addEventListener("click", toggleSignIn);
+  document.getElementById("sign-in-google").addEventListener("click", () => {
+    signInWithPopup(auth, new GoogleAuthProvider()).catch(showError);
+  });
+  document.getElementById("sign-in-github").addEventListener("click", () => {
+    signInWithPopup(auth, new GithubAuthProvider()).catch(showError);
+  });
+  document.getElementById("sign-in-email").addEventListener("submit", (event) => {
+    event.preventDefault();
+    const [email, password] = emailAndPassword("email", "password");
+    signInWithEmailAndPassword(auth, email, password).catch(showError);
+  });
+  document.getElementById("sign-up-email").addEventListener("click", () => {
+    const [email, password] = emailAndPassword("email", "password");
+    createUserWithEmailAndPassword(auth, email, password).catch(showError);
+  });
+  document.getElementById("sign-in-anonymous").addEventListener("click", () => {
+    signInAnonymously(auth).catch(showError);
+  });
+
+  // Linking keeps the user id, so the characters stay with the account. The
+  // session still holds the token from before the link, so send a fresh one.
+  const linked = async () => {
+    const user = auth.currentUser;
+    await user.getIdToken(true);
+    sendToken(user);
+    showSignedIn(user);
+  };
+  document.getElementById("link-google").addEventListener("click", () => {
+    linkWithPopup(auth.currentUser, new GoogleAuthProvider()).then(linked, showError);
+  });
+  document.getElementById("link-github").addEventListener("click", () => {
+    linkWithPopup(auth.currentUser, new GithubAuthProvider()).then(linked, showError);
+  });
+  document.getElementById("link-email").addEventListener("submit", (event) => {
+    event.preventDefault();
+    const [email, password] = emailAndPassword("link-email-address", "link-password");
+    const credential = EmailAuthProvider.credential(email, password);
+    linkWithCredential(auth.currentUser, credential).then(linked, showError);
+  });
 
-  if (!auth.currentUser) {
-    toggleSignIn();
-  }
+  document.getElementById("sign-out").addEventListener("click", () => {
+    signOut(auth);
+  });
 }
 
 window.onload = function () {
@@ -0,0 +1,74 @@
+//! Dev identity mode, where tyche-auth signs tokens itself instead of waiting
+//! for Firebase. Only built with the `dev` feature, and only on when
+//! `DEV_IDENTITY` is set.
+
+use std::sync::{Arc, RwLock};
+
+use axum::{
+    extract::{Query, State},
+    http::{header::CACHE_CONTROL, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use tyche_identity::dev::DevIssuer;
+
+use crate::AppState;
+
+pub fn issuer() -> Option<DevIssuer> {
+    tyche_identity::dev_identity().then(|| {
+        println!("Dev identity mode, signing tokens with a local key");
+        DevIssuer::generate()
+    })
+}
+
+/// Who to sign a dev token for, everything has a default so a bare request
+/// still gets one.
+#[derive(Debug, Deserialize)]
+pub struct DevUser {
+    user_id: Option<String>,
+    name: Option<String>,
+    provider: Option<String>,
+}
+
+impl DevUser {
+    pub fn sign(&self, dev: &DevIssuer) -> String {
+        let name = self.name.as_deref().unwrap_or("Dev Player");
+        let user_id = self.user_id.clone().unwrap_or_else(|| {
+            let slug: String = name
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        '-'
+                    }
+                })
+                .collect();
+            format!("dev-{slug}")
+        });
+        let provider = self.provider.as_deref().unwrap_or("dev");
+        dev.sign(&user_id, name, provider)
+    }
+}
+
+pub async fn jwks(State(state): State<Arc<RwLock<AppState>>>) -> impl IntoResponse {
+    let state = state.read().unwrap();
+    let Some(dev) = &state.dev else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    Ok(([(CACHE_CONTROL, "public, max-age=3600")], Json(dev.jwks())))
+}
+
+/// Signs a token for anyone who asks, for tests and bots. Only available in
+/// dev identity mode.
+pub async fn token(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Query(user): Query<DevUser>,
+) -> Result<String, StatusCode> {
+    let state = state.read().unwrap();
+    let dev = state.dev.as_ref().ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(user.sign(dev))
+}
@@ -1,9 +1,14 @@
+#[cfg(feature = "dev")]
+mod dev;
+
 use std::{
     collections::HashMap,
     env,
     sync::{Arc, RwLock},
 };
 
+#[cfg(feature = "dev")]
+use axum::extract::Query;
 use axum::{
     extract::{Path, State},
     http::StatusCode,
@@ -12,20 +17,35 @@ use axum::{
 };
 use uuid::Uuid;
 
-#[derive(Debug, Default)]
+#[derive(Default)]
 struct AppState {
     sessions: HashMap<Uuid, Option<String>>,
+    /// Signs tokens itself instead of waiting for Firebase, see [`dev`].
+    #[cfg(feature = "dev")]
+    dev: Option<tyche_identity::dev::DevIssuer>,
 }
 
 #[tokio::main]
 async fn main() {
-    let shared_state = Arc::new(RwLock::new(AppState::default()));
+    #[cfg(not(feature = "dev"))]
+    if tyche_identity::dev_identity() {
+        eprintln!("DEV_IDENTITY is set, but tyche-auth was built without the dev feature");
+    }
+    let shared_state = Arc::new(RwLock::new(AppState {
+        #[cfg(feature = "dev")]
+        dev: dev::issuer(),
+        ..AppState::default()
+    }));
 
     let app = Router::new()
         .route("/v1", get(generate_auth_session))
         .route("/v1/:id", post(receive_token))
-        .route("/v1/:id", get(get_token))
-        .with_state(shared_state);
+        .route("/v1/:id", get(get_token));
+    #[cfg(feature = "dev")]
+    let app = app
+        .route("/dev/jwks", get(dev::jwks))
+        .route("/dev/token", get(dev::token));
+    let app = app.with_state(shared_state);
 
     let port = env::var("PORT").unwrap_or("3000".to_string());
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
@@ -34,11 +54,20 @@ async fn main() {
     axum::serve(listener, app).await.unwrap();
 }
 
-async fn generate_auth_session(State(state): State<Arc<RwLock<AppState>>>) -> String {
-    let sessions = &mut state.write().unwrap().sessions;
+async fn generate_auth_session(
+    State(state): State<Arc<RwLock<AppState>>>,
+    #[cfg(feature = "dev")] Query(user): Query<dev::DevUser>,
+) -> String {
+    let state = &mut *state.write().unwrap();
     let id = Uuid::new_v4();
 
-    sessions.insert(id, None);
+    // There is no browser sign-in in dev identity mode, so the session
+    // comes with its token right away.
+    #[cfg(feature = "dev")]
+    let token = state.dev.as_ref().map(|dev| user.sign(dev));
+    #[cfg(not(feature = "dev"))]
+    let token = None;
+    state.sessions.insert(id, token);
 
     id.to_string()
 }
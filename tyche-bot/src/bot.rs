@@ -0,0 +1,388 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    net::UdpSocket,
+    time::{Duration, Instant, SystemTime},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::Rng;
+use renet::{
+    transport::{ClientAuthentication, NetcodeClientTransport},
+    ConnectionConfig, DefaultChannel, RenetClient,
+};
+use serde::Deserialize;
+use tyche_protocol::{
+    map::CELL_SIZE,
+    token::{Health, TokenId},
+    CharacterInfo, ClientMessage, ServerMessage, PROTOCOL_ID,
+};
+
+use crate::{metrics::Metrics, Config};
+
+/// How long the host gets to answer before an action counts as lost.
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Bots wander around where they started, so they stay in sight of each other.
+const WANDER_CELLS: f32 = 5.0;
+
+/// Who a bot plays as, taken from the claims of its token.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub token: String,
+    pub user_id: String,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    user_id: Option<String>,
+    sub: Option<String>,
+    name: Option<String>,
+}
+
+impl Identity {
+    pub fn from_token(token: String) -> Result<Self, String> {
+        let payload = token.split('.').nth(1).ok_or("it is not a JWT")?;
+        let payload = URL_SAFE_NO_PAD
+            .decode(payload.trim_end_matches('='))
+            .map_err(|error| error.to_string())?;
+        let claims: Claims = serde_json::from_slice(&payload).map_err(|error| error.to_string())?;
+
+        Ok(Self {
+            token,
+            user_id: claims
+                .user_id
+                .or(claims.sub)
+                .ok_or("it has no user_id or sub claim")?,
+            name: claims.name.unwrap_or("Bot".to_owned()),
+        })
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Connecting { since: Instant },
+    Joining { since: Instant },
+    Joined,
+    Gone,
+}
+
+/// The token the bot plays and where it is.
+#[derive(Debug)]
+struct Own {
+    id: TokenId,
+    home: [f32; 2],
+    at: [f32; 2],
+}
+
+pub struct Bot {
+    index: usize,
+    identity: Identity,
+    client: RenetClient,
+    transport: NetcodeClientTransport,
+    state: State,
+    client_id: u64,
+    /// The last delta applied, `None` while waiting for a snapshot.
+    seq: Option<u64>,
+    token: Option<Own>,
+    next_move_seq: u32,
+    moves: BTreeMap<u32, (Instant, [f32; 2])>,
+    rolls: VecDeque<Instant>,
+    chats: VecDeque<Instant>,
+    next_move: Instant,
+    next_roll: Instant,
+    next_chat: Instant,
+}
+
+impl Bot {
+    pub fn connect(config: &Config, identity: Identity, index: usize) -> Self {
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let current_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        let authentication = ClientAuthentication::Unsecure {
+            client_id: current_time.as_millis() as u64 + index as u64,
+            protocol_id: PROTOCOL_ID,
+            server_addr: config.host,
+            user_data: None,
+        };
+        let transport = NetcodeClientTransport::new(current_time, authentication, socket).unwrap();
+        let now = Instant::now();
+
+        Self {
+            index,
+            identity,
+            client: RenetClient::new(ConnectionConfig::default()),
+            transport,
+            state: State::Connecting { since: now },
+            client_id: 0,
+            seq: None,
+            token: None,
+            next_move_seq: 0,
+            moves: BTreeMap::new(),
+            rolls: VecDeque::new(),
+            chats: VecDeque::new(),
+            next_move: next(now, config.moves),
+            next_roll: next(now, config.rolls),
+            next_chat: next(now, config.chats),
+        }
+    }
+
+    pub fn tick(&mut self, delta: Duration, now: Instant, config: &Config, metrics: &mut Metrics) {
+        if matches!(self.state, State::Gone) {
+            return;
+        }
+
+        self.client.update(delta);
+        if let Err(error) = self.transport.update(delta, &mut self.client) {
+            eprintln!("Bot {}: {error}", self.index);
+        }
+
+        if self.client.is_disconnected() {
+            let reason = self.client.disconnect_reason();
+            eprintln!("Bot {} lost the connection: {reason:?}", self.index);
+            metrics.disconnects += 1;
+            self.state = State::Gone;
+            return;
+        }
+
+        match self.state {
+            State::Connecting { since } if self.client.is_connected() => {
+                self.join(config);
+                metrics.joins.sent += 1;
+                self.state = State::Joining { since };
+            }
+            State::Connecting { since } | State::Joining { since } if now - since > TIMEOUT => {
+                eprintln!("Bot {} could not join {}", self.index, config.room);
+                metrics.joins.timeouts += 1;
+                self.transport.disconnect();
+                self.state = State::Gone;
+                return;
+            }
+            _ => {}
+        }
+
+        while let Some(bytes) = self.client.receive_message(DefaultChannel::ReliableOrdered) {
+            match tyche_protocol::decode::<ServerMessage>(&bytes) {
+                Some(message) => self.receive(message, now, metrics),
+                None => metrics.malformed += 1,
+            }
+        }
+
+        if matches!(self.state, State::Joined) {
+            self.act(now, config, metrics);
+        }
+        self.expire(now, metrics);
+
+        if let Err(error) = self.transport.send_packets(&mut self.client) {
+            eprintln!("Bot {}: {error}", self.index);
+        }
+    }
+
+    /// Whatever is still unanswered is too recent to count as lost.
+    pub fn disconnect(&mut self) {
+        if !matches!(self.state, State::Gone) {
+            self.transport.disconnect();
+        }
+    }
+
+    fn send(&mut self, message: &ClientMessage) {
+        self.client.send_message(
+            DefaultChannel::ReliableOrdered,
+            tyche_protocol::encode(message),
+        );
+    }
+
+    fn join(&mut self, config: &Config) {
+        let character = CharacterInfo {
+            id: self.index as u64,
+            name: self.identity.name.clone(),
+            initiative_bonus: 0,
+            saves: [0; 6],
+            health: Health {
+                current: 10,
+                max: 10,
+                temp: 0,
+            },
+            token_image: None,
+        };
+        self.send(&ClientMessage::JoinRoom {
+            room: config.room.clone(),
+            token: self.identity.token.clone(),
+            character: Some(character),
+        });
+    }
+
+    fn receive(&mut self, message: ServerMessage, now: Instant, metrics: &mut Metrics) {
+        match message {
+            ServerMessage::Joined {
+                client_id,
+                seq,
+                tokens,
+                ..
+            } => {
+                if let State::Joining { since } = self.state {
+                    metrics.joins.acked(now - since);
+                    self.state = State::Joined;
+                }
+                self.client_id = client_id;
+                self.seq = Some(seq);
+                self.token = tokens
+                    .iter()
+                    .find(|token| token.owner.as_ref() == Some(&self.identity.user_id))
+                    .map(|token| Own {
+                        id: token.id,
+                        home: [token.x, token.y],
+                        at: [token.x, token.y],
+                    });
+            }
+            ServerMessage::Delta { seq, changes, .. } => {
+                let Some(last) = self.seq else {
+                    return;
+                };
+                if seq != last + 1 {
+                    eprintln!("Bot {} missed a delta, resyncing", self.index);
+                    metrics.resyncs += 1;
+                    self.seq = None;
+                    self.send(&ClientMessage::RequestSnapshot);
+                    return;
+                }
+                self.seq = Some(seq);
+                for change in changes {
+                    self.receive(change, now, metrics);
+                }
+            }
+            ServerMessage::TokenSpawned(token)
+                if token.owner.as_ref() == Some(&self.identity.user_id) =>
+            {
+                self.token = Some(Own {
+                    id: token.id,
+                    home: [token.x, token.y],
+                    at: [token.x, token.y],
+                });
+            }
+            ServerMessage::TokenRemoved(token)
+                if self.token.as_ref().is_some_and(|own| own.id == token) =>
+            {
+                self.token = None;
+            }
+            ServerMessage::TokenMoved {
+                token,
+                x,
+                y,
+                client_id,
+                seq,
+            } => {
+                let Some(own) = self.token.as_mut().filter(|own| own.id == token) else {
+                    return;
+                };
+                own.at = [x, y];
+                if client_id != self.client_id {
+                    return;
+                }
+
+                // The host only sends the last of several moves in a tick, so
+                // this answers the earlier ones too.
+                let answered: Vec<u32> = self.moves.range(..=seq).map(|(seq, _)| *seq).collect();
+                for answered_seq in answered {
+                    let (sent, target) = self.moves.remove(&answered_seq).unwrap();
+                    if answered_seq == seq && target != [x, y] {
+                        metrics.moves.rejected += 1;
+                    } else {
+                        metrics.moves.acked(now - sent);
+                    }
+                }
+            }
+            ServerMessage::Rolled(roll) if roll.client_id == self.client_id => {
+                if let Some(sent) = self.rolls.pop_front() {
+                    metrics.rolls.acked(now - sent);
+                }
+            }
+            ServerMessage::Chat(message) if message.client_id == self.client_id => {
+                if let Some(sent) = self.chats.pop_front() {
+                    metrics.chats.acked(now - sent);
+                }
+            }
+            ServerMessage::Error(error) => {
+                eprintln!("Bot {}: {error}", self.index);
+                metrics.errors += 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn act(&mut self, now: Instant, config: &Config, metrics: &mut Metrics) {
+        if now >= self.next_move {
+            self.next_move = next(now, config.moves);
+            self.move_token(now, metrics);
+        }
+        if now >= self.next_roll {
+            self.next_roll = next(now, config.rolls);
+            self.send(&ClientMessage::Roll {
+                expression: config.expression.clone(),
+                label: Some("Bot roll".to_owned()),
+                secret: false,
+            });
+            self.rolls.push_back(now);
+            metrics.rolls.sent += 1;
+        }
+        if now >= self.next_chat {
+            self.next_chat = next(now, config.chats);
+            let text = format!("Hello from bot {} ({})", self.index, metrics.chats.sent);
+            self.send(&ClientMessage::Chat(text));
+            self.chats.push_back(now);
+            metrics.chats.sent += 1;
+        }
+    }
+
+    /// Steps to a neighbouring cell, staying close to where the token started.
+    fn move_token(&mut self, now: Instant, metrics: &mut Metrics) {
+        let Some(own) = &self.token else {
+            return;
+        };
+
+        let mut rng = rand::thread_rng();
+        let mut target = own.at;
+        for (axis, home) in target.iter_mut().zip(own.home) {
+            let step = rng.gen_range(-1..=1) as f32 * CELL_SIZE;
+            *axis = (*axis + step).clamp(
+                home - WANDER_CELLS * CELL_SIZE,
+                home + WANDER_CELLS * CELL_SIZE,
+            );
+        }
+        let (token, [x, y]) = (own.id, target);
+
+        let seq = self.next_move_seq;
+        self.next_move_seq = self.next_move_seq.wrapping_add(1);
+        self.send(&ClientMessage::MoveToken { token, x, y, seq });
+        self.moves.insert(seq, (now, target));
+        metrics.moves.sent += 1;
+    }
+
+    /// Gives up on actions the host never answered.
+    fn expire(&mut self, now: Instant, metrics: &mut Metrics) {
+        let before = self.moves.len();
+        self.moves.retain(|_, (sent, _)| now - *sent < TIMEOUT);
+        metrics.moves.timeouts += (before - self.moves.len()) as u64;
+
+        for (queue, action) in [
+            (&mut self.rolls, &mut metrics.rolls),
+            (&mut self.chats, &mut metrics.chats),
+        ] {
+            while queue.front().is_some_and(|sent| now - *sent >= TIMEOUT) {
+                queue.pop_front();
+                action.timeouts += 1;
+            }
+        }
+    }
+}
+
+/// When to do something next at the given rate, with some jitter so the bots
+/// do not all act in the same tick.
+fn next(now: Instant, per_second: f64) -> Instant {
+    if per_second <= 0.0 {
+        return now + Duration::from_secs(u32::MAX as u64);
+    }
+    let jitter = rand::thread_rng().gen_range(0.5..1.5);
+    now + Duration::from_secs_f64(jitter / per_second)
+}
@@ -0,0 +1,171 @@
+//! A headless player for load-testing tyche-host and for end-to-end tests.
+//!
+//! Every bot connects on its own, joins the room with a character and then
+//! moves its token, rolls and chats at the configured rates. Metrics are
+//! printed every few seconds and once more at the end, and the exit code is
+//! non-zero when anything went wrong.
+//!
+//! Configured through the environment:
+//! - `DEV_IDENTITY`: `1` to have a tyche-auth in dev identity mode sign a
+//!   token for every bot, `DEV_AUTH_SERVICE` is its address
+//! - `BOT_TOKEN`: otherwise, the token of the one bot
+//! - `HOST_SERVICE`: the host to connect to, `127.0.0.1:5000` by default
+//! - `BOT_ROOM`: the room to join, `bots` by default
+//! - `BOT_COUNT`: how many bots to run, 1 by default
+//! - `BOT_MOVES`, `BOT_ROLLS`, `BOT_CHATS`: actions per second of each bot
+//! - `BOT_ROLL`: the dice expression to roll, `1d20 + 5` by default
+//! - `BOT_SECONDS`: how long to run, 60 by default and 0 to run until stopped
+
+mod bot;
+mod metrics;
+
+use std::{
+    env,
+    fmt::Debug,
+    net::SocketAddr,
+    process::ExitCode,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bot::{Bot, Identity};
+use metrics::Metrics;
+
+const TICK: Duration = Duration::from_micros(16_667);
+const REPORT_EVERY: Duration = Duration::from_secs(10);
+
+/// What the bots do, read from the environment like the other services.
+#[derive(Debug)]
+pub struct Config {
+    pub host: SocketAddr,
+    pub room: String,
+    pub bots: usize,
+    /// Actions per second, for every bot.
+    pub moves: f64,
+    pub rolls: f64,
+    pub chats: f64,
+    pub expression: String,
+    /// Runs until interrupted when not set.
+    pub duration: Option<Duration>,
+}
+
+impl Config {
+    fn from_env() -> Self {
+        let seconds: u64 = var("BOT_SECONDS", 60);
+        Self {
+            host: var("HOST_SERVICE", "127.0.0.1:5000".parse().unwrap()),
+            room: var("BOT_ROOM", "bots".to_owned()),
+            bots: var("BOT_COUNT", 1),
+            moves: var("BOT_MOVES", 1.0),
+            rolls: var("BOT_ROLLS", 0.2),
+            chats: var("BOT_CHATS", 0.1),
+            expression: var("BOT_ROLL", "1d20 + 5".to_owned()),
+            duration: (seconds > 0).then(|| Duration::from_secs(seconds)),
+        }
+    }
+}
+
+fn var<T: FromStr>(name: &str, default: T) -> T
+where
+    T::Err: Debug,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|error| panic!("{name} is not valid: {error:?}")),
+        Err(_) => default,
+    }
+}
+
+/// Bots sign in as users of their own when tyche-auth can sign tokens for
+/// them, the host only keeps one connection per user.
+fn identities(bots: usize) -> Result<Vec<Identity>, String> {
+    if let Some(auth_service) = tyche_identity::dev_auth_service() {
+        return (0..bots)
+            .map(|index| {
+                let user_id = format!("bot-{index}");
+                let name = format!("Bot {}", index + 1);
+                let token = tyche_identity::dev_token(&auth_service, &user_id, &name)
+                    .map_err(|error| error.to_string())?;
+                Identity::from_token(token)
+            })
+            .collect();
+    }
+
+    let token = env::var("BOT_TOKEN").map_err(|_| {
+        "set BOT_TOKEN to a test token, or DEV_IDENTITY=1 to sign in through tyche-auth"
+    })?;
+    if bots > 1 {
+        return Err("bots sharing BOT_TOKEN would keep replacing each other".to_owned());
+    }
+    Ok(vec![Identity::from_token(token)?])
+}
+
+fn main() -> ExitCode {
+    let config = Config::from_env();
+    let identities = match identities(config.bots) {
+        Ok(identities) => identities,
+        Err(error) => {
+            eprintln!("Cannot sign the bots in: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let running = Arc::new(AtomicBool::new(true));
+    {
+        let running = running.clone();
+        ctrlc::set_handler(move || running.store(false, Ordering::SeqCst)).unwrap();
+    }
+
+    println!(
+        "Starting {} bot(s) in {} on {}",
+        config.bots, config.room, config.host
+    );
+    let mut bots: Vec<Bot> = identities
+        .into_iter()
+        .enumerate()
+        .map(|(index, identity)| Bot::connect(&config, identity, index))
+        .collect();
+
+    let mut metrics = Metrics::default();
+    let start = Instant::now();
+    let mut last_tick = start;
+    let mut last_report = start;
+
+    let end = config.duration.map(|duration| start + duration);
+
+    while running.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if end.is_some_and(|end| now >= end) {
+            break;
+        }
+        let delta = now - last_tick;
+        last_tick = now;
+
+        for bot in &mut bots {
+            bot.tick(delta, now, &config, &mut metrics);
+        }
+
+        if now - last_report >= REPORT_EVERY {
+            metrics.report(start.elapsed());
+            last_report = now;
+        }
+        thread::sleep(TICK.saturating_sub(now.elapsed()));
+    }
+
+    for bot in &mut bots {
+        bot.disconnect();
+    }
+    metrics.report(start.elapsed());
+
+    if metrics.failed(config.bots) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+/// How one kind of action fared, from sending it to seeing the host's answer.
+#[derive(Debug, Default)]
+pub struct Action {
+    pub sent: u64,
+    /// The host answered, but not with what was asked for.
+    pub rejected: u64,
+    pub timeouts: u64,
+    latencies: Vec<Duration>,
+}
+
+impl Action {
+    pub fn acked(&mut self, latency: Duration) {
+        self.latencies.push(latency);
+    }
+
+    fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+        let index = (sorted.len() * percent / 100).min(sorted.len() - 1);
+        sorted[index]
+    }
+
+    fn summary(&self) -> String {
+        let mut line = format!(
+            "{} sent, {} acked, {} rejected, {} timed out",
+            self.sent,
+            self.latencies.len(),
+            self.rejected,
+            self.timeouts
+        );
+        if !self.latencies.is_empty() {
+            let mut sorted = self.latencies.clone();
+            sorted.sort();
+            let total: Duration = sorted.iter().sum();
+            line += &format!(
+                ", latency min {:?} avg {:?} p50 {:?} p95 {:?} max {:?}",
+                sorted[0],
+                total / sorted.len() as u32,
+                Self::percentile(&sorted, 50),
+                Self::percentile(&sorted, 95),
+                sorted[sorted.len() - 1],
+            );
+        }
+        line
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub joins: Action,
+    pub moves: Action,
+    pub rolls: Action,
+    pub chats: Action,
+    /// Error messages from the host.
+    pub errors: u64,
+    /// Messages that could not be decoded.
+    pub malformed: u64,
+    pub disconnects: u64,
+    /// Snapshots requested after a missed delta.
+    pub resyncs: u64,
+}
+
+impl Metrics {
+    pub fn report(&self, elapsed: Duration) {
+        println!("After {}s:", elapsed.as_secs());
+        println!("  joins: {}", self.joins.summary());
+        println!("  moves: {}", self.moves.summary());
+        println!("  rolls: {}", self.rolls.summary());
+        println!("  chats: {}", self.chats.summary());
+        println!(
+            "  {} errors, {} malformed messages, {} disconnects, {} resyncs",
+            self.errors, self.malformed, self.disconnects, self.resyncs
+        );
+    }
+
+    /// Whether the run should fail a test: a bot did not get in, or
+    /// something it did went unanswered or wrong.
+    pub fn failed(&self, bots: usize) -> bool {
+        let actions = [&self.moves, &self.rolls, &self.chats];
+        self.joins.latencies.len() < bots
+            || actions
+                .iter()
+                .any(|action| action.rejected > 0 || action.timeouts > 0)
+            || self.errors > 0
+            || self.malformed > 0
+            || self.disconnects > 0
+    }
+}
@@ -0,0 +1,166 @@
+//! Portraits and token art of characters. Uploads are checked, shrunk to a
+//! thumbnail and stored by their hash in `ART_DIR`, the same way the host
+//! stores map images.
+
+use std::{io::Cursor, path::Path as FilePath, sync::Arc};
+
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path, State},
+    http::{header::CONTENT_TYPE, HeaderName, StatusCode},
+    Json,
+};
+use image::{imageops::FilterType, io::Reader, ImageFormat, ImageOutputFormat};
+use tokio::{fs, sync::RwLock};
+use tyche_identity::FirebaseUser;
+use tyche_protocol::{
+    asset::{AssetHash, AssetInfo},
+    character::Character,
+};
+
+use crate::AppState;
+
+const MAX_UPLOAD_SIZE: usize = 4 * 1024 * 1024;
+/// Bigger images are turned away before decoding them.
+const MAX_DIMENSION: u32 = 4096;
+const PORTRAIT_SIZE: u32 = 512;
+const TOKEN_SIZE: u32 = 256;
+
+#[derive(Clone, Copy, Debug)]
+enum Art {
+    Portrait,
+    Token,
+}
+
+pub async fn upload_portrait(
+    state: State<Arc<RwLock<AppState>>>,
+    user: Extension<FirebaseUser>,
+    id: Path<u64>,
+    bytes: Bytes,
+) -> Result<Json<Character>, (StatusCode, &'static str)> {
+    upload(state, user, id, bytes, Art::Portrait).await
+}
+
+pub async fn upload_token(
+    state: State<Arc<RwLock<AppState>>>,
+    user: Extension<FirebaseUser>,
+    id: Path<u64>,
+    bytes: Bytes,
+) -> Result<Json<Character>, (StatusCode, &'static str)> {
+    upload(state, user, id, bytes, Art::Token).await
+}
+
+async fn upload(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Extension(user): Extension<FirebaseUser>,
+    Path(id): Path<u64>,
+    bytes: Bytes,
+    art: Art,
+) -> Result<Json<Character>, (StatusCode, &'static str)> {
+    let not_found = (StatusCode::NOT_FOUND, "");
+    let dir = {
+        let state = state.read().await;
+        if !state
+            .characters
+            .iter()
+            .any(|stored| stored.is(id, &user.user_id))
+        {
+            return Err(not_found);
+        }
+        state.art_dir.clone()
+    };
+    if bytes.len() > MAX_UPLOAD_SIZE {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, "Images can be at most 4 MiB"));
+    }
+
+    // Decoding and resizing takes a while, so keep it off the async workers.
+    let thumbnail = tokio::task::spawn_blocking(move || thumbnail(&bytes, art))
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, ""))??;
+    let info = AssetInfo::of(&thumbnail).map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, ""))?;
+    store(&dir, &info.hash, &thumbnail)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, ""))?;
+
+    let mut state = state.write().await;
+    let character = state
+        .characters
+        .iter_mut()
+        .find(|stored| stored.is(id, &user.user_id))
+        .map(|stored| &mut stored.character)
+        .ok_or(not_found)?;
+    match art {
+        Art::Portrait => character.portrait = Some(info),
+        Art::Token => character.token_image = Some(info),
+    }
+    Ok(Json(character.clone()))
+}
+
+/// Anyone signed in may see the art, the hash is only known to those who
+/// were shown the character or its tokens.
+pub async fn get_art(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Path(hash): Path<String>,
+) -> Result<([(HeaderName, &'static str); 1], Vec<u8>), StatusCode> {
+    let hash = AssetHash::parse(&hash).ok_or(StatusCode::NOT_FOUND)?;
+    let path = state.read().await.art_dir.join(hash.to_string());
+    let bytes = fs::read(path).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    Ok(([(CONTENT_TYPE, "image/png")], bytes))
+}
+
+/// Checks an upload is a PNG, JPEG or WebP image of a sensible size, and
+/// shrinks it to a PNG thumbnail. Token art is cropped to a square.
+fn thumbnail(bytes: &[u8], art: Art) -> Result<Vec<u8>, (StatusCode, &'static str)> {
+    let unsupported = (
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "Images have to be PNG, JPEG or WebP",
+    );
+    let format = image::guess_format(bytes).map_err(|_| unsupported)?;
+    if !matches!(
+        format,
+        ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP
+    ) {
+        return Err(unsupported);
+    }
+
+    let invalid = (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "The image could not be read",
+    );
+    let reader = || Reader::with_format(Cursor::new(bytes), format);
+    let (width, height) = reader().into_dimensions().map_err(|_| invalid)?;
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Images can be at most 4096 by 4096 pixels",
+        ));
+    }
+    let image = reader().decode().map_err(|_| invalid)?;
+
+    let thumbnail = match art {
+        Art::Portrait if width > PORTRAIT_SIZE || height > PORTRAIT_SIZE => {
+            image.resize(PORTRAIT_SIZE, PORTRAIT_SIZE, FilterType::Lanczos3)
+        }
+        Art::Portrait => image,
+        Art::Token => image.resize_to_fill(TOKEN_SIZE, TOKEN_SIZE, FilterType::Lanczos3),
+    };
+
+    let mut png = Vec::new();
+    thumbnail
+        .write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, ""))?;
+    Ok(png)
+}
+
+/// Stores a thumbnail unless it is already there.
+async fn store(dir: &FilePath, hash: &AssetHash, bytes: &[u8]) -> std::io::Result<()> {
+    let path = dir.join(hash.to_string());
+    if fs::try_exists(&path).await? {
+        return Ok(());
+    }
+
+    fs::create_dir_all(dir).await?;
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, bytes).await?;
+    fs::rename(&temporary, &path).await
+}
@@ -0,0 +1,815 @@
+//! Imports characters built elsewhere. Fields are mapped onto a [`Character`]
+//! one at a time, and whatever has no place on it is listed in the report
+//! that comes back with the character, rather than dropped.
+//!
+//! # Tyche JSON, `POST /v1/import/tyche`
+//!
+//! The JSON tyche-character hands out for a character:
+//!
+//! ```json
+//! {
+//!     "name": "Vex",
+//!     "abilities": {
+//!         "strength": 8, "dexterity": 16, "constitution": 12,
+//!         "intelligence": 14, "wisdom": 10, "charisma": 13
+//!     },
+//!     "proficiency_bonus": 2,
+//!     "hit_points": { "current": 9, "max": 9, "temp": 0 },
+//!     "saving_throws": ["dexterity", "intelligence"],
+//!     "skills": ["stealth", "sleight_of_hand"],
+//!     "attacks": [
+//!         { "name": "Dagger", "ability": "dexterity", "proficient": true, "damage": "1d4" }
+//!     ]
+//! }
+//! ```
+//!
+//! Only `name` is required, anything left out is what a new character starts
+//! with. Abilities and skills are written in snake case. `id` is assigned
+//! anew and art has to be uploaded again.
+//!
+//! # Foundry VTT, `POST /v1/import/foundry-dnd5e`
+//!
+//! Player characters of the dnd5e system, as exported with "Export Data" on
+//! the actor. Weapons become attacks, their ability modifier is left out of
+//! the damage as the sheet adds it when rolling. Class levels set the
+//! proficiency bonus when the export does not have one. Expertise is imported
+//! as proficiency. Foundry's own bookkeeping, such as ids, folders,
+//! permissions, flags and token settings, is left out of the report, and so
+//! are the weight, price and description of weapons.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::RwLock;
+use tyche_identity::FirebaseUser;
+use tyche_protocol::character::{Abilities, Ability, Attack, Character, HitPoints, Skill};
+
+use crate::AppState;
+
+/// Each ability with its name in Tyche JSON and its key in Foundry.
+const ABILITIES: [(Ability, &str, &str); 6] = [
+    (Ability::Strength, "strength", "str"),
+    (Ability::Dexterity, "dexterity", "dex"),
+    (Ability::Constitution, "constitution", "con"),
+    (Ability::Intelligence, "intelligence", "int"),
+    (Ability::Wisdom, "wisdom", "wis"),
+    (Ability::Charisma, "charisma", "cha"),
+];
+
+/// Each skill with its key in Foundry and the key of the ability it uses.
+const FOUNDRY_SKILLS: [(Skill, &str, &str); 18] = [
+    (Skill::Acrobatics, "acr", "dex"),
+    (Skill::AnimalHandling, "ani", "wis"),
+    (Skill::Arcana, "arc", "int"),
+    (Skill::Athletics, "ath", "str"),
+    (Skill::Deception, "dec", "cha"),
+    (Skill::History, "his", "int"),
+    (Skill::Insight, "ins", "wis"),
+    (Skill::Intimidation, "itm", "cha"),
+    (Skill::Investigation, "inv", "int"),
+    (Skill::Medicine, "med", "wis"),
+    (Skill::Nature, "nat", "int"),
+    (Skill::Perception, "prc", "wis"),
+    (Skill::Performance, "prf", "cha"),
+    (Skill::Persuasion, "per", "cha"),
+    (Skill::Religion, "rel", "int"),
+    (Skill::SleightOfHand, "slt", "dex"),
+    (Skill::Stealth, "ste", "dex"),
+    (Skill::Survival, "sur", "wis"),
+];
+
+/// Parts of a Foundry actor that only matter to Foundry.
+const FOUNDRY_BOOKKEEPING: [&str; 7] = [
+    "_id",
+    "_stats",
+    "folder",
+    "sort",
+    "ownership",
+    "flags",
+    "prototypeToken",
+];
+
+/// Class levels past this are not counted towards the proficiency bonus.
+const MAX_LEVEL: i32 = 20;
+
+const NO_FIELD: &str = "Tyche has no field for it";
+
+type ImportError = (StatusCode, &'static str);
+
+#[derive(Debug, Serialize)]
+pub struct Imported {
+    character: Character,
+    unmapped: Vec<Unmapped>,
+}
+
+/// Something in the import that did not make it onto the character.
+#[derive(Debug, Serialize)]
+struct Unmapped {
+    /// Where it is in the import, such as `system.currency.gp`.
+    field: String,
+    value: Value,
+    reason: String,
+}
+
+#[derive(Debug, Default)]
+struct Report(Vec<Unmapped>);
+
+impl Report {
+    fn unmapped(&mut self, field: impl Into<String>, value: Value, reason: impl Into<String>) {
+        self.0.push(Unmapped {
+            field: field.into(),
+            value,
+            reason: reason.into(),
+        });
+    }
+
+    /// Reports what is left of an import once everything that could be
+    /// mapped was taken out of it. Empty values, zeros and `false` carry
+    /// nothing and are left out.
+    fn leftovers(&mut self, field: &str, value: &Value, reason: &str) {
+        let join = |key: &str| match field.is_empty() {
+            true => key.to_owned(),
+            false => format!("{field}.{key}"),
+        };
+
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object {
+                    self.leftovers(&join(key), value, reason);
+                }
+            }
+            Value::Array(array) if array.iter().any(|v| v.is_array() || v.is_object()) => {
+                for (i, value) in array.iter().enumerate() {
+                    self.leftovers(&format!("{field}[{i}]"), value, reason);
+                }
+            }
+            value if !is_empty(value) => self.unmapped(field, value.clone(), reason),
+            _ => {}
+        }
+    }
+}
+
+pub async fn import_character(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Extension(user): Extension<FirebaseUser>,
+    Path(format): Path<String>,
+    Json(import): Json<Value>,
+) -> Result<Json<Imported>, ImportError> {
+    if !import.is_object() {
+        return Err((StatusCode::BAD_REQUEST, "Imports have to be JSON objects"));
+    }
+
+    let mut report = Report::default();
+    let character = match format.as_str() {
+        "tyche" => tyche(import, &mut report)?,
+        "foundry-dnd5e" => foundry_dnd5e(import, &mut report)?,
+        _ => return Err((StatusCode::NOT_FOUND, "")),
+    };
+
+    let character = state.write().await.add(character, user.user_id);
+    Ok(Json(Imported {
+        character,
+        unmapped: report.0,
+    }))
+}
+
+fn tyche(mut import: Value, report: &mut Report) -> Result<Character, ImportError> {
+    let mut character = Character::new(name(&mut import, &["name"])?);
+    take(&mut import, &["id"]);
+    for field in ["portrait", "token_image"] {
+        if let Some(art) = take(&mut import, &[field]).filter(|art| !art.is_null()) {
+            report.unmapped(field, art, "Art has to be uploaded again");
+        }
+    }
+
+    for (ability, key, _) in ABILITIES {
+        if let Some(score) = int(&mut import, &["abilities", key], report) {
+            *character.abilities.score_mut(ability) = score;
+        }
+    }
+    if let Some(bonus) = int(&mut import, &["proficiency_bonus"], report) {
+        character.proficiency_bonus = bonus;
+    }
+    let hit_points = &mut character.hit_points;
+    for (key, field) in [
+        ("current", &mut hit_points.current),
+        ("max", &mut hit_points.max),
+        ("temp", &mut hit_points.temp),
+    ] {
+        if let Some(value) = int(&mut import, &["hit_points", key], report) {
+            *field = value;
+        }
+    }
+    let fields = ["current", "max", "temp"].map(|key| format!("hit_points.{key}"));
+    character.hit_points = in_range(character.hit_points, fields, report);
+
+    character.saving_throws = list(&mut import, "saving_throws", report);
+    character.skills = list(&mut import, "skills", report);
+    character.attacks = list(&mut import, "attacks", report);
+
+    report.leftovers("", &import, NO_FIELD);
+    Ok(character)
+}
+
+fn foundry_dnd5e(mut import: Value, report: &mut Report) -> Result<Character, ImportError> {
+    if let Some(Value::String(system)) = take(&mut import, &["_stats", "systemId"]) {
+        if system != "dnd5e" {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Only actors of the dnd5e system can be imported",
+            ));
+        }
+    }
+    if let Some(kind) = take(&mut import, &["type"]) {
+        if kind != "character" {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Only player characters can be imported",
+            ));
+        }
+    }
+
+    let mut character = Character::new(name(&mut import, &["name"])?);
+    if let Some(art) = take(&mut import, &["prototypeToken", "texture", "src"]) {
+        report.leftovers(
+            "prototypeToken.texture.src",
+            &art,
+            "Art has to be uploaded again",
+        );
+    }
+    if let Some(art) = take(&mut import, &["img"]) {
+        report.leftovers("img", &art, "Art has to be uploaded again");
+    }
+    for key in FOUNDRY_BOOKKEEPING {
+        take(&mut import, &[key]);
+    }
+
+    for (ability, _, key) in ABILITIES {
+        if let Some(score) = int(&mut import, &["system", "abilities", key, "value"], report) {
+            *character.abilities.score_mut(ability) = score;
+        }
+        let proficient = take(&mut import, &["system", "abilities", key, "proficient"]);
+        if proficient
+            .as_ref()
+            .and_then(number)
+            .is_some_and(|p| p >= 1.0)
+        {
+            character.saving_throws.push(ability);
+        }
+    }
+
+    for (skill, key, ability) in FOUNDRY_SKILLS {
+        let field = format!("system.skills.{key}");
+        if let Some(value) = take(&mut import, &["system", "skills", key, "value"]) {
+            match number(&value) {
+                Some(p) if p >= 2.0 => {
+                    character.skills.push(skill);
+                    report.unmapped(
+                        format!("{field}.value"),
+                        value,
+                        "Expertise is imported as proficiency",
+                    );
+                }
+                Some(p) if p >= 1.0 => character.skills.push(skill),
+                Some(p) if p > 0.0 => report.unmapped(
+                    format!("{field}.value"),
+                    value,
+                    "Tyche has no half proficiency",
+                ),
+                _ => {}
+            }
+        }
+        match take(&mut import, &["system", "skills", key, "ability"]) {
+            Some(Value::String(used)) if used != ability && !used.is_empty() => report.unmapped(
+                format!("{field}.ability"),
+                Value::String(used),
+                "Skills always use their usual ability in Tyche",
+            ),
+            _ => {}
+        }
+    }
+
+    let current = int(
+        &mut import,
+        &["system", "attributes", "hp", "value"],
+        report,
+    );
+    let max = int(&mut import, &["system", "attributes", "hp", "max"], report);
+    let temp = int(&mut import, &["system", "attributes", "hp", "temp"], report);
+    // Newer versions work out the maximum from the class hit dice.
+    let max = max.or(current).unwrap_or(character.hit_points.max);
+    let read = HitPoints {
+        current: current.unwrap_or(max),
+        max,
+        temp: temp.unwrap_or(0),
+    };
+    let fields = ["value", "max", "temp"].map(|key| format!("system.attributes.hp.{key}"));
+    character.hit_points = in_range(read, fields, report);
+
+    let bonus = int(&mut import, &["system", "attributes", "prof"], report);
+    let mut level: i32 = 0;
+    if let Some(Value::Array(items)) = take(&mut import, &["items"]) {
+        for (i, mut item) in items.into_iter().enumerate() {
+            let field = format!("items[{i}]");
+            let name = take(&mut item, &["name"]).unwrap_or(Value::Null);
+            let kind = take(&mut item, &["type"]).unwrap_or(Value::Null);
+            match kind.as_str() {
+                Some("weapon") => {
+                    let name = name.as_str().unwrap_or("Weapon").to_owned();
+                    let attack =
+                        foundry_attack(&mut item, name, &character.abilities, &field, report);
+                    character.attacks.push(attack);
+                }
+                Some("class") => {
+                    let levels = take(&mut item, &["system", "levels"])
+                        .as_ref()
+                        .and_then(number)
+                        .unwrap_or(1.0);
+                    level = level
+                        .saturating_add(levels.clamp(1.0, MAX_LEVEL as f64) as i32)
+                        .min(MAX_LEVEL);
+                    report.unmapped(field, name, "Classes only set the proficiency bonus");
+                }
+                Some(kind) => {
+                    report.unmapped(field, name, format!("Tyche has no place for {kind} items"))
+                }
+                None => report.unmapped(field, name, "The item has no type"),
+            }
+        }
+    }
+    character.proficiency_bonus = match (bonus, level) {
+        (Some(bonus), _) => bonus,
+        (None, 0) => character.proficiency_bonus,
+        (None, level) => 2 + (level - 1) / 4,
+    };
+
+    if let Some(Value::Array(effects)) = take(&mut import, &["effects"]) {
+        for (i, mut effect) in effects.into_iter().enumerate() {
+            let name = take(&mut effect, &["name"])
+                .or_else(|| take(&mut effect, &["label"]))
+                .unwrap_or(Value::Null);
+            report.unmapped(format!("effects[{i}]"), name, "Tyche has no active effects");
+        }
+    }
+
+    report.leftovers("", &import, NO_FIELD);
+    Ok(character)
+}
+
+/// Keeps hit points in range the way the sheet does, reporting each one that
+/// had to change. `fields` name the current, maximum and temporary hit points
+/// in the import.
+fn in_range(read: HitPoints, fields: [String; 3], report: &mut Report) -> HitPoints {
+    let max = read.max.max(1);
+    let kept = HitPoints {
+        current: read.current.clamp(0, max),
+        max,
+        temp: read.temp.max(0),
+    };
+
+    let [current, max, temp] = fields;
+    for (field, read, kept) in [
+        (current, read.current, kept.current),
+        (max, read.max, kept.max),
+        (temp, read.temp, kept.temp),
+    ] {
+        if read != kept {
+            report.unmapped(field, read.into(), format!("Out of range, set to {kept}"));
+        }
+    }
+    kept
+}
+
+/// Turns a Foundry weapon into an attack, reporting what it cannot express.
+fn foundry_attack(
+    item: &mut Value,
+    name: String,
+    abilities: &Abilities,
+    field: &str,
+    report: &mut Report,
+) -> Attack {
+    let field = format!("{field}.system");
+    let properties: Vec<String> = match take(item, &["system", "properties"]) {
+        // Newer versions list them, older ones flag each.
+        Some(Value::Array(properties)) => properties
+            .iter()
+            .filter_map(|p| p.as_str().map(str::to_owned))
+            .collect(),
+        Some(Value::Object(properties)) => properties
+            .into_iter()
+            .filter(|(_, set)| set.as_bool() == Some(true))
+            .map(|(property, _)| property)
+            .collect(),
+        _ => Vec::new(),
+    };
+    let action = take(item, &["system", "actionType"]);
+    let kind =
+        take(item, &["system", "type", "value"]).or_else(|| take(item, &["system", "weaponType"]));
+    let ranged = action.as_ref().and_then(Value::as_str) == Some("rwak")
+        || kind
+            .as_ref()
+            .and_then(Value::as_str)
+            .is_some_and(|kind| kind.ends_with('R'));
+
+    let chosen = take(item, &["system", "ability"])
+        .and_then(|ability| {
+            let ability = ability.as_str()?.to_owned();
+            ABILITIES.into_iter().find(|(_, _, key)| *key == ability)
+        })
+        .map(|(ability, ..)| ability);
+    let ability = match chosen {
+        Some(ability) => ability,
+        None if ranged => Ability::Dexterity,
+        None if properties.iter().any(|p| p == "fin")
+            && abilities.dexterity > abilities.strength =>
+        {
+            Ability::Dexterity
+        }
+        None => Ability::Strength,
+    };
+
+    // Left out, Foundry works it out from the class proficiencies.
+    let proficient = match take(item, &["system", "proficient"]) {
+        Some(Value::Bool(proficient)) => proficient,
+        Some(proficient) => number(&proficient).unwrap_or(1.0) >= 1.0,
+        None => true,
+    };
+
+    let mut formula = None;
+    if let Some(Value::Array(parts)) = take(item, &["system", "damage", "parts"]) {
+        for (i, part) in parts.into_iter().enumerate() {
+            let dice = part.get(0).and_then(Value::as_str).unwrap_or("").to_owned();
+            match formula {
+                None => {
+                    formula = Some(dice);
+                    if let Some(kind) = part.get(1).filter(|kind| !is_empty(kind)) {
+                        report.unmapped(
+                            format!("{field}.damage.parts[{i}][1]"),
+                            kind.clone(),
+                            "Tyche has no damage types",
+                        );
+                    }
+                }
+                Some(_) => report.unmapped(
+                    format!("{field}.damage.parts[{i}]"),
+                    part,
+                    "Attacks only have one damage roll",
+                ),
+            }
+        }
+    }
+    let count = take(item, &["system", "damage", "base", "number"])
+        .as_ref()
+        .and_then(number);
+    let die = take(item, &["system", "damage", "base", "denomination"])
+        .as_ref()
+        .and_then(number);
+    let bonus = take(item, &["system", "damage", "base", "bonus"]);
+    if let (None, Some(count), Some(die)) = (&formula, count, die) {
+        let bonus = bonus
+            .as_ref()
+            .and_then(Value::as_str)
+            .filter(|b| !b.trim().is_empty());
+        formula = Some(match bonus {
+            Some(bonus) => format!("{count}d{die} + {bonus}"),
+            None => format!("{count}d{die}"),
+        });
+    }
+
+    let formula = formula.unwrap_or_default();
+    let mut terms = Vec::new();
+    for term in formula.split('+').map(str::trim).filter(|t| !t.is_empty()) {
+        match term {
+            "@mod" => {}
+            term if term.contains('@') => report.unmapped(
+                format!("{field}.damage"),
+                Value::String(term.to_owned()),
+                "Foundry roll data cannot be worked out outside of Foundry",
+            ),
+            term => terms.push(term),
+        }
+    }
+    let damage = match terms.is_empty() {
+        true => {
+            report.unmapped(
+                format!("{field}.damage"),
+                Value::String(formula.clone()),
+                "The damage was set to 1",
+            );
+            "1".to_owned()
+        }
+        false => terms.join(" + "),
+    };
+
+    for key in ["attackBonus", "magicalBonus"] {
+        if let Some(bonus) = take(item, &["system", key]).filter(|b| !is_empty(b)) {
+            report.unmapped(
+                format!("{field}.{key}"),
+                bonus,
+                "Attacks have no bonus of their own",
+            );
+        }
+    }
+    if let Some(versatile) = take(item, &["system", "damage", "versatile"]).filter(|v| !is_empty(v))
+    {
+        report.unmapped(
+            format!("{field}.damage.versatile"),
+            versatile,
+            "Attacks only have one damage roll",
+        );
+    }
+
+    Attack {
+        name,
+        ability,
+        proficient,
+        damage,
+    }
+}
+
+/// Takes a value out of the import, so that it is not reported as left over.
+fn take(value: &mut Value, path: &[&str]) -> Option<Value> {
+    let (last, parents) = path.split_last()?;
+    let mut value = value;
+    for key in parents {
+        value = value.get_mut(*key)?;
+    }
+    value.as_object_mut()?.remove(*last)
+}
+
+fn name(import: &mut Value, path: &[&str]) -> Result<String, ImportError> {
+    take(import, path)
+        .as_ref()
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_owned)
+        .ok_or((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "The character has no name",
+        ))
+}
+
+/// Numbers can also be strings, Foundry stores some of them that way.
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(number) => number.trim().parse().ok(),
+        Value::Bool(set) => Some(f64::from(u8::from(*set))),
+        _ => None,
+    }
+}
+
+/// Takes a whole number out of the import, reporting it if it is not one.
+fn int(import: &mut Value, path: &[&str], report: &mut Report) -> Option<i32> {
+    let value = take(import, path).filter(|value| !value.is_null())?;
+    match number(&value) {
+        Some(number) if number.fract() == 0.0 && number.abs() < 1e6 => Some(number as i32),
+        _ => {
+            report.unmapped(path.join("."), value, "Not a whole number");
+            None
+        }
+    }
+}
+
+/// Takes a list of things Tyche knows out of the import, reporting the ones
+/// it does not.
+fn list<T: serde::de::DeserializeOwned>(
+    import: &mut Value,
+    key: &str,
+    report: &mut Report,
+) -> Vec<T> {
+    let Some(value) = take(import, &[key]) else {
+        return Vec::new();
+    };
+    let Value::Array(values) = value else {
+        report.unmapped(key, value, "Not a list");
+        return Vec::new();
+    };
+
+    let mut list = Vec::new();
+    for (i, value) in values.into_iter().enumerate() {
+        match serde_json::from_value(value.clone()) {
+            Ok(item) => list.push(item),
+            Err(error) => report.unmapped(format!("{key}[{i}]"), value, error.to_string()),
+        }
+    }
+    list
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null | Value::Bool(false) => true,
+        Value::Number(number) => number.as_f64() == Some(0.0),
+        Value::String(string) => string.trim().is_empty(),
+        Value::Array(array) => array.is_empty(),
+        Value::Object(object) => object.is_empty(),
+        Value::Bool(true) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn fields(report: &Report) -> Vec<&str> {
+        report
+            .0
+            .iter()
+            .map(|unmapped| unmapped.field.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn tyche_json_round_trips() {
+        let mut character = Character::new("Vex".to_owned());
+        character.id = 7;
+        *character.abilities.score_mut(Ability::Dexterity) = 16;
+        character.proficiency_bonus = 3;
+        character.hit_points = HitPoints {
+            current: 5,
+            max: 9,
+            temp: 2,
+        };
+        character.saving_throws = vec![Ability::Dexterity, Ability::Intelligence];
+        character.skills = vec![Skill::Stealth, Skill::SleightOfHand];
+        character.attacks = vec![Attack {
+            name: "Dagger".to_owned(),
+            ability: Ability::Dexterity,
+            proficient: true,
+            damage: "1d4".to_owned(),
+        }];
+
+        let mut report = Report::default();
+        let mut imported = tyche(serde_json::to_value(&character).unwrap(), &mut report).unwrap();
+
+        assert_eq!(imported.id, 0);
+        imported.id = character.id;
+        assert_eq!(
+            serde_json::to_value(&imported).unwrap(),
+            serde_json::to_value(&character).unwrap()
+        );
+        assert!(report.0.is_empty(), "{:?}", report.0);
+    }
+
+    #[test]
+    fn tyche_reports_what_it_cannot_keep() {
+        let import = json!({
+            "name": " Vex ",
+            "hit_points": { "current": 30, "max": 10, "temp": -2 },
+            "skills": ["stealth", "juggling"],
+            "abilities": { "strength": "strong" },
+            "portrait": { "hash": "abc" },
+            "notes": "Owes the guild money",
+        });
+
+        let mut report = Report::default();
+        let character = tyche(import, &mut report).unwrap();
+
+        assert_eq!(character.name, "Vex");
+        assert_eq!(
+            character.hit_points,
+            HitPoints {
+                current: 10,
+                max: 10,
+                temp: 0
+            }
+        );
+        assert_eq!(character.skills, [Skill::Stealth]);
+        assert_eq!(character.abilities.strength, Abilities::default().strength);
+        assert_eq!(
+            fields(&report),
+            [
+                "portrait",
+                "abilities.strength",
+                "hit_points.current",
+                "hit_points.temp",
+                "skills[1]",
+                "notes",
+            ]
+        );
+        assert_eq!(report.0[2].value, json!(30));
+    }
+
+    #[test]
+    fn tyche_requires_a_name() {
+        let mut report = Report::default();
+        let error = tyche(json!({ "name": "  " }), &mut report).unwrap_err();
+        assert_eq!(error.0, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[test]
+    fn foundry_dnd5e_actor() {
+        let import = json!({
+            "_id": "c4Z8lSxUJKbBdkqN",
+            "name": "Vex",
+            "type": "character",
+            "img": "icons/vex.webp",
+            "_stats": { "systemId": "dnd5e", "systemVersion": "3.0.0" },
+            "system": {
+                "abilities": {
+                    "str": { "value": 10, "proficient": 0 },
+                    "dex": { "value": 16, "proficient": 1 },
+                    "int": { "value": "14", "proficient": 1 },
+                },
+                "skills": {
+                    "acr": { "value": 0.5, "ability": "dex" },
+                    "prc": { "value": 1, "ability": "wis" },
+                    "ste": { "value": 2, "ability": "dex" },
+                    "ath": { "value": 0, "ability": "dex" },
+                },
+                "attributes": { "hp": { "value": 12, "max": 10, "temp": 0 } },
+                "currency": { "gp": 15, "sp": 0 },
+            },
+            "items": [
+                {
+                    "name": "Dagger",
+                    "type": "weapon",
+                    "system": {
+                        "properties": ["fin", "lgt", "thr"],
+                        "actionType": "mwak",
+                        "damage": { "parts": [["1d4 + @mod", "piercing"]] },
+                    },
+                },
+                { "name": "Rogue", "type": "class", "system": { "levels": 5 } },
+                { "name": "Rope", "type": "loot" },
+            ],
+            "effects": [{ "name": "Bless" }],
+        });
+
+        let mut report = Report::default();
+        let character = foundry_dnd5e(import, &mut report).unwrap();
+
+        assert_eq!(character.name, "Vex");
+        assert_eq!(character.abilities.dexterity, 16);
+        assert_eq!(character.abilities.intelligence, 14);
+        assert_eq!(
+            character.saving_throws,
+            [Ability::Dexterity, Ability::Intelligence]
+        );
+        assert_eq!(character.skills, [Skill::Perception, Skill::Stealth]);
+        assert_eq!(
+            character.hit_points,
+            HitPoints {
+                current: 10,
+                max: 10,
+                temp: 0
+            }
+        );
+        assert_eq!(character.proficiency_bonus, 3);
+        assert_eq!(character.attacks.len(), 1);
+        let dagger = &character.attacks[0];
+        assert_eq!(dagger.name, "Dagger");
+        assert_eq!(dagger.ability, Ability::Dexterity);
+        assert!(dagger.proficient);
+        assert_eq!(dagger.damage, "1d4");
+
+        assert_eq!(
+            fields(&report),
+            [
+                "img",
+                "system.skills.acr.value",
+                "system.skills.ath.ability",
+                "system.skills.ste.value",
+                "system.attributes.hp.value",
+                "items[0].system.damage.parts[0][1]",
+                "items[1]",
+                "items[2]",
+                "effects[0]",
+                "system.currency.gp",
+            ]
+        );
+        assert_eq!(report.0[4].value, json!(12));
+        assert_eq!(report.0[4].reason, "Out of range, set to 10");
+        assert_eq!(report.0[5].value, json!("piercing"));
+    }
+
+    #[test]
+    fn foundry_class_levels_stop_at_twenty() {
+        let class = json!({ "name": "Wizard", "type": "class", "system": { "levels": 1e12 } });
+        let import = json!({ "name": "Vex", "items": [class.clone(), class, { "type": "class", "system": { "levels": -5 } }] });
+
+        let mut report = Report::default();
+        let character = foundry_dnd5e(import, &mut report).unwrap();
+
+        assert_eq!(character.proficiency_bonus, 6);
+        assert_eq!(fields(&report), ["items[0]", "items[1]", "items[2]"]);
+    }
+
+    #[test]
+    fn foundry_refuses_other_systems_and_npcs() {
+        let mut report = Report::default();
+        let other = json!({ "name": "Vex", "_stats": { "systemId": "pf2e" } });
+        assert!(foundry_dnd5e(other, &mut report).is_err());
+        let npc = json!({ "name": "Goblin", "type": "npc" });
+        assert!(foundry_dnd5e(npc, &mut report).is_err());
+    }
+}
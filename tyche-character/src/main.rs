@@ -1,16 +1,41 @@
-use std::sync::Arc;
+mod art;
+mod import;
+mod profile;
 
-use axum::{extract::State, routing::get, Json, Router};
-use serde::{Deserialize, Serialize};
+use std::{env, path::PathBuf, sync::Arc};
+
+use axum::{
+    extract::{Extension, Path, Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, post, put},
+    Json, Router,
+};
 use tokio::sync::RwLock;
+use tyche_identity::{FirebaseUser, Verifier};
+use tyche_protocol::{character::Character, token::CharacterId};
 
 #[tokio::main]
 async fn main() {
-    let shared_state = Arc::new(RwLock::new(AppState::default()));
+    let shared_state = Arc::new(RwLock::new(AppState {
+        art_dir: env::var("ART_DIR")
+            .unwrap_or("character-art".to_owned())
+            .into(),
+        ..AppState::default()
+    }));
+    let verifier = Arc::new(Verifier::from_env());
 
     // build our application with a single route
     let app = Router::new()
         .route("/v1", get(get_characters).post(create_character))
+        .route("/v1/:id", put(update_character))
+        .route("/v1/:id/portrait", put(art::upload_portrait))
+        .route("/v1/:id/token", put(art::upload_token))
+        .route("/v1/art/:hash", get(art::get_art))
+        .route("/v1/import/:format", post(import::import_character))
+        .merge(profile::routes())
+        .route_layer(middleware::from_fn_with_state(verifier, authenticate))
         .with_state(shared_state);
 
     // run our app with hyper, listening globally on port 3000
@@ -18,23 +43,111 @@ async fn main() {
     axum::serve(listener, app).await.unwrap();
 }
 
+/// Turns away requests without a valid token, and hands the user it belongs
+/// to on to the handlers.
+async fn authenticate(
+    State(verifier): State<Arc<Verifier>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .ok_or(StatusCode::UNAUTHORIZED)?
+        .to_owned();
+
+    // Fetching the public keys blocks, so keep it off the async workers.
+    let user = tokio::task::spawn_blocking(move || verifier.verify(&token))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+
+    request.extensions_mut().insert(user);
+    Ok(next.run(request).await)
+}
+
 #[derive(Debug, Default)]
 struct AppState {
-    characters: Vec<Character>,
+    characters: Vec<Stored>,
+    next_id: u64,
+    art_dir: PathBuf,
+}
+
+impl AppState {
+    /// Stores a new character of `owner`, which has no art yet.
+    fn add(&mut self, mut character: Character, owner: String) -> Character {
+        self.next_id += 1;
+        character.id = self.next_id;
+        character.portrait = None;
+        character.token_image = None;
+        self.characters.push(Stored {
+            owner,
+            character: character.clone(),
+        });
+        character
+    }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-struct Character {
-    name: String,
+/// A character together with the user id of the player, which stays the
+/// same when they link another sign-in provider to their account.
+#[derive(Debug)]
+struct Stored {
+    owner: String,
+    character: Character,
+}
+
+impl Stored {
+    fn is(&self, id: CharacterId, owner: &str) -> bool {
+        self.character.id == id && self.owner == owner
+    }
 }
 
 async fn create_character(
     State(state): State<Arc<RwLock<AppState>>>,
+    Extension(user): Extension<FirebaseUser>,
     Json(character): Json<Character>,
-) {
-    state.write().await.characters.push(character);
+) -> Json<Character> {
+    let character = state.write().await.add(character, user.user_id);
+    Json(character)
 }
 
-async fn get_characters(State(state): State<Arc<RwLock<AppState>>>) -> Json<Vec<Character>> {
-    Json(state.read().await.characters.clone())
+/// Replaces a character sheet, used to keep it in sync with its token.
+async fn update_character(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Extension(user): Extension<FirebaseUser>,
+    Path(id): Path<u64>,
+    Json(mut character): Json<Character>,
+) -> StatusCode {
+    let mut state = state.write().await;
+    let Some(existing) = state
+        .characters
+        .iter_mut()
+        .find(|stored| stored.is(id, &user.user_id))
+        .map(|stored| &mut stored.character)
+    else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    character.id = id;
+    character.portrait = existing.portrait;
+    character.token_image = existing.token_image;
+    *existing = character;
+    StatusCode::NO_CONTENT
+}
+
+/// The characters of whoever is asking.
+async fn get_characters(
+    State(state): State<Arc<RwLock<AppState>>>,
+    Extension(user): Extension<FirebaseUser>,
+) -> Json<Vec<Character>> {
+    let state = state.read().await;
+    let characters = state
+        .characters
+        .iter()
+        .filter(|stored| stored.owner == user.user_id)
+        .map(|stored| stored.character.clone())
+        .collect();
+    Json(characters)
 }
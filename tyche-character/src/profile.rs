@@ -0,0 +1,220 @@
+//! How players show up to others: a display name, a color and an avatar.
+//! Unlike characters, profiles are kept on disk, in `PROFILE_DIR`.
+
+use std::{env, io::ErrorKind, path::PathBuf, sync::Arc};
+
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path, State},
+    http::{header::CONTENT_TYPE, HeaderName, StatusCode},
+    routing::{get, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tyche_identity::FirebaseUser;
+use tyche_protocol::map::ImageFormat;
+
+const MAX_NAME_LENGTH: usize = 32;
+const MAX_AVATAR_SIZE: usize = 256 * 1024;
+/// Colors players get until they pick their own.
+const PALETTE: [[u8; 3]; 8] = [
+    [38, 89, 204],
+    [204, 38, 38],
+    [46, 160, 67],
+    [219, 143, 20],
+    [142, 68, 173],
+    [22, 160, 160],
+    [214, 84, 150],
+    [120, 120, 120],
+];
+
+pub fn routes<S: Clone + Send + Sync + 'static>() -> Router<S> {
+    let store = ProfileStore {
+        dir: env::var("PROFILE_DIR")
+            .unwrap_or("profiles".to_owned())
+            .into(),
+    };
+
+    Router::new()
+        .route("/v1/profile", get(get_profile).put(update_profile))
+        .route("/v1/profile/avatar", put(upload_avatar))
+        .route("/v1/profile/:user_id/avatar", get(get_avatar))
+        .with_state(Arc::new(store))
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct Profile {
+    display_name: String,
+    color: [u8; 3],
+    /// The content type of the avatar, if the player uploaded one.
+    #[serde(default)]
+    avatar: Option<String>,
+}
+
+impl Profile {
+    /// What a player starts out with, taken from how they signed in.
+    fn new(user: &FirebaseUser) -> Self {
+        let email_name = user
+            .email
+            .as_deref()
+            .and_then(|email| email.split('@').next());
+        let name = user
+            .name
+            .as_deref()
+            .or(email_name)
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .unwrap_or("Player");
+
+        let hash = user.user_id.bytes().fold(0usize, |hash, byte| {
+            hash.wrapping_mul(31).wrapping_add(byte as usize)
+        });
+
+        Self {
+            display_name: name.chars().take(MAX_NAME_LENGTH).collect(),
+            color: PALETTE[hash % PALETTE.len()],
+            avatar: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ProfileStore {
+    dir: PathBuf,
+}
+
+impl ProfileStore {
+    /// User ids end up in file names, so only the characters Firebase and
+    /// dev identities use are allowed.
+    fn path(&self, user_id: &str, extension: &str) -> Result<PathBuf, StatusCode> {
+        let valid = !user_id.is_empty()
+            && user_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Ok(self.dir.join(format!("{user_id}.{extension}")))
+    }
+
+    async fn load(&self, user_id: &str) -> Result<Option<Profile>, StatusCode> {
+        match fs::read(self.path(user_id, "json")?).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+            Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    async fn load_or_new(&self, user: &FirebaseUser) -> Result<Profile, StatusCode> {
+        let profile = self.load(&user.user_id).await?;
+        Ok(profile.unwrap_or_else(|| Profile::new(user)))
+    }
+
+    async fn write(&self, user_id: &str, extension: &str, bytes: &[u8]) -> Result<(), StatusCode> {
+        let path = self.path(user_id, extension)?;
+        fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        fs::write(path, bytes)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    async fn save(&self, user_id: &str, profile: &Profile) -> Result<(), StatusCode> {
+        let json = serde_json::to_vec_pretty(profile).expect("profiles are serializable");
+        self.write(user_id, "json", &json).await
+    }
+}
+
+/// The profile of whoever is asking, made up from their sign-in if they
+/// never saved one.
+async fn get_profile(
+    State(store): State<Arc<ProfileStore>>,
+    Extension(user): Extension<FirebaseUser>,
+) -> Result<Json<Profile>, StatusCode> {
+    store.load_or_new(&user).await.map(Json)
+}
+
+async fn update_profile(
+    State(store): State<Arc<ProfileStore>>,
+    Extension(user): Extension<FirebaseUser>,
+    Json(update): Json<Profile>,
+) -> Result<Json<Profile>, (StatusCode, &'static str)> {
+    let display_name = update.display_name.trim();
+    if display_name.is_empty() || display_name.chars().count() > MAX_NAME_LENGTH {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Display names are 1 to 32 characters long",
+        ));
+    }
+    if display_name.chars().any(char::is_control) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Display names cannot contain control characters",
+        ));
+    }
+
+    let mut profile = store.load_or_new(&user).await.map_err(no_reason)?;
+    profile.display_name = display_name.to_owned();
+    profile.color = update.color;
+    store
+        .save(&user.user_id, &profile)
+        .await
+        .map_err(no_reason)?;
+    Ok(Json(profile))
+}
+
+/// Replaces the avatar with a PNG, JPEG or WebP image.
+async fn upload_avatar(
+    State(store): State<Arc<ProfileStore>>,
+    Extension(user): Extension<FirebaseUser>,
+    bytes: Bytes,
+) -> Result<Json<Profile>, (StatusCode, &'static str)> {
+    if bytes.len() > MAX_AVATAR_SIZE {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "Avatars can be at most 256 KiB",
+        ));
+    }
+    let Some(format) = ImageFormat::detect(&bytes) else {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Avatars have to be PNG, JPEG or WebP images",
+        ));
+    };
+
+    let mut profile = store.load_or_new(&user).await.map_err(no_reason)?;
+    store
+        .write(&user.user_id, "avatar", &bytes)
+        .await
+        .map_err(no_reason)?;
+    profile.avatar = Some(format.mime().to_owned());
+    store
+        .save(&user.user_id, &profile)
+        .await
+        .map_err(no_reason)?;
+    Ok(Json(profile))
+}
+
+/// Anyone signed in may see the avatars of the other players.
+async fn get_avatar(
+    State(store): State<Arc<ProfileStore>>,
+    Path(user_id): Path<String>,
+) -> Result<([(HeaderName, String); 1], Vec<u8>), StatusCode> {
+    let content_type = store
+        .load(&user_id)
+        .await?
+        .and_then(|profile| profile.avatar)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let bytes = fs::read(store.path(&user_id, "avatar")?)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    Ok(([(CONTENT_TYPE, content_type)], bytes))
+}
+
+fn no_reason(status: StatusCode) -> (StatusCode, &'static str) {
+    (status, "")
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    env, fs,
+    path::PathBuf,
+};
+
+use bevy::prelude::*;
+use tyche_protocol::{
+    asset::{AssetHash, AssetInfo},
+    map::CHUNK_SIZE,
+    ClientMessage, ServerMessage,
+};
+
+use crate::network::{Connection, HostMessage, ToHost};
+
+const UPLOAD_CHUNKS_PER_TICK: usize = 2;
+
+pub struct AssetCachePlugin;
+
+impl Plugin for AssetCachePlugin {
+    fn build(&self, app: &mut App) {
+        let dir = env::var("ASSET_CACHE").unwrap_or("asset-cache".to_owned());
+
+        app.insert_resource(AssetCache::new(dir.into()))
+            .add_event::<AssetDownloaded>()
+            .add_systems(
+                Update,
+                (
+                    (handle_asset_messages, request_assets).chain(),
+                    drop_transfers.run_if(resource_exists_and_changed::<Connection>()),
+                ),
+            )
+            .add_systems(FixedUpdate, send_upload);
+    }
+}
+
+/// An asset that finished downloading and has been cached.
+#[derive(Event)]
+pub struct AssetDownloaded {
+    pub hash: AssetHash,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct Download {
+    info: AssetInfo,
+    bytes: Vec<u8>,
+    next_chunk: usize,
+}
+
+/// Assets kept on disk by their hash, so they are only ever downloaded once.
+#[derive(Debug, Resource)]
+pub struct AssetCache {
+    dir: PathBuf,
+    /// Missing assets, requested from the host together at the end of the frame.
+    wanted: Vec<AssetHash>,
+    downloads: HashMap<AssetHash, Download>,
+    upload: VecDeque<ClientMessage>,
+    uploading: Option<AssetHash>,
+}
+
+impl AssetCache {
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            wanted: Vec::new(),
+            downloads: HashMap::new(),
+            upload: VecDeque::new(),
+            uploading: None,
+        }
+    }
+
+    /// Reads a cached asset, ignoring files that no longer match their hash.
+    pub fn get(&self, hash: &AssetHash) -> Option<Vec<u8>> {
+        let bytes = fs::read(self.dir.join(hash.to_string())).ok()?;
+        (AssetHash::of(&bytes) == *hash).then_some(bytes)
+    }
+
+    /// Returns the asset if it is cached, or starts downloading it. Wait for
+    /// [`AssetDownloaded`] in that case.
+    pub fn fetch(&mut self, info: &AssetInfo) -> Option<Vec<u8>> {
+        if let Some(bytes) = self.get(&info.hash) {
+            return Some(bytes);
+        }
+
+        if let Entry::Vacant(entry) = self.downloads.entry(info.hash) {
+            entry.insert(Download {
+                info: *info,
+                bytes: Vec::with_capacity(info.size),
+                next_chunk: 0,
+            });
+            self.wanted.push(info.hash);
+        }
+        None
+    }
+
+    /// How much of an asset has been downloaded, between 0 and 1.
+    pub fn progress(&self, hash: &AssetHash) -> f32 {
+        self.downloads.get(hash).map_or(1.0, |download| {
+            download.bytes.len() as f32 / download.info.size.max(1) as f32
+        })
+    }
+
+    fn store(&self, hash: &AssetHash, bytes: &[u8]) {
+        let stored = fs::create_dir_all(&self.dir)
+            .and_then(|()| fs::write(self.dir.join(hash.to_string()), bytes));
+        if let Err(error) = stored {
+            warn!("Could not cache asset {hash}: {error}");
+        }
+    }
+
+    /// Uploads an asset unless the host turns out to have it already, wait
+    /// for [`ServerMessage::AssetStored`] before using it.
+    pub fn upload(&mut self, bytes: &[u8]) -> Result<AssetHash, String> {
+        let info = AssetInfo::of(bytes)?;
+        self.store(&info.hash, bytes);
+
+        self.upload.clear();
+        self.upload.push_back(ClientMessage::BeginAssetUpload {
+            hash: info.hash,
+            size: info.size,
+        });
+        self.upload.extend(
+            bytes
+                .chunks(CHUNK_SIZE)
+                .map(|chunk| ClientMessage::AssetChunk(chunk.to_vec())),
+        );
+        self.uploading = Some(info.hash);
+        Ok(info.hash)
+    }
+
+    pub fn upload_remaining(&self) -> usize {
+        self.upload.len()
+    }
+}
+
+fn handle_asset_messages(
+    mut ev_host: EventReader<HostMessage>,
+    mut cache: ResMut<AssetCache>,
+    mut ev_downloaded: EventWriter<AssetDownloaded>,
+) {
+    for HostMessage(message) in ev_host.read() {
+        match message {
+            ServerMessage::AssetChunk {
+                asset,
+                index,
+                bytes,
+            } => {
+                let Some(download) = cache.downloads.get_mut(asset) else {
+                    continue;
+                };
+                if download.next_chunk != *index {
+                    continue;
+                }
+
+                download.bytes.extend_from_slice(bytes);
+                download.next_chunk += 1;
+                if download.next_chunk < download.info.chunks() {
+                    continue;
+                }
+
+                let download = cache.downloads.remove(asset).unwrap();
+                if AssetHash::of(&download.bytes) != *asset {
+                    error!("Asset {asset} arrived corrupted");
+                    continue;
+                }
+                cache.store(asset, &download.bytes);
+                ev_downloaded.send(AssetDownloaded {
+                    hash: *asset,
+                    bytes: download.bytes,
+                });
+            }
+            // The host may have had it all along, the rest need not be sent.
+            ServerMessage::AssetStored(info) if cache.uploading == Some(info.hash) => {
+                cache.upload.clear();
+                cache.uploading = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn request_assets(mut cache: ResMut<AssetCache>, mut ev_to_host: EventWriter<ToHost>) {
+    if !cache.wanted.is_empty() {
+        let wanted = std::mem::take(&mut cache.wanted);
+        ev_to_host.send(ToHost(ClientMessage::RequestAssets(wanted)));
+    }
+}
+
+fn send_upload(mut cache: ResMut<AssetCache>, mut ev_to_host: EventWriter<ToHost>) {
+    let count = cache.upload.len().min(UPLOAD_CHUNKS_PER_TICK);
+    for message in cache.upload.drain(..count) {
+        ev_to_host.send(ToHost(message));
+    }
+}
+
+/// The host forgets about transfers when a client drops out, so they are
+/// started over after reconnecting.
+fn drop_transfers(connection: Res<Connection>, mut cache: ResMut<AssetCache>) {
+    if !connection.is_connected() {
+        cache.downloads.clear();
+        cache.upload.clear();
+        cache.uploading = None;
+    }
+}
@@ -0,0 +1,67 @@
+use bevy::{
+    input::mouse::{MouseMotion, MouseWheel},
+    prelude::*,
+    window::PrimaryWindow,
+};
+use bevy_egui::EguiContexts;
+
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 4.0;
+const ZOOM_SPEED: f32 = 0.1;
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Cursor::default())
+            .add_systems(PreUpdate, track_cursor)
+            .add_systems(Update, pan_and_zoom);
+    }
+}
+
+/// Where the mouse is on the map, if it is over the map rather than the UI.
+#[derive(Debug, Default, Resource)]
+pub struct Cursor(pub Option<Vec2>);
+
+fn track_cursor(
+    mut contexts: EguiContexts,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut cursor: ResMut<Cursor>,
+) {
+    let over_ui = contexts.ctx_mut().wants_pointer_input();
+    let position = windows.get_single().ok().and_then(Window::cursor_position);
+
+    cursor.0 = position.filter(|_| !over_ui).and_then(|position| {
+        cameras
+            .iter()
+            .find_map(|(camera, transform)| camera.viewport_to_world_2d(transform, position))
+    });
+}
+
+/// Pans the map with the right or middle mouse button and zooms with the wheel.
+fn pan_and_zoom(
+    mut contexts: EguiContexts,
+    buttons: Res<Input<MouseButton>>,
+    mut ev_motion: EventReader<MouseMotion>,
+    mut ev_wheel: EventReader<MouseWheel>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection)>,
+) {
+    let motion: Vec2 = ev_motion.read().map(|motion| motion.delta).sum();
+    let scroll: f32 = ev_wheel.read().map(|wheel| wheel.y).sum();
+    if contexts.ctx_mut().wants_pointer_input() {
+        return;
+    }
+
+    let panning = buttons.any_pressed([MouseButton::Right, MouseButton::Middle]);
+    for (mut transform, mut projection) in &mut cameras {
+        if panning {
+            transform.translation.x -= motion.x * projection.scale;
+            transform.translation.y += motion.y * projection.scale;
+        }
+        if scroll != 0.0 {
+            projection.scale =
+                (projection.scale * (1.0 - scroll.signum() * ZOOM_SPEED)).clamp(MIN_ZOOM, MAX_ZOOM);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::{collections::VecDeque, fs, path::PathBuf};
+
+use bevy::prelude::*;
+use tyche_protocol::{archive::MAX_ARCHIVE_SIZE, map::CHUNK_SIZE, ClientMessage, ServerMessage};
+
+use crate::network::{Connection, HostMessage, ToHost};
+
+const UPLOAD_CHUNKS_PER_TICK: usize = 2;
+
+pub struct CampaignPlugin;
+
+impl Plugin for CampaignPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Campaign::default())
+            .add_systems(
+                Update,
+                (
+                    handle_campaign_messages,
+                    drop_transfers.run_if(resource_exists_and_changed::<Connection>()),
+                ),
+            )
+            .add_systems(FixedUpdate, send_import);
+    }
+}
+
+/// Campaign archives moving between this client and the host.
+#[derive(Debug, Default, Resource)]
+pub struct Campaign {
+    /// Where the archive being exported is written to once it has arrived.
+    export_path: Option<PathBuf>,
+    export_size: usize,
+    bytes: Vec<u8>,
+    upload: VecDeque<ClientMessage>,
+    pub status: Option<String>,
+    /// The room the last import was restored into.
+    pub imported: Option<String>,
+}
+
+impl Campaign {
+    pub fn export(&mut self, path: PathBuf) -> ClientMessage {
+        self.export_path = Some(path);
+        self.bytes.clear();
+        self.status = None;
+        ClientMessage::ExportRoom
+    }
+
+    /// How much of the export has been downloaded, between 0 and 1.
+    pub fn export_progress(&self) -> Option<f32> {
+        self.export_path.as_ref()?;
+        Some(self.bytes.len() as f32 / self.export_size.max(1) as f32)
+    }
+
+    pub fn import(&mut self, path: &str, room: String) -> Result<(), String> {
+        let archive = fs::read(path).map_err(|error| format!("Could not read {path}: {error}"))?;
+        if archive.len() > MAX_ARCHIVE_SIZE {
+            return Err(format!(
+                "Campaigns can be at most {} MB",
+                MAX_ARCHIVE_SIZE / 1024 / 1024
+            ));
+        }
+
+        self.upload.clear();
+        self.upload.push_back(ClientMessage::BeginImport {
+            room,
+            size: archive.len(),
+        });
+        self.upload.extend(
+            archive
+                .chunks(CHUNK_SIZE)
+                .map(|chunk| ClientMessage::ImportChunk(chunk.to_vec())),
+        );
+        self.status = None;
+        self.imported = None;
+        Ok(())
+    }
+
+    pub fn import_remaining(&self) -> usize {
+        self.upload.len()
+    }
+}
+
+fn handle_campaign_messages(mut ev_host: EventReader<HostMessage>, mut campaign: ResMut<Campaign>) {
+    for HostMessage(message) in ev_host.read() {
+        match message {
+            ServerMessage::BeginExport { size } => {
+                campaign.export_size = *size;
+                campaign.bytes = Vec::with_capacity(*size);
+            }
+            ServerMessage::ExportChunk(bytes) => {
+                if campaign.export_path.is_none() {
+                    continue;
+                }
+                campaign.bytes.extend_from_slice(bytes);
+                if campaign.bytes.len() < campaign.export_size {
+                    continue;
+                }
+
+                let path = campaign.export_path.take().unwrap();
+                let bytes = std::mem::take(&mut campaign.bytes);
+                campaign.status = Some(match fs::write(&path, bytes) {
+                    Ok(()) => format!("Exported to {}", path.display()),
+                    Err(error) => format!("Could not write {}: {error}", path.display()),
+                });
+            }
+            ServerMessage::Imported { room } => {
+                campaign.status = Some(format!("Imported as room {room}"));
+                campaign.imported = Some(room.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+fn send_import(mut campaign: ResMut<Campaign>, mut ev_to_host: EventWriter<ToHost>) {
+    let count = campaign.upload.len().min(UPLOAD_CHUNKS_PER_TICK);
+    for message in campaign.upload.drain(..count) {
+        ev_to_host.send(ToHost(message));
+    }
+}
+
+fn drop_transfers(connection: Res<Connection>, mut campaign: ResMut<Campaign>) {
+    let busy = campaign.export_path.is_some() || !campaign.upload.is_empty();
+    if busy && !connection.is_connected() {
+        campaign.export_path = None;
+        campaign.upload.clear();
+        campaign.status = Some("The connection dropped, try again".to_owned());
+    }
+}
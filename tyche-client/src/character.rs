@@ -0,0 +1,245 @@
+use bevy::prelude::*;
+use reqwest::{blocking::Response, header::CONTENT_TYPE};
+use serde::{de::DeserializeOwned, Deserialize};
+use tyche_protocol::{
+    asset::{AssetHash, AssetInfo},
+    character::{Character, HitPoints},
+    token::{HealthView, Token},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    asset_cache::AssetCache,
+    character_service,
+    network::{CurrentRoom, HostMessage, ToHost},
+    user::User,
+};
+
+pub struct CharacterPlugin;
+
+impl Plugin for CharacterPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TokenArt::default())
+            .add_systems(Update, (sync_sheet, share_token_art));
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Art {
+    Portrait,
+    Token,
+}
+
+/// Where characters built elsewhere can be imported from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImportFormat {
+    #[default]
+    Tyche,
+    FoundryDnd5e,
+}
+
+impl ImportFormat {
+    pub const ALL: [ImportFormat; 2] = [ImportFormat::Tyche, ImportFormat::FoundryDnd5e];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ImportFormat::Tyche => "Tyche JSON",
+            ImportFormat::FoundryDnd5e => "Foundry VTT (dnd5e)",
+        }
+    }
+
+    fn path(&self) -> &'static str {
+        match self {
+            ImportFormat::Tyche => "tyche",
+            ImportFormat::FoundryDnd5e => "foundry-dnd5e",
+        }
+    }
+}
+
+/// A character tyche-character created from an import, with what it could
+/// not map onto the character.
+#[derive(Debug, Deserialize)]
+pub struct Imported {
+    pub character: Character,
+    pub unmapped: Vec<Unmapped>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Unmapped {
+    pub field: String,
+    pub value: serde_json::Value,
+    pub reason: String,
+}
+
+/// Where the token art of the player's character is in getting to the host,
+/// which only puts art on tokens once it has stored it.
+#[derive(Debug, Default, Resource)]
+struct TokenArt {
+    shared: Option<AssetHash>,
+    uploading: Option<AssetHash>,
+}
+
+/// Stores a character sheet in tyche-character.
+pub fn save_character(token: &str, character: &Character) -> Result<(), reqwest::Error> {
+    reqwest::blocking::Client::new()
+        .put(format!("{}/{}", character_service!(), character.id))
+        .bearer_auth(token)
+        .json(character)
+        .send()?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Replaces the portrait or token art of a character, tyche-character shrinks
+/// it to a thumbnail.
+pub fn upload_art(
+    token: &str,
+    character: &Character,
+    art: Art,
+    image: Vec<u8>,
+) -> Result<Character, String> {
+    let kind = match art {
+        Art::Portrait => "portrait",
+        Art::Token => "token",
+    };
+    let response = reqwest::blocking::Client::new()
+        .put(format!("{}/{}/{kind}", character_service!(), character.id))
+        .bearer_auth(token)
+        .body(image)
+        .send()
+        .map_err(|error| error.to_string())?;
+    service_reply(response)
+}
+
+pub fn import_character(
+    token: &str,
+    format: ImportFormat,
+    json: Vec<u8>,
+) -> Result<Imported, String> {
+    let response = reqwest::blocking::Client::new()
+        .post(format!("{}/import/{}", character_service!(), format.path()))
+        .bearer_auth(token)
+        .header(CONTENT_TYPE, "application/json")
+        .body(json)
+        .send()
+        .map_err(|error| error.to_string())?;
+    service_reply(response)
+}
+
+pub fn fetch_art(token: &str, art: &AssetInfo) -> Result<Vec<u8>, String> {
+    let response = reqwest::blocking::Client::new()
+        .get(format!("{}/art/{}", character_service!(), art.hash))
+        .bearer_auth(token)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(|error| error.to_string())?;
+    let bytes = response.bytes().map_err(|error| error.to_string())?;
+    Ok(bytes.to_vec())
+}
+
+/// tyche-character explains why it refused a change in the body.
+pub fn service_reply<T: DeserializeOwned>(response: Response) -> Result<T, String> {
+    let status = response.status();
+    if status.is_success() {
+        return response.json().map_err(|error| error.to_string());
+    }
+
+    match response.text() {
+        Ok(reason) if !reason.is_empty() => Err(reason),
+        _ => Err(status.to_string()),
+    }
+}
+
+/// Uploads the token art of the player's character to the host whenever it
+/// joins a room or the art changes, and links it to the character's tokens
+/// once the host has it.
+fn share_token_art(
+    mut ev_host: EventReader<HostMessage>,
+    user: Res<User>,
+    room: Res<CurrentRoom>,
+    mut art: ResMut<TokenArt>,
+    mut cache: ResMut<AssetCache>,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    for HostMessage(message) in ev_host.read() {
+        match message {
+            ServerMessage::Joined { .. } => *art = TokenArt::default(),
+            ServerMessage::AssetStored(info) if art.uploading == Some(info.hash) => {
+                art.shared = art.uploading.take();
+                if let Some(character) = &user.character {
+                    let character = ClientMessage::UpdateCharacter(character.info());
+                    ev_to_host.send(ToHost(character));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let wanted = user.character.as_ref().and_then(|c| c.token_image);
+    if room.name.is_empty() || art.uploading.is_some() || wanted.map(|i| i.hash) == art.shared {
+        return;
+    }
+    let Some(wanted) = wanted else {
+        art.shared = None;
+        return;
+    };
+
+    let bytes = match cache.get(&wanted.hash) {
+        Some(bytes) => Ok(bytes),
+        None => fetch_art(&user.token, &wanted),
+    };
+    match bytes.and_then(|bytes| cache.upload(&bytes)) {
+        Ok(hash) => art.uploading = Some(hash),
+        Err(error) => {
+            warn!("Could not share the token art: {error}");
+            art.shared = Some(wanted.hash);
+        }
+    }
+}
+
+/// Damage and healing done to the token of the player's character end up on
+/// its sheet.
+fn sync_sheet(mut ev_host: EventReader<HostMessage>, mut user: ResMut<User>) {
+    for HostMessage(message) in ev_host.read() {
+        let tokens = match message {
+            ServerMessage::Joined { tokens, .. } => tokens.as_slice(),
+            ServerMessage::TokenSpawned(token) | ServerMessage::TokenChanged(token) => {
+                std::slice::from_ref(token)
+            }
+            _ => continue,
+        };
+
+        for token in tokens {
+            update_hit_points(&mut user, token);
+        }
+    }
+}
+
+fn update_hit_points(user: &mut User, token: &Token) {
+    let Some(character) = &mut user.character else {
+        return;
+    };
+    let HealthView::Full(health) = token.health else {
+        return;
+    };
+    if token.character != Some(character.id) || token.owner.as_ref() != Some(&user.user_id) {
+        return;
+    }
+
+    let hit_points = HitPoints {
+        current: health.current,
+        max: health.max,
+        temp: health.temp,
+    };
+    if character.hit_points == hit_points {
+        return;
+    }
+
+    character.hit_points = hit_points;
+    if let Err(error) = save_character(&user.token, character) {
+        warn!("Could not save {}: {error}", character.name);
+    }
+    for stored in user.characters.iter_mut().filter(|c| c.id == character.id) {
+        stored.hit_points = hit_points;
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use tyche_protocol::{
+    drawing::{Drawing, DrawingId, Shape, TEMPORARY_DURATION},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    camera::Cursor,
+    map::MapTool,
+    network::{CurrentRoom, HostMessage, ToHost},
+    user::User,
+};
+
+const DRAWING_Z: f32 = 6.0;
+/// How far apart, in world units, the points of a pen stroke are.
+const PEN_SPACING: f32 = 4.0;
+/// How close, in world units, a click has to be to pick a drawing.
+const PICK_DISTANCE: f32 = 10.0;
+
+pub struct DrawingPlugin;
+
+impl Plugin for DrawingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Drawings::default())
+            .insert_resource(DrawingSettings::default())
+            .add_systems(
+                Update,
+                (
+                    handle_drawing_messages,
+                    draw_on_map,
+                    erase_drawings,
+                    render_drawings,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawTool {
+    Pen,
+    Line,
+    Rectangle,
+    Circle,
+    Cone,
+    Text,
+}
+
+#[derive(Debug, Resource)]
+pub struct DrawingSettings {
+    pub color: [u8; 3],
+    pub temporary: bool,
+    pub text: String,
+}
+
+impl Default for DrawingSettings {
+    fn default() -> Self {
+        Self {
+            color: [230, 60, 60],
+            temporary: false,
+            text: String::new(),
+        }
+    }
+}
+
+struct MapDrawing {
+    drawing: Drawing,
+    /// When a temporary drawing disappears, in seconds since startup.
+    expires: Option<f32>,
+    label: Option<Entity>,
+}
+
+#[derive(Default, Resource)]
+struct Drawings(BTreeMap<DrawingId, MapDrawing>);
+
+fn color(drawing: &Drawing) -> Color {
+    let [r, g, b] = drawing.color;
+    Color::rgb_u8(r, g, b)
+}
+
+fn handle_drawing_messages(
+    mut ev_host: EventReader<HostMessage>,
+    mut drawings: ResMut<Drawings>,
+    time: Res<Time>,
+    mut commands: Commands,
+) {
+    for HostMessage(message) in ev_host.read() {
+        match message {
+            ServerMessage::Joined {
+                drawings: joined, ..
+            } => {
+                for (_, drawing) in std::mem::take(&mut drawings.0) {
+                    despawn_label(&mut commands, &drawing);
+                }
+                for drawing in joined {
+                    add_drawing(&mut drawings, &mut commands, drawing, &time);
+                }
+            }
+            ServerMessage::Drawn(drawing) => {
+                add_drawing(&mut drawings, &mut commands, drawing, &time);
+            }
+            ServerMessage::Erased(id) => {
+                if let Some(drawing) = drawings.0.remove(id) {
+                    despawn_label(&mut commands, &drawing);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn add_drawing(drawings: &mut Drawings, commands: &mut Commands, drawing: &Drawing, time: &Time) {
+    // Gizmos can't draw text, so labels get an entity of their own.
+    let label = match &drawing.shape {
+        Shape::Text { position, text } => {
+            let style = TextStyle {
+                font_size: 24.0,
+                color: color(drawing),
+                ..default()
+            };
+            let entity = commands.spawn(Text2dBundle {
+                text: Text::from_section(text, style),
+                transform: Transform::from_xyz(position[0], position[1], DRAWING_Z),
+                ..default()
+            });
+            Some(entity.id())
+        }
+        _ => None,
+    };
+
+    let expires = drawing
+        .temporary
+        .then(|| time.elapsed_seconds() + TEMPORARY_DURATION);
+    let drawing = MapDrawing {
+        drawing: drawing.clone(),
+        expires,
+        label,
+    };
+    drawings.0.insert(drawing.drawing.id, drawing);
+}
+
+fn despawn_label(commands: &mut Commands, drawing: &MapDrawing) {
+    if let Some(label) = drawing.label {
+        commands.entity(label).despawn_recursive();
+    }
+}
+
+/// The two far corners of a cone that is as wide as it is long.
+fn cone_corners(origin: Vec2, target: Vec2) -> [Vec2; 2] {
+    let side = (target - origin).perp() / 2.0;
+    [target + side, target - side]
+}
+
+fn draw_shape(gizmos: &mut Gizmos, shape: &Shape, color: Color) {
+    match shape {
+        Shape::Pen(points) => {
+            gizmos.linestrip_2d(points.iter().map(|point| Vec2::from(*point)), color);
+        }
+        Shape::Line { start, end } => gizmos.line_2d((*start).into(), (*end).into(), color),
+        Shape::Rectangle { start, end } => {
+            let (start, end) = (Vec2::from(*start), Vec2::from(*end));
+            gizmos.rect_2d((start + end) / 2.0, 0.0, (end - start).abs(), color);
+        }
+        Shape::Circle { center, radius } => {
+            gizmos.circle_2d((*center).into(), *radius, color);
+        }
+        Shape::Cone { origin, target } => {
+            let origin = Vec2::from(*origin);
+            let [left, right] = cone_corners(origin, (*target).into());
+            gizmos.linestrip_2d([origin, left, right, origin], color);
+        }
+        Shape::Text { .. } => {}
+    }
+}
+
+fn distance_to_segment(point: Vec2, start: Vec2, end: Vec2) -> f32 {
+    let edge = end - start;
+    let t = match edge.length_squared() > 0.0 {
+        true => ((point - start).dot(edge) / edge.length_squared()).clamp(0.0, 1.0),
+        false => 0.0,
+    };
+    point.distance(start + edge * t)
+}
+
+/// How far a point is from the outline of a shape.
+fn distance_to_shape(point: Vec2, shape: &Shape) -> f32 {
+    let polyline = |points: &[Vec2]| {
+        points
+            .windows(2)
+            .map(|pair| distance_to_segment(point, pair[0], pair[1]))
+            .fold(f32::INFINITY, f32::min)
+    };
+
+    match shape {
+        Shape::Pen(points) => match points.as_slice() {
+            [only] => point.distance((*only).into()),
+            points => polyline(&points.iter().map(|p| Vec2::from(*p)).collect::<Vec<_>>()),
+        },
+        Shape::Line { start, end } => distance_to_segment(point, (*start).into(), (*end).into()),
+        Shape::Rectangle { start, end } => {
+            let (start, end) = (Vec2::from(*start), Vec2::from(*end));
+            let corners = [
+                start,
+                Vec2::new(end.x, start.y),
+                end,
+                Vec2::new(start.x, end.y),
+                start,
+            ];
+            polyline(&corners)
+        }
+        Shape::Circle { center, radius } => (point.distance((*center).into()) - radius).abs(),
+        Shape::Cone { origin, target } => {
+            let origin = Vec2::from(*origin);
+            let [left, right] = cone_corners(origin, (*target).into());
+            polyline(&[origin, left, right, origin])
+        }
+        Shape::Text { position, .. } => point.distance((*position).into()) / 2.0,
+    }
+}
+
+/// The shape being drawn between pressing and releasing the mouse.
+fn drag_shape(tool: DrawTool, points: &[Vec2], cursor: Vec2, settings: &DrawingSettings) -> Shape {
+    let start = points.first().copied().unwrap_or(cursor);
+    match tool {
+        DrawTool::Pen => Shape::Pen(points.iter().map(|point| (*point).into()).collect()),
+        DrawTool::Line => Shape::Line {
+            start: start.into(),
+            end: cursor.into(),
+        },
+        DrawTool::Rectangle => Shape::Rectangle {
+            start: start.into(),
+            end: cursor.into(),
+        },
+        DrawTool::Circle => Shape::Circle {
+            center: start.into(),
+            radius: start.distance(cursor),
+        },
+        DrawTool::Cone => Shape::Cone {
+            origin: start.into(),
+            target: cursor.into(),
+        },
+        DrawTool::Text => Shape::Text {
+            position: cursor.into(),
+            text: settings.text.clone(),
+        },
+    }
+}
+
+fn draw_on_map(
+    buttons: Res<Input<MouseButton>>,
+    cursor: Res<Cursor>,
+    tool: Res<MapTool>,
+    settings: Res<DrawingSettings>,
+    mut points: Local<Vec<Vec2>>,
+    mut gizmos: Gizmos,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    let MapTool::Draw(tool) = *tool else {
+        points.clear();
+        return;
+    };
+    let Some(cursor) = cursor.0 else {
+        return;
+    };
+
+    if buttons.just_pressed(MouseButton::Left) {
+        *points = vec![cursor];
+    }
+    if points.is_empty() {
+        return;
+    }
+    let far_enough = points
+        .last()
+        .is_some_and(|last| last.distance(cursor) >= PEN_SPACING);
+    if tool == DrawTool::Pen && far_enough {
+        points.push(cursor);
+    }
+
+    let [r, g, b] = settings.color;
+    let shape = drag_shape(tool, &points, cursor, &settings);
+    draw_shape(&mut gizmos, &shape, Color::rgb_u8(r, g, b));
+    if buttons.pressed(MouseButton::Left) {
+        return;
+    }
+
+    if shape.is_valid() {
+        ev_to_host.send(ToHost(ClientMessage::Draw {
+            shape,
+            color: settings.color,
+            temporary: settings.temporary,
+        }));
+    }
+    points.clear();
+}
+
+/// Erases the drawing under the cursor, if it is yours or you are the GM.
+fn erase_drawings(
+    buttons: Res<Input<MouseButton>>,
+    cursor: Res<Cursor>,
+    tool: Res<MapTool>,
+    drawings: Res<Drawings>,
+    room: Res<CurrentRoom>,
+    user: Res<User>,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    let Some(cursor) = cursor.0 else {
+        return;
+    };
+    if *tool != MapTool::EraseDrawing || !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let erasable = drawings.0.values().filter(|drawing| {
+        !drawing.drawing.temporary && (room.is_gm || drawing.drawing.owner == user.user_id)
+    });
+    let closest = erasable
+        .map(|drawing| {
+            let distance = distance_to_shape(cursor, &drawing.drawing.shape);
+            (drawing.drawing.id, distance)
+        })
+        .filter(|(_, distance)| *distance < PICK_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    if let Some((id, _)) = closest {
+        ev_to_host.send(ToHost(ClientMessage::Erase(id)));
+    }
+}
+
+/// Draws every drawing, fading temporary ones out and dropping them once
+/// they expire.
+fn render_drawings(
+    mut drawings: ResMut<Drawings>,
+    time: Res<Time>,
+    mut labels: Query<&mut Text>,
+    mut gizmos: Gizmos,
+    mut commands: Commands,
+) {
+    let now = time.elapsed_seconds();
+    drawings.0.retain(|_, drawing| {
+        let expired = drawing.expires.is_some_and(|expires| expires <= now);
+        if expired {
+            despawn_label(&mut commands, drawing);
+        }
+        !expired
+    });
+
+    for drawing in drawings.0.values() {
+        let alpha = drawing.expires.map_or(1.0, |expires| {
+            ((expires - now) / TEMPORARY_DURATION).clamp(0.0, 1.0)
+        });
+        let color = color(&drawing.drawing).with_a(alpha);
+
+        draw_shape(&mut gizmos, &drawing.drawing.shape, color);
+        if let Some(mut text) = drawing.label.and_then(|label| labels.get_mut(label).ok()) {
+            for section in &mut text.sections {
+                section.style.color = color;
+            }
+        }
+    }
+}
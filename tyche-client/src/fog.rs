@@ -0,0 +1,231 @@
+use bevy::{
+    input::mouse::MouseButton,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use tyche_protocol::{
+    fog::{FogMask, FogShape, FogStroke, FOG_SIZE, FOG_TEXEL},
+    map::{MapId, CELL_SIZE},
+    vision::Point,
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    camera::Cursor,
+    map::MapTool,
+    network::{CurrentRoom, HostMessage, ToHost},
+};
+
+const FOG_COLOR: [u8; 3] = [20, 20, 28];
+/// The GM sees through the fog, but still needs to know where it is.
+const GM_FOG_ALPHA: u8 = 110;
+const FOG_Z: f32 = 4.0;
+const STROKE_COLOR: Color = Color::rgb(0.9, 0.9, 0.2);
+
+pub struct FogPlugin;
+
+impl Plugin for FogPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(FogLayer::default())
+            .add_systems(Startup, spawn_fog_layer)
+            .add_systems(
+                Update,
+                (handle_fog_messages, paint_fog, render_fog_layer).chain(),
+            );
+    }
+}
+
+/// The fog the GM painted over the current map.
+#[derive(Resource)]
+pub struct FogLayer {
+    map: Option<MapId>,
+    mask: FogMask,
+    /// Radius of the freehand brush, in world units.
+    pub brush_radius: f32,
+}
+
+impl Default for FogLayer {
+    fn default() -> Self {
+        Self {
+            map: None,
+            mask: FogMask::default(),
+            brush_radius: CELL_SIZE,
+        }
+    }
+}
+
+#[derive(Component)]
+struct FogSprite;
+
+fn handle_fog_messages(mut ev_host: EventReader<HostMessage>, mut fog: ResMut<FogLayer>) {
+    for HostMessage(message) in ev_host.read() {
+        match message {
+            ServerMessage::Joined { .. } | ServerMessage::MapChanged(_) => {
+                fog.map = None;
+                fog.mask = FogMask::default();
+            }
+            ServerMessage::FogMask { map, mask } => match FogMask::decompress(mask) {
+                Some(mask) => {
+                    fog.map = Some(*map);
+                    fog.mask = mask;
+                }
+                None => warn!("Received a malformed fog mask"),
+            },
+            ServerMessage::FogPainted { map, stroke } if fog.map == Some(*map) => {
+                fog.mask.apply(stroke);
+            }
+            _ => {}
+        }
+    }
+}
+
+enum FogTool {
+    Rectangle,
+    Polygon,
+    Freehand,
+}
+
+fn paint_fog(
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    cursor: Res<Cursor>,
+    tool: Res<MapTool>,
+    fog: Res<FogLayer>,
+    mut points: Local<Vec<Vec2>>,
+    mut gizmos: Gizmos,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    let (brush, kind) = match *tool {
+        MapTool::FogRectangle(brush) => (brush, FogTool::Rectangle),
+        MapTool::FogPolygon(brush) => (brush, FogTool::Polygon),
+        MapTool::FogFreehand(brush) => (brush, FogTool::Freehand),
+        _ => {
+            points.clear();
+            return;
+        }
+    };
+    let Some(cursor) = cursor.0 else {
+        return;
+    };
+    let points = &mut *points;
+    if tool.is_changed() {
+        points.clear();
+    }
+    let to_points = |points: &[Vec2]| -> Vec<Point> { points.iter().map(|&p| p.into()).collect() };
+
+    let shape = match kind {
+        FogTool::Rectangle => {
+            if buttons.just_pressed(MouseButton::Left) {
+                *points = vec![cursor];
+            }
+            let Some(&start) = points.first() else {
+                return;
+            };
+
+            gizmos.rect_2d(
+                (start + cursor) / 2.0,
+                0.0,
+                (cursor - start).abs(),
+                STROKE_COLOR,
+            );
+            if buttons.pressed(MouseButton::Left) {
+                return;
+            }
+            FogShape::Rectangle {
+                start: start.into(),
+                end: cursor.into(),
+            }
+        }
+        // Click to add corners, click the first corner again to finish.
+        FogTool::Polygon => {
+            if keys.just_pressed(KeyCode::Back) {
+                points.pop();
+            }
+            let closes = points
+                .first()
+                .is_some_and(|first| points.len() > 2 && first.distance(cursor) < FOG_TEXEL);
+
+            gizmos.linestrip_2d(points.iter().copied().chain([cursor]), STROKE_COLOR);
+            if !buttons.just_pressed(MouseButton::Left) {
+                return;
+            }
+            if !closes {
+                points.push(cursor);
+                return;
+            }
+            FogShape::Polygon(to_points(points))
+        }
+        FogTool::Freehand => {
+            let too_close = points
+                .last()
+                .is_some_and(|last| last.distance(cursor) <= fog.brush_radius / 2.0);
+            if buttons.pressed(MouseButton::Left) && !too_close {
+                points.push(cursor);
+            }
+
+            gizmos.circle_2d(cursor, fog.brush_radius, STROKE_COLOR);
+            gizmos.linestrip_2d(points.iter().copied(), STROKE_COLOR);
+            if buttons.pressed(MouseButton::Left) || points.is_empty() {
+                return;
+            }
+            FogShape::Freehand {
+                points: to_points(points),
+                radius: fog.brush_radius,
+            }
+        }
+    };
+
+    ev_to_host.send(ToHost(ClientMessage::PaintFog(FogStroke { brush, shape })));
+    points.clear();
+}
+
+fn spawn_fog_layer(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let size = Extent3d {
+        width: FOG_SIZE as u32,
+        height: FOG_SIZE as u32,
+        depth_or_array_layers: 1,
+    };
+    let image = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0; 4],
+        TextureFormat::Rgba8UnormSrgb,
+    );
+
+    commands.spawn((
+        Name::new("Fog layer"),
+        FogSprite,
+        SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(FOG_SIZE as f32 * FOG_TEXEL)),
+                ..default()
+            },
+            texture: images.add(image),
+            transform: Transform::from_xyz(0.0, 0.0, FOG_Z),
+            ..default()
+        },
+    ));
+}
+
+fn render_fog_layer(
+    fog: Res<FogLayer>,
+    room: Res<CurrentRoom>,
+    sprites: Query<&Handle<Image>, With<FogSprite>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !fog.is_changed() && !room.is_changed() {
+        return;
+    }
+    let Some(image) = sprites.get_single().ok().and_then(|h| images.get_mut(h)) else {
+        return;
+    };
+
+    let alpha = if room.is_gm { GM_FOG_ALPHA } else { u8::MAX };
+    for (i, texel) in image.data.chunks_exact_mut(4).enumerate() {
+        // Images start at the top, the mask at the bottom.
+        let (x, y) = (i % FOG_SIZE, FOG_SIZE - 1 - i / FOG_SIZE);
+        let [r, g, b] = FOG_COLOR;
+        let a = if fog.mask.texel(x, y) { alpha } else { 0 };
+        texel.copy_from_slice(&[r, g, b, a]);
+    }
+}
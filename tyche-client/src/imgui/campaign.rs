@@ -0,0 +1,93 @@
+use bevy::ecs::{
+    event::EventWriter,
+    system::{Res, ResMut, Resource},
+};
+use bevy_egui::{
+    egui::{ProgressBar, Window},
+    EguiContexts,
+};
+use tyche_protocol::{archive::EXTENSION, ClientMessage};
+
+use crate::{
+    campaign::Campaign,
+    network::{CurrentRoom, ToHost},
+    user::User,
+};
+
+#[derive(Default, Resource)]
+pub struct CampaignWindow {
+    export_path: String,
+    import_path: String,
+    room: String,
+    error: Option<String>,
+}
+
+pub fn campaign_ui(
+    mut contexts: EguiContexts,
+    mut window: ResMut<CampaignWindow>,
+    mut campaign: ResMut<Campaign>,
+    room: Res<CurrentRoom>,
+    user: Res<User>,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    if !room.is_gm {
+        return;
+    }
+    let window = &mut *window;
+    if window.export_path.is_empty() {
+        window.export_path = format!("{}.{EXTENSION}", room.name);
+    }
+
+    Window::new("Campaign").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Export to: ");
+            ui.text_edit_singleline(&mut window.export_path);
+            let path = window.export_path.trim();
+            if campaign.export_progress().is_none() && ui.button("Export").clicked() {
+                ev_to_host.send(ToHost(campaign.export(path.into())));
+            }
+        });
+        if let Some(progress) = campaign.export_progress() {
+            ui.add(ProgressBar::new(progress).text("Exporting"));
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Import from: ");
+            ui.text_edit_singleline(&mut window.import_path);
+        });
+        ui.horizontal(|ui| {
+            ui.label("As room: ");
+            ui.text_edit_singleline(&mut window.room);
+            let name = window.room.trim();
+            let idle = campaign.import_remaining() == 0;
+            if idle && !name.is_empty() && ui.button("Import").clicked() {
+                window.error = campaign
+                    .import(window.import_path.trim(), name.to_owned())
+                    .err();
+            }
+        });
+        if campaign.import_remaining() > 0 {
+            ui.label(format!(
+                "Uploading, {} chunks left",
+                campaign.import_remaining()
+            ));
+        }
+
+        if let Some(error) = &window.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        if let Some(status) = &campaign.status {
+            ui.label(status);
+        }
+        if let Some(imported) = &campaign.imported {
+            if ui.button(format!("Join {imported}")).clicked() {
+                ev_to_host.send(ToHost(ClientMessage::JoinRoom {
+                    room: imported.clone(),
+                    token: user.token.clone(),
+                    character: user.character.as_ref().map(|character| character.info()),
+                }));
+            }
+        }
+    });
+}
@@ -0,0 +1,379 @@
+use std::fs;
+
+use bevy::{
+    asset::Assets,
+    ecs::{
+        event::{EventReader, EventWriter},
+        system::{Res, ResMut, Resource},
+    },
+    log::warn,
+    render::texture::{CompressedImageFormats, Image, ImageSampler, ImageType},
+};
+use bevy_egui::{
+    egui::{DragValue, Grid, ScrollArea, TextureId, Vec2, Window},
+    EguiContexts,
+};
+use tyche_protocol::{
+    asset::AssetHash,
+    character::{Ability, Attack, Character, HitPoints, Skill},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    character::{fetch_art, save_character, upload_art, Art},
+    network::{HostMessage, ToHost},
+    user::User,
+};
+
+const ROLL_LOG_SIZE: usize = 100;
+const PORTRAIT_SIZE: f32 = 128.0;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+enum RollMode {
+    #[default]
+    Normal,
+    Advantage,
+    Disadvantage,
+}
+
+impl RollMode {
+    fn d20(&self) -> &'static str {
+        match self {
+            RollMode::Normal => "1d20",
+            RollMode::Advantage => "2d20kh1",
+            RollMode::Disadvantage => "2d20kl1",
+        }
+    }
+}
+
+#[derive(Default, Resource)]
+pub struct CharacterSheetWindow {
+    mode: RollMode,
+    secret: bool,
+    /// Hit points edited on the sheet but not saved yet.
+    hit_points: Option<HitPoints>,
+    /// The portrait shown, `None` if it could not be loaded.
+    portrait: Option<(AssetHash, Option<TextureId>)>,
+    art_path: String,
+    /// What happened to the last upload.
+    art_status: String,
+}
+
+#[derive(Default, Resource)]
+pub struct RollLog {
+    entries: Vec<String>,
+}
+
+/// Builds a dice expression such as `1d20 + 3 + 2`, leaving out modifiers of zero.
+fn expression(dice: &str, modifiers: &[i32]) -> String {
+    let mut expression = dice.to_owned();
+
+    for modifier in modifiers {
+        match modifier.signum() {
+            1 => expression += &format!(" + {modifier}"),
+            -1 => expression += &format!(" - {}", -modifier),
+            _ => {}
+        }
+    }
+
+    expression
+}
+
+fn ability_check(character: &Character, ability: Ability, mode: RollMode) -> String {
+    expression(mode.d20(), &[character.abilities.modifier(ability)])
+}
+
+fn saving_throw(character: &Character, ability: Ability, mode: RollMode) -> String {
+    expression(mode.d20(), &[character.save_bonus(ability)])
+}
+
+fn skill_check(character: &Character, skill: Skill, mode: RollMode) -> String {
+    let proficiency = match character.is_proficient(skill) {
+        true => character.proficiency_bonus,
+        false => 0,
+    };
+
+    expression(
+        mode.d20(),
+        &[character.abilities.modifier(skill.ability()), proficiency],
+    )
+}
+
+fn attack_roll(character: &Character, attack: &Attack, mode: RollMode) -> String {
+    let proficiency = match attack.proficient {
+        true => character.proficiency_bonus,
+        false => 0,
+    };
+
+    expression(
+        mode.d20(),
+        &[character.abilities.modifier(attack.ability), proficiency],
+    )
+}
+
+fn damage_roll(character: &Character, attack: &Attack) -> String {
+    expression(
+        &attack.damage,
+        &[character.abilities.modifier(attack.ability)],
+    )
+}
+
+fn signed(modifier: i32) -> String {
+    format!("{modifier:+}")
+}
+
+/// Loads the portrait of a character the first time it is shown.
+fn portrait(
+    token: &str,
+    character: &Character,
+    ui_state: &mut CharacterSheetWindow,
+    images: &mut Assets<Image>,
+    contexts: &mut EguiContexts,
+) -> Option<TextureId> {
+    let info = character.portrait?;
+    if let Some((hash, texture)) = ui_state.portrait {
+        if hash == info.hash {
+            return texture;
+        }
+    }
+
+    let image = fetch_art(token, &info).and_then(|bytes| {
+        Image::from_buffer(
+            &bytes,
+            ImageType::Extension(info.format.extension()),
+            CompressedImageFormats::NONE,
+            true,
+            ImageSampler::Default,
+        )
+        .map_err(|error| error.to_string())
+    });
+    let texture = match image {
+        Ok(image) => Some(contexts.add_image(images.add(image))),
+        Err(error) => {
+            warn!("Could not load the portrait of {}: {error}", character.name);
+            None
+        }
+    };
+    ui_state.portrait = Some((info.hash, texture));
+    texture
+}
+
+pub fn character_sheet_ui(
+    mut user: ResMut<User>,
+    mut contexts: EguiContexts,
+    mut images: ResMut<Assets<Image>>,
+    mut ui_state: ResMut<CharacterSheetWindow>,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    let Some(character) = &user.character else {
+        return;
+    };
+    let ui_state = &mut *ui_state;
+    let portrait = portrait(&user.token, character, ui_state, &mut images, &mut contexts);
+    let mode = ui_state.mode;
+    let mut roll = None;
+    let mut save = false;
+    let mut upload = None;
+
+    Window::new(&character.name).show(contexts.ctx_mut(), |ui| {
+        if let Some(portrait) = portrait {
+            ui.image((portrait, Vec2::splat(PORTRAIT_SIZE)));
+        }
+        ui.horizontal(|ui| {
+            ui.label("Art: ");
+            ui.text_edit_singleline(&mut ui_state.art_path);
+            if ui.button("Upload portrait").clicked() {
+                upload = Some(Art::Portrait);
+            }
+            if ui.button("Upload token art").clicked() {
+                upload = Some(Art::Token);
+            }
+        });
+        ui.small("PNG, JPEG or WebP, up to 4 MiB");
+        if !ui_state.art_status.is_empty() {
+            ui.label(&ui_state.art_status);
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut ui_state.mode, RollMode::Normal, "Normal");
+            ui.selectable_value(&mut ui_state.mode, RollMode::Advantage, "Advantage");
+            ui.selectable_value(&mut ui_state.mode, RollMode::Disadvantage, "Disadvantage");
+            ui.checkbox(&mut ui_state.secret, "Secret");
+        });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            let mut hit_points = ui_state.hit_points.unwrap_or(character.hit_points);
+            ui.label("HP: ");
+            let edits = [
+                ui.add(DragValue::new(&mut hit_points.current).clamp_range(0..=hit_points.max)),
+                ui.label("/"),
+                ui.add(DragValue::new(&mut hit_points.max).clamp_range(1..=9999)),
+                ui.label("Temp: "),
+                ui.add(DragValue::new(&mut hit_points.temp).clamp_range(0..=9999)),
+            ];
+            if edits.iter().any(|edit| edit.changed()) {
+                hit_points.current = hit_points.current.min(hit_points.max);
+                ui_state.hit_points = Some(hit_points);
+            }
+            if ui_state.hit_points.is_some() && ui.button("Save").clicked() {
+                save = true;
+            }
+        });
+
+        ui.separator();
+        Grid::new("abilities").show(ui, |ui| {
+            for (i, ability) in Ability::ALL.into_iter().enumerate() {
+                let modifier = character.abilities.modifier(ability);
+                let label = format!(
+                    "{} {} ({})",
+                    ability.short_name(),
+                    character.abilities.score(ability),
+                    signed(modifier)
+                );
+                if ui.button(label).clicked() {
+                    roll = Some((
+                        ability_check(character, ability, mode),
+                        format!("{} check", ability.short_name()),
+                    ));
+                }
+                if i % 3 == 2 {
+                    ui.end_row();
+                }
+            }
+        });
+
+        ui.separator();
+        ui.label("Saving throws");
+        Grid::new("saving throws").show(ui, |ui| {
+            for (i, ability) in Ability::ALL.into_iter().enumerate() {
+                let proficient = match character.saving_throws.contains(&ability) {
+                    true => "●",
+                    false => "○",
+                };
+                let modifier = signed(character.save_bonus(ability));
+                let label = format!("{proficient} {} {modifier}", ability.short_name());
+                if ui.button(label).clicked() {
+                    roll = Some((
+                        saving_throw(character, ability, mode),
+                        format!("{} save", ability.short_name()),
+                    ));
+                }
+                if i % 3 == 2 {
+                    ui.end_row();
+                }
+            }
+        });
+
+        ui.separator();
+        ScrollArea::vertical()
+            .id_source("skills")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for skill in Skill::ALL {
+                    let proficient = match character.is_proficient(skill) {
+                        true => "●",
+                        false => "○",
+                    };
+                    let modifier = character.abilities.modifier(skill.ability())
+                        + match character.is_proficient(skill) {
+                            true => character.proficiency_bonus,
+                            false => 0,
+                        };
+                    let label = format!("{proficient} {} {}", skill.name(), signed(modifier));
+                    if ui.button(label).clicked() {
+                        roll = Some((
+                            skill_check(character, skill, mode),
+                            format!("{} check", skill.name()),
+                        ));
+                    }
+                }
+            });
+
+        if !character.attacks.is_empty() {
+            ui.separator();
+            Grid::new("attacks").show(ui, |ui| {
+                for attack in &character.attacks {
+                    ui.label(&attack.name);
+                    if ui.button("To hit").clicked() {
+                        roll = Some((
+                            attack_roll(character, attack, mode),
+                            format!("{} attack", attack.name),
+                        ));
+                    }
+                    if ui.button("Damage").clicked() {
+                        roll = Some((
+                            damage_roll(character, attack),
+                            format!("{} damage", attack.name),
+                        ));
+                    }
+                    ui.end_row();
+                }
+            });
+        }
+    });
+
+    let user = &mut *user;
+    if let (Some(art), Some(character)) = (upload, &mut user.character) {
+        let uploaded = fs::read(ui_state.art_path.trim())
+            .map_err(|error| format!("Cannot read {}: {error}", ui_state.art_path))
+            .and_then(|image| upload_art(&user.token, character, art, image));
+        ui_state.art_status = match uploaded {
+            Ok(uploaded) => {
+                character.portrait = uploaded.portrait;
+                character.token_image = uploaded.token_image;
+                for stored in user.characters.iter_mut().filter(|c| c.id == character.id) {
+                    stored.portrait = uploaded.portrait;
+                    stored.token_image = uploaded.token_image;
+                }
+                "Art uploaded".to_owned()
+            }
+            Err(error) => error,
+        };
+    }
+
+    if let (true, Some(character)) = (save, &mut user.character) {
+        if let Some(hit_points) = ui_state.hit_points.take() {
+            character.hit_points = hit_points;
+        }
+        if let Err(error) = save_character(&user.token, character) {
+            warn!("Could not save {}: {error}", character.name);
+        }
+        ev_to_host.send(ToHost(ClientMessage::UpdateCharacter(character.info())));
+    }
+
+    if let Some((expression, label)) = roll {
+        ev_to_host.send(ToHost(ClientMessage::Roll {
+            expression,
+            label: Some(label),
+            secret: ui_state.secret,
+        }));
+    }
+}
+
+pub fn update_roll_log(mut ev_host: EventReader<HostMessage>, mut log: ResMut<RollLog>) {
+    for HostMessage(message) in ev_host.read() {
+        match message {
+            ServerMessage::Joined { rolls, .. } => {
+                log.entries = rolls.iter().map(ToString::to_string).collect();
+            }
+            ServerMessage::Rolled(roll) => log.entries.push(roll.to_string()),
+            ServerMessage::Error(error) => log.entries.push(error.clone()),
+            _ => {}
+        }
+    }
+
+    let overflow = log.entries.len().saturating_sub(ROLL_LOG_SIZE);
+    log.entries.drain(..overflow);
+}
+
+pub fn roll_log_ui(mut contexts: EguiContexts, log: Res<RollLog>) {
+    Window::new("Rolls").show(contexts.ctx_mut(), |ui| {
+        ScrollArea::vertical().stick_to_bottom(true).show(ui, |ui| {
+            for entry in &log.entries {
+                ui.label(entry);
+            }
+        });
+    });
+}
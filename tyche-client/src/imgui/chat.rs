@@ -0,0 +1,124 @@
+use bevy::ecs::{
+    event::{EventReader, EventWriter},
+    system::{Res, ResMut, Resource},
+};
+use bevy_egui::{
+    egui::{Color32, Key, ScrollArea, Window},
+    EguiContexts,
+};
+use tyche_protocol::{ClientMessage, ServerMessage};
+
+use crate::{
+    network::{CurrentRoom, HostMessage, ToHost},
+    profile::Players,
+};
+
+const CHAT_HISTORY_SIZE: usize = 500;
+
+struct ChatEntry {
+    timestamp: u64,
+    text: String,
+    /// The color of the sender, if they were in the room.
+    color: Option<[u8; 3]>,
+}
+
+#[derive(Default, Resource)]
+pub struct ChatWindow {
+    input: String,
+    entries: Vec<ChatEntry>,
+}
+
+/// Formats a unix timestamp as `HH:MM` (UTC).
+fn clock(timestamp: u64) -> String {
+    format!("{:02}:{:02}", timestamp / 3600 % 24, timestamp / 60 % 60)
+}
+
+pub fn update_chat(
+    mut ev_host: EventReader<HostMessage>,
+    players: Res<Players>,
+    mut chat: ResMut<ChatWindow>,
+) {
+    let color_of = |client_id| players.players.get(&client_id).map(|p| p.color);
+
+    for HostMessage(message) in ev_host.read() {
+        match message {
+            ServerMessage::Joined {
+                chat: history,
+                rolls,
+                players,
+                ..
+            } => {
+                let color_of = |client_id| {
+                    let player = players.iter().find(|p| p.client_id == client_id);
+                    player.map(|player| player.color)
+                };
+                let messages = history.iter().map(|message| ChatEntry {
+                    timestamp: message.timestamp,
+                    text: message.to_string(),
+                    color: color_of(message.client_id),
+                });
+                let rolls = rolls.iter().map(|roll| ChatEntry {
+                    timestamp: roll.timestamp,
+                    text: roll.to_string(),
+                    color: color_of(roll.client_id),
+                });
+
+                chat.entries = messages.chain(rolls).collect();
+                chat.entries.sort_by_key(|entry| entry.timestamp);
+            }
+            ServerMessage::Chat(message) => chat.entries.push(ChatEntry {
+                timestamp: message.timestamp,
+                text: message.to_string(),
+                color: color_of(message.client_id),
+            }),
+            ServerMessage::Rolled(roll) => chat.entries.push(ChatEntry {
+                timestamp: roll.timestamp,
+                text: roll.to_string(),
+                color: color_of(roll.client_id),
+            }),
+            _ => {}
+        }
+    }
+
+    let overflow = chat.entries.len().saturating_sub(CHAT_HISTORY_SIZE);
+    chat.entries.drain(..overflow);
+}
+
+pub fn chat_ui(
+    mut contexts: EguiContexts,
+    room: Res<CurrentRoom>,
+    mut chat: ResMut<ChatWindow>,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    let chat = &mut *chat;
+
+    Window::new(format!("Chat – {}", room.name))
+        .id("chat".into())
+        .show(contexts.ctx_mut(), |ui| {
+            ScrollArea::vertical()
+                .max_height(300.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for entry in &chat.entries {
+                        ui.horizontal_wrapped(|ui| {
+                            ui.label(format!("[{}]", clock(entry.timestamp)));
+                            if let Some([r, g, b]) = entry.color {
+                                ui.colored_label(Color32::from_rgb(r, g, b), "●");
+                            }
+                            ui.label(&entry.text);
+                        });
+                    }
+                });
+
+            ui.separator();
+            let response = ui.text_edit_singleline(&mut chat.input);
+            if response.lost_focus() && ui.input(|input| input.key_pressed(Key::Enter)) {
+                if !chat.input.trim().is_empty() {
+                    ev_to_host.send(ToHost(ClientMessage::Chat(chat.input.clone())));
+                }
+                chat.input.clear();
+                response.request_focus();
+            }
+            ui.small("/me, /w <name>, /gm, /roll <dice>, /gmroll <dice>");
+        });
+}
@@ -1,20 +1,45 @@
-use bevy::{
-    core::Name,
-    ecs::{
-        event::EventWriter,
-        schedule::NextState,
-        system::{Res, ResMut},
-    },
+use std::fs;
+
+use bevy::ecs::{
+    event::EventWriter,
+    schedule::NextState,
+    system::{ResMut, Resource},
+};
+use bevy_egui::{
+    egui::{ComboBox, ScrollArea, Window},
+    EguiContexts,
 };
-use bevy_egui::{egui::Window, EguiContexts};
 use reqwest::StatusCode;
+use tyche_protocol::ClientMessage;
 
-use crate::{character_service, user::User, SpawnToken, Token};
+use crate::{
+    character::{import_character, ImportFormat},
+    character_service,
+    network::ToHost,
+    user::User,
+};
 
 use super::GameMenus;
 
-#[derive(Default)]
-pub struct ChooseCharacterWindow {}
+#[derive(Resource)]
+pub struct ChooseCharacterWindow {
+    room: String,
+    import_path: String,
+    import_format: ImportFormat,
+    /// How the last import went, followed by what it left out.
+    import_report: Vec<String>,
+}
+
+impl Default for ChooseCharacterWindow {
+    fn default() -> Self {
+        Self {
+            room: "tyche".to_owned(),
+            import_path: String::new(),
+            import_format: ImportFormat::default(),
+            import_report: Vec::new(),
+        }
+    }
+}
 
 pub fn load_characters(mut user: ResMut<User>, mut menu_state: ResMut<NextState<GameMenus>>) {
     let client = reqwest::blocking::Client::new();
@@ -23,36 +48,105 @@ pub fn load_characters(mut user: ResMut<User>, mut menu_state: ResMut<NextState<
         .bearer_auth(&user.token)
         .send();
 
-
     match response {
         Ok(response) => {
             if response.status() == StatusCode::OK {
                 user.characters = response.json().unwrap();
                 menu_state.set(GameMenus::ChooseCharacter);
             }
-        },
+        }
         Err(_) => menu_state.set(GameMenus::Failed),
     }
-
 }
 
 pub fn choose_character_ui(
-    user: Res<User>,
+    mut user: ResMut<User>,
     mut contexts: EguiContexts,
-    mut ev_spawn_token: EventWriter<SpawnToken>,
+    mut ui_state: ResMut<ChooseCharacterWindow>,
+    mut ev_to_host: EventWriter<ToHost>,
     mut menu_state: ResMut<NextState<GameMenus>>,
 ) {
+    let mut chosen = None;
+    let mut import = false;
+
     Window::new("Choose your character").show(contexts.ctx_mut(), |ui| {
         ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "Signed in as {} with {}",
+                    user.name,
+                    user.provider_name()
+                ));
+                if ui.small_button("Edit profile").clicked() {
+                    menu_state.set(GameMenus::EditProfile);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Room: ");
+                ui.text_edit_singleline(&mut ui_state.room);
+            });
+
             for character in &user.characters {
                 if ui.button(&character.name).clicked() {
-                    ev_spawn_token.send(SpawnToken(Token::new(Name::new(character.name.clone()))));
+                    chosen = Some(character.clone());
                 }
             }
 
             if ui.button("Create new character").clicked() {
                 menu_state.set(GameMenus::CreateCharacter);
             }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Import: ");
+                ui.text_edit_singleline(&mut ui_state.import_path);
+                ComboBox::from_id_source("import format")
+                    .selected_text(ui_state.import_format.name())
+                    .show_ui(ui, |ui| {
+                        for format in ImportFormat::ALL {
+                            ui.selectable_value(&mut ui_state.import_format, format, format.name());
+                        }
+                    });
+                import = ui.button("Import").clicked();
+            });
+            ScrollArea::vertical()
+                .id_source("import report")
+                .max_height(150.0)
+                .show(ui, |ui| {
+                    for line in &ui_state.import_report {
+                        ui.label(line);
+                    }
+                });
         });
     });
+
+    if import {
+        let imported = fs::read(ui_state.import_path.trim())
+            .map_err(|error| format!("Cannot read {}: {error}", ui_state.import_path))
+            .and_then(|json| import_character(&user.token, ui_state.import_format, json));
+        ui_state.import_report = match imported {
+            Ok(imported) => {
+                let mut report = vec![format!("Imported {}", imported.character.name)];
+                report.extend(imported.unmapped.iter().map(|unmapped| {
+                    format!(
+                        "Left out {} = {}: {}",
+                        unmapped.field, unmapped.value, unmapped.reason
+                    )
+                }));
+                user.characters.push(imported.character);
+                report
+            }
+            Err(error) => vec![error],
+        };
+    }
+
+    if let Some(character) = chosen {
+        ev_to_host.send(ToHost(ClientMessage::JoinRoom {
+            room: ui_state.room.clone(),
+            token: user.token.clone(),
+            character: Some(character.info()),
+        }));
+        user.character = Some(character);
+        menu_state.set(GameMenus::CharacterSheet);
+    }
 }
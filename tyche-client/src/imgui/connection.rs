@@ -0,0 +1,47 @@
+use bevy::ecs::system::Res;
+use bevy_egui::{
+    egui::{Align2, Area, Frame, Spinner},
+    EguiContexts,
+};
+
+use crate::network::{Connection, CurrentRoom};
+
+/// Covers the screen while the host is unreachable, since nothing done in
+/// the meantime would reach it.
+pub fn connection_ui(
+    mut contexts: EguiContexts,
+    connection: Option<Res<Connection>>,
+    room: Res<CurrentRoom>,
+) {
+    let Some(connection) = connection else {
+        return;
+    };
+    if connection.is_connected() {
+        return;
+    }
+
+    let action = match room.name.is_empty() {
+        true => "Connecting to the host",
+        false => "Reconnecting to the host",
+    };
+    let status = match &*connection {
+        Connection::Retrying { timer, .. } => {
+            let seconds = timer.remaining().as_secs_f32().ceil();
+            format!("{action}, retrying in {seconds}s")
+        }
+        _ => format!("{action}…"),
+    };
+
+    let ctx = contexts.ctx_mut();
+    Area::new("connection")
+        .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+        .interactable(false)
+        .show(ctx, |ui| {
+            Frame::popup(ui.style()).show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(Spinner::new());
+                    ui.label(status);
+                });
+            });
+        });
+}
@@ -0,0 +1,155 @@
+use bevy::ecs::{
+    schedule::NextState,
+    system::{Res, ResMut, Resource},
+};
+use bevy_egui::{
+    egui::{ComboBox, DragValue, Grid, ScrollArea, Window},
+    EguiContexts,
+};
+use tyche_protocol::character::{Ability, Attack, Character, Skill};
+
+use crate::{character::service_reply, character_service, user::User};
+
+use super::GameMenus;
+
+#[derive(Resource)]
+pub struct CreateCharacterWindow {
+    character: Character,
+    /// Why tyche-character refused the character, if it did.
+    status: String,
+}
+
+impl Default for CreateCharacterWindow {
+    fn default() -> Self {
+        Self {
+            character: Character::new(String::new()),
+            status: String::new(),
+        }
+    }
+}
+
+pub fn on_enter_create_character_ui(mut ui_state: ResMut<CreateCharacterWindow>) {
+    *ui_state = CreateCharacterWindow::default();
+}
+
+pub fn create_character_ui(
+    user: Res<User>,
+    mut contexts: EguiContexts,
+    mut ui_state: ResMut<CreateCharacterWindow>,
+    mut menu_state: ResMut<NextState<GameMenus>>,
+) {
+    let ui_state = &mut *ui_state;
+    let character = &mut ui_state.character;
+
+    Window::new("Create character").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Name: ");
+            ui.text_edit_singleline(&mut character.name);
+        });
+
+        ui.separator();
+        Grid::new("abilities").show(ui, |ui| {
+            for (i, ability) in Ability::ALL.into_iter().enumerate() {
+                ui.label(ability.short_name());
+                ui.add(DragValue::new(character.abilities.score_mut(ability)).clamp_range(1..=30));
+                let save = character.saving_throws.contains(&ability);
+                let mut proficient = save;
+                ui.checkbox(&mut proficient, "Save");
+                if proficient != save {
+                    toggle(&mut character.saving_throws, ability);
+                }
+                if i % 2 == 1 {
+                    ui.end_row();
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Proficiency bonus: ");
+            ui.add(DragValue::new(&mut character.proficiency_bonus).clamp_range(2..=6));
+            ui.label("Max HP: ");
+            ui.add(DragValue::new(&mut character.hit_points.max).clamp_range(1..=9999));
+        });
+
+        ui.separator();
+        ui.label("Proficient skills");
+        ScrollArea::vertical()
+            .id_source("skills")
+            .max_height(150.0)
+            .show(ui, |ui| {
+                for skill in Skill::ALL {
+                    let known = character.skills.contains(&skill);
+                    let mut proficient = known;
+                    let label = format!("{} ({})", skill.name(), skill.ability().short_name());
+                    ui.checkbox(&mut proficient, label);
+                    if proficient != known {
+                        toggle(&mut character.skills, skill);
+                    }
+                }
+            });
+
+        ui.separator();
+        ui.label("Attacks");
+        let mut removed = None;
+        Grid::new("attacks").show(ui, |ui| {
+            for (i, attack) in character.attacks.iter_mut().enumerate() {
+                ui.text_edit_singleline(&mut attack.name);
+                ComboBox::from_id_source(("attack ability", i))
+                    .selected_text(attack.ability.short_name())
+                    .show_ui(ui, |ui| {
+                        for ability in Ability::ALL {
+                            ui.selectable_value(&mut attack.ability, ability, ability.short_name());
+                        }
+                    });
+                ui.checkbox(&mut attack.proficient, "Proficient");
+                ui.text_edit_singleline(&mut attack.damage);
+                if ui.small_button("x").clicked() {
+                    removed = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(i) = removed {
+            character.attacks.remove(i);
+        }
+        if ui.button("Add attack").clicked() {
+            character.attacks.push(Attack {
+                name: "Attack".to_owned(),
+                ability: Ability::Strength,
+                proficient: true,
+                damage: "1d6".to_owned(),
+            });
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.small_button("<").clicked() {
+                menu_state.set(GameMenus::ChooseCharacter);
+            }
+            if ui.button("Create").clicked() {
+                character.hit_points.current = character.hit_points.max;
+                let created = reqwest::blocking::Client::new()
+                    .post(character_service!())
+                    .bearer_auth(&user.token)
+                    .json(&*character)
+                    .send()
+                    .map_err(|error| error.to_string())
+                    .and_then(service_reply::<Character>);
+
+                match created {
+                    Ok(_) => menu_state.set(GameMenus::LoadCharacters),
+                    Err(error) => ui_state.status = error,
+                }
+            }
+        });
+        ui.label(&ui_state.status);
+    });
+}
+
+fn toggle<T: PartialEq>(list: &mut Vec<T>, item: T) {
+    match list.iter().position(|known| *known == item) {
+        Some(i) => {
+            list.remove(i);
+        }
+        None => list.push(item),
+    }
+}
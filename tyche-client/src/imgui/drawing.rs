@@ -0,0 +1,43 @@
+use bevy::ecs::system::ResMut;
+use bevy_egui::{egui::Window, EguiContexts};
+
+use crate::{
+    drawing::{DrawTool, DrawingSettings},
+    map::MapTool,
+};
+
+pub fn drawing_ui(
+    mut contexts: EguiContexts,
+    mut settings: ResMut<DrawingSettings>,
+    mut tool: ResMut<MapTool>,
+) {
+    Window::new("Draw").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal_wrapped(|ui| {
+            let tools = [
+                (MapTool::Select, "Select"),
+                (MapTool::Draw(DrawTool::Pen), "Pen"),
+                (MapTool::Draw(DrawTool::Line), "Line"),
+                (MapTool::Draw(DrawTool::Rectangle), "Rectangle"),
+                (MapTool::Draw(DrawTool::Circle), "Circle"),
+                (MapTool::Draw(DrawTool::Cone), "Cone"),
+                (MapTool::Draw(DrawTool::Text), "Text"),
+                (MapTool::EraseDrawing, "Erase"),
+            ];
+            for (option, name) in tools {
+                ui.selectable_value(&mut *tool, option, name);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Color: ");
+            ui.color_edit_button_srgb(&mut settings.color);
+            ui.checkbox(&mut settings.temporary, "Fade away");
+        });
+        if *tool == MapTool::Draw(DrawTool::Text) {
+            ui.horizontal(|ui| {
+                ui.label("Text: ");
+                ui.text_edit_singleline(&mut settings.text);
+            });
+        }
+    });
+}
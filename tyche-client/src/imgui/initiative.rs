@@ -0,0 +1,157 @@
+use bevy::{
+    core::Name,
+    ecs::{
+        event::{EventReader, EventWriter},
+        system::{Query, Res, ResMut, Resource},
+    },
+};
+use bevy_egui::{
+    egui::{Color32, ComboBox, Grid, RichText, Window},
+    EguiContexts,
+};
+use tyche_protocol::{
+    initiative::{Initiative, InitiativeCommand, TieBreak},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    network::{CurrentRoom, HostMessage, ToHost},
+    token::Token,
+    user::User,
+};
+
+#[derive(Default, Resource)]
+pub struct InitiativeTracker(pub Initiative);
+
+pub fn update_initiative(
+    mut ev_host: EventReader<HostMessage>,
+    mut tracker: ResMut<InitiativeTracker>,
+) {
+    for HostMessage(message) in ev_host.read() {
+        match message {
+            ServerMessage::Joined { initiative, .. } | ServerMessage::Initiative(initiative) => {
+                tracker.0 = initiative.clone();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn tie_break_name(tie_break: TieBreak) -> &'static str {
+    match tie_break {
+        TieBreak::Bonus => "Highest bonus",
+        TieBreak::PlayersFirst => "Players first",
+        TieBreak::Random => "Random",
+    }
+}
+
+pub fn initiative_ui(
+    mut contexts: EguiContexts,
+    tracker: Res<InitiativeTracker>,
+    room: Res<CurrentRoom>,
+    user: Res<User>,
+    tokens: Query<(&Token, &Name)>,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    let initiative = &tracker.0;
+    let active = initiative.active().map(|combatant| combatant.token);
+    let controls = |token| {
+        room.is_gm
+            || tokens
+                .iter()
+                .any(|(t, _)| t.id == token && t.owner.as_ref() == Some(&user.user_id))
+    };
+    let mut commands = Vec::new();
+
+    Window::new("Initiative").show(contexts.ctx_mut(), |ui| {
+        match initiative.round {
+            0 => ui.label("Combat has not started"),
+            round => ui.label(format!("Round {round}")),
+        };
+
+        Grid::new("combatants").striped(true).show(ui, |ui| {
+            for combatant in &initiative.combatants {
+                let value = combatant
+                    .initiative
+                    .map_or("–".to_owned(), |value| value.to_string());
+                let mut name = RichText::new(&combatant.name);
+                if Some(combatant.token) == active {
+                    name = name.strong().color(Color32::GOLD);
+                }
+                if combatant.delayed {
+                    name = name.italics().weak();
+                }
+
+                ui.label(value);
+                ui.label(name);
+                if controls(combatant.token) {
+                    if combatant.delayed {
+                        if ui.small_button("Act now").clicked() {
+                            commands.push(InitiativeCommand::Resume(combatant.token));
+                        }
+                    } else if ui.small_button("Delay").clicked() {
+                        commands.push(InitiativeCommand::Delay(combatant.token));
+                    }
+                }
+                if room.is_gm && ui.small_button("Remove").clicked() {
+                    commands.push(InitiativeCommand::Remove(combatant.token));
+                }
+                ui.end_row();
+            }
+        });
+
+        ui.separator();
+        if active.is_some_and(controls) && ui.button("End turn").clicked() {
+            commands.push(InitiativeCommand::Next);
+        }
+
+        if !room.is_gm {
+            return;
+        }
+
+        ComboBox::from_label("Add to combat")
+            .selected_text("Choose a token")
+            .show_ui(ui, |ui| {
+                for (token, name) in &tokens {
+                    let in_combat = initiative.combatants.iter().any(|c| c.token == token.id);
+                    if !in_combat && ui.selectable_label(false, name.as_str()).clicked() {
+                        commands.push(InitiativeCommand::Add(token.id));
+                    }
+                }
+            });
+
+        let mut tie_break = initiative.tie_break;
+        ComboBox::from_label("Ties")
+            .selected_text(tie_break_name(tie_break))
+            .show_ui(ui, |ui| {
+                for option in [TieBreak::Bonus, TieBreak::PlayersFirst, TieBreak::Random] {
+                    ui.selectable_value(&mut tie_break, option, tie_break_name(option));
+                }
+            });
+        if tie_break != initiative.tie_break {
+            commands.push(InitiativeCommand::SetTieBreak(tie_break));
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Roll all").clicked() {
+                commands.push(InitiativeCommand::RollAll);
+            }
+            if ui.button("Sort").clicked() {
+                commands.push(InitiativeCommand::Sort);
+            }
+            if initiative.round == 0 && ui.button("Start").clicked() {
+                commands.push(InitiativeCommand::Start);
+            }
+            if initiative.round > 0 && ui.button("Next").clicked() {
+                commands.push(InitiativeCommand::Next);
+            }
+            if ui.button("Clear").clicked() {
+                commands.push(InitiativeCommand::Clear);
+            }
+        });
+    });
+
+    for command in commands {
+        ev_to_host.send(ToHost(ClientMessage::Initiative(command)));
+    }
+}
@@ -0,0 +1,239 @@
+use std::{fs, path::Path};
+
+use bevy::ecs::{
+    event::EventWriter,
+    system::{Res, ResMut, Resource},
+};
+use bevy_egui::{
+    egui::{ComboBox, DragValue, ProgressBar, Ui, Window},
+    EguiContexts,
+};
+use tyche_protocol::{
+    fog::{FogBrush, FogShape, FogStroke, MAX_BRUSH_RADIUS},
+    grid::{Diagonals, GridKind},
+    map::CELL_SIZE,
+    vision::WallKind,
+    ClientMessage,
+};
+
+use crate::{
+    asset_cache::AssetCache,
+    fog::FogLayer,
+    map::{CurrentMap, MapTool, MapUpload},
+    network::{CurrentRoom, ToHost},
+    vision::Walls,
+};
+
+#[derive(Resource)]
+pub struct MapWindow {
+    path: String,
+    error: Option<String>,
+    brush: FogBrush,
+    npc: String,
+}
+
+impl Default for MapWindow {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            error: None,
+            brush: FogBrush::Reveal,
+            npc: String::new(),
+        }
+    }
+}
+
+fn read_map(path: &str) -> Result<(String, Vec<u8>), String> {
+    let image = fs::read(path).map_err(|error| format!("Could not read {path}: {error}"))?;
+
+    let name = Path::new(path)
+        .file_stem()
+        .map_or("Map".to_owned(), |name| name.to_string_lossy().into_owned());
+    Ok((name, image))
+}
+
+pub fn map_ui(
+    mut contexts: EguiContexts,
+    room: Res<CurrentRoom>,
+    mut window: ResMut<MapWindow>,
+    mut map: ResMut<CurrentMap>,
+    mut upload: ResMut<MapUpload>,
+    mut cache: ResMut<AssetCache>,
+    mut tool: ResMut<MapTool>,
+    walls: Res<Walls>,
+    mut fog_layer: ResMut<FogLayer>,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    let window = &mut *window;
+
+    Window::new("Map").show(contexts.ctx_mut(), |ui| {
+        match &map.info {
+            Some(info) => ui.label(&info.name),
+            None => ui.label("No map"),
+        };
+        if let Some(info) = &map.info {
+            let progress = cache.progress(&info.asset.hash);
+            if progress < 1.0 {
+                ui.add(ProgressBar::new(progress).text("Downloading"));
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut map.show_grid, "Show grid");
+            ui.selectable_value(&mut *tool, MapTool::Select, "Select");
+            ui.selectable_value(&mut *tool, MapTool::Measure, "Measure");
+        });
+
+        if !room.is_gm {
+            return;
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Image: ");
+            ui.text_edit_singleline(&mut window.path);
+            if cache.upload_remaining() == 0 && ui.button("Upload").clicked() {
+                window.error = read_map(window.path.trim())
+                    .and_then(|(name, image)| upload.start(&mut cache, name, &image))
+                    .err();
+            }
+        });
+        if cache.upload_remaining() > 0 {
+            let remaining = cache.upload_remaining();
+            ui.label(format!("Uploading, {remaining} chunks left"));
+        }
+        if let Some(error) = &window.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("NPC: ");
+            ui.text_edit_singleline(&mut window.npc);
+            let name = window.npc.trim();
+            if !name.is_empty() && ui.button("Add token").clicked() {
+                ev_to_host.send(ToHost(ClientMessage::SpawnToken {
+                    name: name.to_owned(),
+                    x: CELL_SIZE / 2.0,
+                    y: CELL_SIZE / 2.0,
+                    initiative_bonus: 0,
+                }));
+            }
+        });
+
+        ui.separator();
+        let mut fog = walls.fog;
+        if ui.checkbox(&mut fog, "Fog of war").changed() {
+            ev_to_host.send(ToHost(ClientMessage::SetFog(fog)));
+        }
+        ui.horizontal_wrapped(|ui| {
+            let tools = [
+                (MapTool::Select, "Select"),
+                (MapTool::Wall(WallKind::Wall), "Wall"),
+                (MapTool::Wall(WallKind::Door { open: false }), "Door"),
+                (MapTool::Wall(WallKind::Window), "Window"),
+                (MapTool::ToggleDoor, "Open/close door"),
+                (MapTool::EraseWall, "Erase wall"),
+            ];
+            for (option, name) in tools {
+                ui.selectable_value(&mut *tool, option, name);
+            }
+        });
+
+        ui.separator();
+        let stroke = fog_ui(ui, &mut window.brush, &mut tool, &mut fog_layer);
+        if let Some(stroke) = stroke {
+            ev_to_host.send(ToHost(ClientMessage::PaintFog(stroke)));
+        }
+
+        let Some(info) = &mut map.info else {
+            return;
+        };
+
+        ui.separator();
+        ui.label("Calibration");
+        let calibration = &mut info.calibration;
+        let grid = &mut calibration.grid;
+        ComboBox::from_label("Grid")
+            .selected_text(grid.kind.name())
+            .show_ui(ui, |ui| {
+                for kind in GridKind::ALL {
+                    ui.selectable_value(&mut grid.kind, kind, kind.name());
+                }
+            });
+        if grid.kind == GridKind::Square {
+            ui.horizontal(|ui| {
+                ui.label("Diagonals: ");
+                ui.radio_value(&mut grid.diagonals, Diagonals::Alternating, "5-10-5");
+                ui.radio_value(&mut grid.diagonals, Diagonals::Uniform, "5 ft");
+            });
+        }
+        ui.horizontal(|ui| {
+            ui.label("Cell size (px): ");
+            ui.add(
+                DragValue::new(&mut calibration.cell_size)
+                    .clamp_range(1.0..=1000.0)
+                    .speed(0.1),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Offset (px): ");
+            ui.add(DragValue::new(&mut calibration.offset_x).speed(0.5));
+            ui.add(DragValue::new(&mut calibration.offset_y).speed(0.5));
+        });
+        if ui.button("Save calibration").clicked() {
+            ev_to_host.send(ToHost(ClientMessage::CalibrateMap(*calibration)));
+        }
+    });
+}
+
+fn fog_ui(
+    ui: &mut Ui,
+    brush: &mut FogBrush,
+    tool: &mut MapTool,
+    fog: &mut FogLayer,
+) -> Option<FogStroke> {
+    let mut stroke = None;
+
+    ui.horizontal(|ui| {
+        ui.label("Fog brush: ");
+        ui.radio_value(brush, FogBrush::Reveal, "Reveal");
+        ui.radio_value(brush, FogBrush::Hide, "Hide");
+    });
+    ui.horizontal(|ui| {
+        ui.selectable_value(tool, MapTool::FogRectangle(*brush), "Rectangle");
+        ui.selectable_value(tool, MapTool::FogPolygon(*brush), "Polygon");
+        ui.selectable_value(tool, MapTool::FogFreehand(*brush), "Freehand");
+    });
+    ui.horizontal(|ui| {
+        let mut cells = fog.brush_radius / CELL_SIZE;
+        ui.label("Brush radius (cells): ");
+        ui.add(
+            DragValue::new(&mut cells)
+                .clamp_range(0.25..=MAX_BRUSH_RADIUS / CELL_SIZE)
+                .speed(0.05),
+        );
+        fog.brush_radius = cells * CELL_SIZE;
+    });
+    ui.horizontal(|ui| {
+        for (brush, name) in [
+            (FogBrush::Hide, "Hide all"),
+            (FogBrush::Reveal, "Reveal all"),
+        ] {
+            if ui.button(name).clicked() {
+                stroke = Some(FogStroke {
+                    brush,
+                    shape: FogShape::All,
+                });
+            }
+        }
+    });
+
+    // Keep the current fog tool painting with the chosen brush.
+    *tool = match *tool {
+        MapTool::FogRectangle(_) => MapTool::FogRectangle(*brush),
+        MapTool::FogPolygon(_) => MapTool::FogPolygon(*brush),
+        MapTool::FogFreehand(_) => MapTool::FogFreehand(*brush),
+        tool => tool,
+    };
+    stroke
+}
@@ -2,18 +2,41 @@ use bevy::{
     app::{App, Plugin, Update},
     ecs::{
         schedule::{common_conditions::in_state, IntoSystemConfigs, NextState, OnEnter, States},
-        system::{Res, ResMut, Resource},
+        system::ResMut,
     },
 };
 use bevy_egui::{egui::Window, EguiContexts};
 
-use crate::{
-    character_service,
-    user::{Character, User},
+mod campaign;
+mod character_sheet;
+mod chat;
+mod choose_character;
+mod connection;
+mod create_character;
+mod drawing;
+mod initiative;
+mod map;
+mod players;
+mod profile;
+mod template;
+mod token;
+use campaign::{campaign_ui, CampaignWindow};
+use character_sheet::{
+    character_sheet_ui, roll_log_ui, update_roll_log, CharacterSheetWindow, RollLog,
 };
+use chat::{chat_ui, update_chat, ChatWindow};
+use choose_character::{choose_character_ui, load_characters, ChooseCharacterWindow};
+use connection::connection_ui;
+use create_character::{create_character_ui, on_enter_create_character_ui, CreateCharacterWindow};
+use drawing::drawing_ui;
+use initiative::{initiative_ui, update_initiative};
+use map::{map_ui, MapWindow};
+use players::players_ui;
+use profile::{on_enter_profile_ui, profile_ui, ProfileWindow};
+use template::template_ui;
+use token::{token_ui, TokenWindow};
 
-mod choose_character;
-use choose_character::{choose_character_ui, load_characters};
+pub use initiative::InitiativeTracker;
 
 pub struct ImguiPlugin;
 
@@ -21,17 +44,43 @@ impl Plugin for ImguiPlugin {
     fn build(&self, app: &mut App) {
         app.add_state::<GameMenus>()
             .insert_resource(CreateCharacterWindow::default())
+            .insert_resource(ChooseCharacterWindow::default())
+            .insert_resource(CharacterSheetWindow::default())
+            .insert_resource(RollLog::default())
+            .insert_resource(ChatWindow::default())
+            .insert_resource(InitiativeTracker::default())
+            .insert_resource(MapWindow::default())
+            .insert_resource(TokenWindow::default())
+            .insert_resource(CampaignWindow::default())
+            .insert_resource(ProfileWindow::default())
             .add_systems(OnEnter(GameMenus::LoadCharacters), load_characters)
             .add_systems(
                 OnEnter(GameMenus::CreateCharacter),
                 on_enter_create_character_ui,
             )
+            .add_systems(OnEnter(GameMenus::EditProfile), on_enter_profile_ui)
             .add_systems(
                 Update,
                 (
                     create_character_ui.run_if(in_state(GameMenus::CreateCharacter)),
                     choose_character_ui.run_if(in_state(GameMenus::ChooseCharacter)),
+                    profile_ui.run_if(in_state(GameMenus::EditProfile)),
                     failed_ui.run_if(in_state(GameMenus::Failed)),
+                    (
+                        character_sheet_ui,
+                        roll_log_ui,
+                        chat_ui,
+                        initiative_ui,
+                        map_ui,
+                        drawing_ui,
+                        template_ui,
+                        token_ui,
+                        campaign_ui,
+                        players_ui,
+                    )
+                        .run_if(in_state(GameMenus::CharacterSheet)),
+                    (update_roll_log, update_chat, update_initiative),
+                    connection_ui,
                 ),
             );
     }
@@ -44,18 +93,11 @@ pub enum GameMenus {
     CreateCharacter,
     LoadCharacters,
     ChooseCharacter,
+    EditProfile,
+    CharacterSheet,
     Failed,
 }
 
-#[derive(Default, Resource)]
-struct CreateCharacterWindow {
-    character_name: String,
-}
-
-fn on_enter_create_character_ui(mut ui_state: ResMut<CreateCharacterWindow>) {
-    ui_state.character_name = "".to_owned();
-}
-
 fn failed_ui(mut ctx: EguiContexts, mut menu_state: ResMut<NextState<GameMenus>>) {
     Window::new("Something went wrong").show(ctx.ctx_mut(), |ui| {
         if ui.button("Reload Characters").clicked() {
@@ -63,33 +105,3 @@ fn failed_ui(mut ctx: EguiContexts, mut menu_state: ResMut<NextState<GameMenus>>
         }
     });
 }
-
-fn create_character_ui(
-    user: Res<User>,
-    mut contexts: EguiContexts,
-    mut ui_state: ResMut<CreateCharacterWindow>,
-    mut menu_state: ResMut<NextState<GameMenus>>,
-) {
-    Window::new("Create character".to_owned() + &user.name).show(contexts.ctx_mut(), |ui| {
-        ui.horizontal(|ui| {
-            ui.label("Write a name: ");
-            ui.text_edit_singleline(&mut ui_state.character_name);
-        });
-
-        ui.horizontal(|ui| {
-            if ui.small_button("<").clicked() {
-                menu_state.set(GameMenus::ChooseCharacter);
-            }
-            if ui.button("Create").clicked() {
-                let client = reqwest::blocking::Client::new();
-                let _reply = client
-                    .post(character_service!())
-                    .header("Content-Type", "application/json")
-                    .json(&Character::new(ui_state.character_name.clone()))
-                    .send();
-
-                menu_state.set(GameMenus::LoadCharacters);
-            }
-        })
-    });
-}
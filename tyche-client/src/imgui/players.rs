@@ -0,0 +1,28 @@
+use bevy::ecs::system::Res;
+use bevy_egui::{
+    egui::{Color32, Grid, RichText, Vec2, Window},
+    EguiContexts,
+};
+
+use crate::profile::Players;
+
+const AVATAR_SIZE: f32 = 24.0;
+
+pub fn players_ui(mut contexts: EguiContexts, players: Res<Players>) {
+    Window::new("Players").show(contexts.ctx_mut(), |ui| {
+        Grid::new("players").striped(true).show(ui, |ui| {
+            for player in players.players.values() {
+                match players.avatar_of(&player.user_id) {
+                    Some(avatar) => ui.image((avatar, Vec2::splat(AVATAR_SIZE))),
+                    None => ui.label(""),
+                };
+
+                let [r, g, b] = player.color;
+                ui.label(RichText::new(&player.name).color(Color32::from_rgb(r, g, b)));
+                ui.label(player.character.as_deref().unwrap_or(""));
+                ui.label(if player.is_gm { "GM" } else { "" });
+                ui.end_row();
+            }
+        });
+    });
+}
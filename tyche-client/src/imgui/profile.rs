@@ -0,0 +1,87 @@
+use std::fs;
+
+use bevy::ecs::{
+    schedule::NextState,
+    system::{Res, ResMut, Resource},
+};
+use bevy_egui::{egui::Window, EguiContexts};
+
+use crate::{
+    profile::{save_profile, upload_avatar},
+    user::{Profile, User},
+};
+
+use super::GameMenus;
+
+#[derive(Default, Resource)]
+pub struct ProfileWindow {
+    display_name: String,
+    color: [u8; 3],
+    avatar_path: String,
+    /// What happened to the last change, shown under the buttons.
+    status: String,
+}
+
+pub fn on_enter_profile_ui(user: Res<User>, mut ui_state: ResMut<ProfileWindow>) {
+    *ui_state = ProfileWindow {
+        display_name: user.name.clone(),
+        color: user.color,
+        ..ProfileWindow::default()
+    };
+}
+
+pub fn profile_ui(
+    mut user: ResMut<User>,
+    mut contexts: EguiContexts,
+    mut ui_state: ResMut<ProfileWindow>,
+    mut menu_state: ResMut<NextState<GameMenus>>,
+) {
+    let ui_state = &mut *ui_state;
+
+    Window::new("Profile").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Display name: ");
+            ui.text_edit_singleline(&mut ui_state.display_name);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Color: ");
+            ui.color_edit_button_srgb(&mut ui_state.color);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Avatar: ");
+            ui.text_edit_singleline(&mut ui_state.avatar_path);
+            if ui.button("Upload").clicked() {
+                ui_state.status = match fs::read(ui_state.avatar_path.trim()) {
+                    Ok(image) => match upload_avatar(&user.token, image) {
+                        Ok(_) => "Avatar uploaded".to_owned(),
+                        Err(error) => error,
+                    },
+                    Err(error) => format!("Cannot read {}: {error}", ui_state.avatar_path),
+                };
+            }
+        });
+        ui.small("PNG, JPEG or WebP, up to 256 KiB");
+
+        ui.horizontal(|ui| {
+            if ui.small_button("<").clicked() {
+                menu_state.set(GameMenus::ChooseCharacter);
+            }
+            if ui.button("Save").clicked() {
+                let profile = Profile {
+                    display_name: ui_state.display_name.clone(),
+                    color: ui_state.color,
+                    avatar: None,
+                };
+                match save_profile(&user.token, &profile) {
+                    Ok(profile) => {
+                        user.name = profile.display_name;
+                        user.color = profile.color;
+                        menu_state.set(GameMenus::ChooseCharacter);
+                    }
+                    Err(error) => ui_state.status = error,
+                }
+            }
+        });
+        ui.label(&ui_state.status);
+    });
+}
@@ -0,0 +1,130 @@
+use bevy::{
+    core::Name,
+    ecs::{
+        event::EventWriter,
+        system::{Query, Res, ResMut},
+    },
+    transform::components::GlobalTransform,
+};
+use bevy_egui::{
+    egui::{ComboBox, DragValue, Window},
+    EguiContexts,
+};
+use tyche_protocol::{
+    character::Ability,
+    template::{TemplateShape, FEET_PER_CELL, MAX_TEMPLATE_FEET},
+    ClientMessage,
+};
+
+use crate::{
+    map::MapTool,
+    network::{CurrentRoom, ToHost},
+    template::{affected_tokens, TemplateSettings, Templates},
+    token::Token,
+    user::User,
+};
+
+pub fn template_ui(
+    mut contexts: EguiContexts,
+    templates: Res<Templates>,
+    mut settings: ResMut<TemplateSettings>,
+    mut tool: ResMut<MapTool>,
+    room: Res<CurrentRoom>,
+    user: Res<User>,
+    tokens: Query<(&Token, &Name, &GlobalTransform)>,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    let settings = &mut *settings;
+
+    Window::new("Templates").show(contexts.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            for shape in TemplateShape::ALL {
+                ui.selectable_value(&mut settings.shape, shape, shape.name());
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Size (ft): ");
+            ui.add(
+                DragValue::new(&mut settings.size_feet)
+                    .clamp_range(FEET_PER_CELL as u32..=MAX_TEMPLATE_FEET)
+                    .speed(1.0),
+            );
+            ui.selectable_value(&mut *tool, MapTool::Template, "Place");
+        });
+
+        ui.separator();
+        for template in templates.0.values() {
+            let name = format!(
+                "{} ft {}",
+                template.size_feet,
+                template.shape.name().to_lowercase()
+            );
+            let selected = settings.selected == Some(template.id);
+            if ui.selectable_label(selected, name).clicked() {
+                settings.selected = Some(template.id);
+            }
+        }
+
+        let Some(template) = settings.selected.and_then(|id| templates.0.get(&id)) else {
+            return;
+        };
+        let mut template = template.clone();
+
+        ui.separator();
+        let affected = affected_tokens(&template, &tokens);
+        ui.label(match affected.is_empty() {
+            true => "Nobody is affected".to_owned(),
+            false => format!("Affected: {}", affected.join(", ")),
+        });
+
+        if !room.is_gm && template.owner != user.user_id {
+            return;
+        }
+
+        let mut degrees = template.angle.to_degrees();
+        ui.horizontal(|ui| {
+            ui.label("Direction (°): ");
+            ui.add(DragValue::new(&mut degrees).speed(1.0));
+            ui.label("Size (ft): ");
+            ui.add(
+                DragValue::new(&mut template.size_feet)
+                    .clamp_range(FEET_PER_CELL as u32..=MAX_TEMPLATE_FEET)
+                    .speed(1.0),
+            );
+        });
+        template.angle = degrees.to_radians();
+        if template.angle != templates.0[&template.id].angle
+            || template.size_feet != templates.0[&template.id].size_feet
+        {
+            ev_to_host.send(ToHost(ClientMessage::PlaceTemplate {
+                id: Some(template.id),
+                shape: template.shape,
+                size_feet: template.size_feet,
+                origin: template.origin,
+                angle: template.angle,
+            }));
+        }
+
+        ui.horizontal(|ui| {
+            ComboBox::from_id_source("save ability")
+                .selected_text(settings.ability.short_name())
+                .show_ui(ui, |ui| {
+                    for ability in Ability::ALL {
+                        ui.selectable_value(&mut settings.ability, ability, ability.short_name());
+                    }
+                });
+            ui.label("DC");
+            ui.add(DragValue::new(&mut settings.dc).clamp_range(1..=40));
+            if ui.button("Roll save for all affected").clicked() {
+                ev_to_host.send(ToHost(ClientMessage::RollSaves {
+                    template: template.id,
+                    ability: settings.ability,
+                    dc: Some(settings.dc),
+                }));
+            }
+        });
+        if ui.button("Remove").clicked() {
+            ev_to_host.send(ToHost(ClientMessage::RemoveTemplate(template.id)));
+        }
+    });
+}
@@ -0,0 +1,203 @@
+use bevy::{
+    core::Name,
+    ecs::{
+        event::EventWriter,
+        system::{Query, Res, ResMut, Resource},
+    },
+};
+use bevy_egui::{
+    egui::{ComboBox, DragValue, Ui, Window},
+    EguiContexts,
+};
+use tyche_protocol::{
+    token::{Condition, Health, HealthView, HealthVisibility, Status, StatusChange},
+    ClientMessage,
+};
+
+use crate::{
+    network::{CurrentRoom, ToHost},
+    token::{SelectedToken, Token},
+    user::User,
+};
+
+#[derive(Resource)]
+pub struct TokenWindow {
+    amount: i32,
+    condition: Condition,
+    /// Zero means the condition lasts until removed.
+    rounds: u32,
+}
+
+impl Default for TokenWindow {
+    fn default() -> Self {
+        Self {
+            amount: 1,
+            condition: Condition::Prone,
+            rounds: 0,
+        }
+    }
+}
+
+fn health_ui(ui: &mut Ui, health: &HealthView, amount: &mut i32) -> Option<StatusChange> {
+    let mut change = None;
+
+    let HealthView::Full(health) = health else {
+        if ui.button("Track hit points").clicked() {
+            let health = Health {
+                current: 10,
+                max: 10,
+                temp: 0,
+            };
+            change = Some(StatusChange::SetHealth(Some(health)));
+        }
+        return change;
+    };
+
+    let mut edited = *health;
+    ui.horizontal(|ui| {
+        ui.label("HP: ");
+        let current = ui.add(DragValue::new(&mut edited.current).clamp_range(0..=edited.max));
+        ui.label("/");
+        let max = ui.add(DragValue::new(&mut edited.max).clamp_range(1..=9999));
+        ui.label("Temp: ");
+        let temp = ui.add(DragValue::new(&mut edited.temp).clamp_range(0..=9999));
+        if current.changed() || max.changed() || temp.changed() {
+            edited.current = edited.current.min(edited.max);
+            change = Some(StatusChange::SetHealth(Some(edited)));
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.add(DragValue::new(amount).clamp_range(1..=9999));
+        if ui.button("Damage").clicked() {
+            change = Some(StatusChange::Damage(*amount));
+        }
+        if ui.button("Heal").clicked() {
+            change = Some(StatusChange::Damage(-*amount));
+        }
+        if ui.button("Stop tracking").clicked() {
+            change = Some(StatusChange::SetHealth(None));
+        }
+    });
+
+    change
+}
+
+fn show_health(ui: &mut Ui, health: &HealthView) {
+    match health {
+        HealthView::Unknown => {}
+        HealthView::Bloodied(true) => {
+            ui.label("Bloodied");
+        }
+        HealthView::Bloodied(false) => {
+            ui.label("Not bloodied");
+        }
+        HealthView::Full(health) => {
+            ui.label(format!(
+                "HP: {}/{} (+{} temp)",
+                health.current, health.max, health.temp
+            ));
+        }
+    }
+}
+
+pub fn token_ui(
+    mut contexts: EguiContexts,
+    mut window: ResMut<TokenWindow>,
+    selected: Res<SelectedToken>,
+    tokens: Query<(&Token, &Name)>,
+    room: Res<CurrentRoom>,
+    user: Res<User>,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    let Some((token, name)) = tokens
+        .iter()
+        .find(|(token, _)| Some(token.id) == selected.0)
+    else {
+        return;
+    };
+    let controls = room.is_gm || token.owner.as_ref() == Some(&user.user_id);
+    let window = &mut *window;
+    let mut changes = Vec::new();
+    let mut messages = Vec::new();
+
+    Window::new("Token").show(contexts.ctx_mut(), |ui| {
+        ui.heading(name.as_str());
+        if token.character.is_some() {
+            ui.label("Follows its character sheet");
+        }
+        if room.is_gm {
+            ui.horizontal(|ui| {
+                if ui.button("Duplicate as minion").clicked() {
+                    messages.push(ClientMessage::DuplicateToken(token.id));
+                }
+                if token.character.is_some() && ui.button("Unlink from sheet").clicked() {
+                    messages.push(ClientMessage::UnlinkToken(token.id));
+                }
+            });
+        }
+
+        match controls {
+            true => changes.extend(health_ui(ui, &token.health, &mut window.amount)),
+            false => show_health(ui, &token.health),
+        }
+        if room.is_gm {
+            let mut visibility = token.health_visibility;
+            ComboBox::from_label("Players see")
+                .selected_text(visibility.name())
+                .show_ui(ui, |ui| {
+                    for option in HealthVisibility::ALL {
+                        ui.selectable_value(&mut visibility, option, option.name());
+                    }
+                });
+            if visibility != token.health_visibility {
+                changes.push(StatusChange::SetHealthVisibility(visibility));
+            }
+        }
+
+        ui.separator();
+        for status in &token.conditions {
+            ui.horizontal(|ui| {
+                match status.rounds {
+                    Some(rounds) => {
+                        ui.label(format!("{} ({rounds} rounds)", status.condition.name()))
+                    }
+                    None => ui.label(status.condition.name()),
+                };
+                if controls && ui.small_button("Remove").clicked() {
+                    changes.push(StatusChange::RemoveCondition(status.condition));
+                }
+            });
+        }
+        if !controls {
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ComboBox::from_id_source("condition")
+                .selected_text(window.condition.name())
+                .show_ui(ui, |ui| {
+                    for condition in Condition::ALL {
+                        ui.selectable_value(&mut window.condition, condition, condition.name());
+                    }
+                });
+            ui.label("Rounds: ");
+            ui.add(DragValue::new(&mut window.rounds).clamp_range(0..=100));
+            if ui.button("Add").clicked() {
+                changes.push(StatusChange::AddCondition(Status {
+                    condition: window.condition,
+                    rounds: (window.rounds > 0).then_some(window.rounds),
+                }));
+            }
+        });
+    });
+
+    let changes = changes
+        .into_iter()
+        .map(|change| ClientMessage::ChangeStatus {
+            token: token.id,
+            change,
+        });
+    for message in messages.into_iter().chain(changes) {
+        ev_to_host.send(ToHost(message));
+    }
+}
@@ -0,0 +1,285 @@
+use std::f32::consts::PI;
+
+use bevy::{
+    prelude::*,
+    render::texture::{CompressedImageFormats, ImageSampler, ImageType},
+    sprite::Anchor,
+};
+use tyche_protocol::{
+    asset::AssetHash,
+    fog::FogBrush,
+    grid::{hex_radius, Grid, GridKind},
+    map::{MapInfo, CELL_SIZE},
+    vision::WallKind,
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    asset_cache::{AssetCache, AssetDownloaded},
+    drawing::DrawTool,
+    network::{HostMessage, ToHost},
+};
+
+const GRID_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.35);
+
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(CurrentMap::default())
+            .insert_resource(MapUpload::default())
+            .insert_resource(MapTool::default())
+            .add_systems(
+                Update,
+                (handle_map_messages, apply_calibration, draw_grid).chain(),
+            );
+    }
+}
+
+/// The map of the current room.
+#[derive(Debug, Resource)]
+pub struct CurrentMap {
+    pub info: Option<MapInfo>,
+    pub show_grid: bool,
+}
+
+impl Default for CurrentMap {
+    fn default() -> Self {
+        Self {
+            info: None,
+            show_grid: true,
+        }
+    }
+}
+
+impl CurrentMap {
+    /// The grid of the map, or a square grid when there is no map.
+    pub fn grid(&self) -> Grid {
+        self.info
+            .as_ref()
+            .map_or(Grid::default(), |info| info.calibration.grid)
+    }
+}
+
+/// A map whose image is being uploaded, it is set once the host has it.
+#[derive(Debug, Default, Resource)]
+pub struct MapUpload(Option<(String, AssetHash)>);
+
+impl MapUpload {
+    pub fn start(
+        &mut self,
+        cache: &mut AssetCache,
+        name: String,
+        image: &[u8],
+    ) -> Result<(), String> {
+        let hash = cache.upload(image)?;
+        self.0 = Some((name, hash));
+        Ok(())
+    }
+
+    fn is_waiting_for(&self, hash: &AssetHash) -> bool {
+        self.0.as_ref().is_some_and(|(_, pending)| pending == hash)
+    }
+}
+
+#[derive(Component)]
+pub struct MapLayer;
+
+/// What clicking and dragging on the map does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Resource)]
+pub enum MapTool {
+    /// Move the tokens you control.
+    #[default]
+    Select,
+    Wall(WallKind),
+    EraseWall,
+    ToggleDoor,
+    FogRectangle(FogBrush),
+    FogPolygon(FogBrush),
+    FogFreehand(FogBrush),
+    Draw(DrawTool),
+    EraseDrawing,
+    Template,
+    Measure,
+}
+
+/// A mesh covering a single cell of the grid, and how to rotate it.
+pub fn cell_mesh(grid: &Grid) -> (Mesh, Quat) {
+    match grid.kind {
+        GridKind::Square | GridKind::Gridless => (
+            shape::Quad::new(Vec2::splat(CELL_SIZE)).into(),
+            Quat::IDENTITY,
+        ),
+        GridKind::HexPointy => (
+            shape::RegularPolygon::new(hex_radius(), 6).into(),
+            Quat::IDENTITY,
+        ),
+        GridKind::HexFlat => (
+            shape::RegularPolygon::new(hex_radius(), 6).into(),
+            Quat::from_rotation_z(PI / 6.0),
+        ),
+    }
+}
+
+fn handle_map_messages(
+    mut ev_host: EventReader<HostMessage>,
+    mut ev_downloaded: EventReader<AssetDownloaded>,
+    mut map: ResMut<CurrentMap>,
+    mut upload: ResMut<MapUpload>,
+    mut cache: ResMut<AssetCache>,
+    mut images: ResMut<Assets<Image>>,
+    layers: Query<Entity, With<MapLayer>>,
+    mut commands: Commands,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    for HostMessage(message) in ev_host.read() {
+        match message {
+            ServerMessage::Joined { map: info, .. } => {
+                change_map(
+                    &mut map,
+                    info.clone(),
+                    &mut cache,
+                    &mut images,
+                    &layers,
+                    &mut commands,
+                );
+            }
+            ServerMessage::MapChanged(info) => {
+                change_map(
+                    &mut map,
+                    Some(info.clone()),
+                    &mut cache,
+                    &mut images,
+                    &layers,
+                    &mut commands,
+                );
+            }
+            ServerMessage::AssetStored(info) if upload.is_waiting_for(&info.hash) => {
+                let (name, asset) = upload.0.take().unwrap();
+                ev_to_host.send(ToHost(ClientMessage::SetMap { name, asset }));
+            }
+            ServerMessage::MapCalibrated {
+                map: id,
+                calibration,
+            } => {
+                if let Some(info) = map.info.as_mut().filter(|info| info.id == *id) {
+                    info.calibration = *calibration;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for AssetDownloaded { hash, bytes } in ev_downloaded.read() {
+        if let Some(info) = map.info.as_ref().filter(|info| info.asset.hash == *hash) {
+            spawn_map(info, bytes, &mut images, &mut commands);
+        }
+    }
+}
+
+fn change_map(
+    map: &mut CurrentMap,
+    info: Option<MapInfo>,
+    cache: &mut AssetCache,
+    images: &mut Assets<Image>,
+    layers: &Query<Entity, With<MapLayer>>,
+    commands: &mut Commands,
+) {
+    for entity in layers {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    if let Some(info) = &info {
+        if let Some(bytes) = cache.fetch(&info.asset) {
+            spawn_map(info, &bytes, images, commands);
+        }
+    }
+    map.info = info;
+}
+
+fn spawn_map(info: &MapInfo, bytes: &[u8], images: &mut Assets<Image>, commands: &mut Commands) {
+    let image = Image::from_buffer(
+        bytes,
+        ImageType::Extension(info.asset.format.extension()),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+    );
+
+    match image {
+        Ok(image) => {
+            commands.spawn((
+                Name::new(info.name.clone()),
+                MapLayer,
+                SpriteBundle {
+                    sprite: Sprite {
+                        anchor: Anchor::TopLeft,
+                        ..default()
+                    },
+                    texture: images.add(image),
+                    ..default()
+                },
+            ));
+        }
+        Err(error) => error!("Could not load map {}: {error}", info.name),
+    }
+}
+
+/// Scales the map so its cells match the token grid, with the calibrated
+/// cell corner at the world origin.
+fn apply_calibration(map: Res<CurrentMap>, mut layers: Query<&mut Transform, With<MapLayer>>) {
+    let Some(info) = &map.info else {
+        return;
+    };
+
+    let calibration = info.calibration;
+    let scale = calibration.scale();
+    for mut transform in &mut layers {
+        transform.translation = Vec3::new(
+            -calibration.offset_x * scale,
+            calibration.offset_y * scale,
+            0.0,
+        );
+        transform.scale = Vec3::new(scale, scale, 1.0);
+    }
+}
+
+fn draw_grid(
+    map: Res<CurrentMap>,
+    cameras: Query<(&OrthographicProjection, &GlobalTransform)>,
+    mut gizmos: Gizmos,
+) {
+    if !map.show_grid {
+        return;
+    }
+
+    let grid = map.grid();
+    for (projection, transform) in &cameras {
+        let center = transform.translation().truncate();
+        let min = ((projection.area.min + center) / CELL_SIZE).floor() * CELL_SIZE;
+        let max = ((projection.area.max + center) / CELL_SIZE).ceil() * CELL_SIZE;
+
+        match grid.kind {
+            GridKind::Square => {
+                let mut x = min.x;
+                while x <= max.x {
+                    gizmos.line_2d(Vec2::new(x, min.y), Vec2::new(x, max.y), GRID_COLOR);
+                    x += CELL_SIZE;
+                }
+                let mut y = min.y;
+                while y <= max.y {
+                    gizmos.line_2d(Vec2::new(min.x, y), Vec2::new(max.x, y), GRID_COLOR);
+                    y += CELL_SIZE;
+                }
+            }
+            GridKind::HexFlat | GridKind::HexPointy => {
+                for cell in grid.cells_within(min.into(), max.into()) {
+                    let corners = grid.corners(cell);
+                    let outline = corners.iter().chain(corners.first());
+                    gizmos.linestrip_2d(outline.map(|corner| Vec2::from(*corner)), GRID_COLOR);
+                }
+            }
+            GridKind::Gridless => {}
+        }
+    }
+}
@@ -1,11 +1,20 @@
+use std::{env, time::Duration};
 
-
-use bevy::{app::AppExit, prelude::*};
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    tasks::{block_on, IoTaskPool, Task},
+};
+use bevy_egui::{
+    egui::{Align2, Area, Frame, Spinner, TextEdit},
+    EguiContexts,
+};
 use reqwest::StatusCode;
+use tyche_identity::JwkConfiguration;
 
 use crate::{
     auth_service,
-    firebase::{self},
+    profile::{fallback_profile, load_profile},
     user::User,
     GameState,
 };
@@ -14,6 +23,10 @@ use super::Page;
 
 const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
 const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+/// How long to wait for the browser before offering to try again.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// How often tyche-auth is asked whether the browser is done.
+const POLL_EVERY: Duration = Duration::from_secs(1);
 
 pub struct LoginPage;
 
@@ -25,6 +38,15 @@ impl Plugin for LoginPage {
             .add_systems(OnExit(GameState::Menu), delete_ui)
             .add_systems(Update, menu_action.run_if(in_state(LoginState::Main)))
             .add_systems(Update, fetch_token.run_if(in_state(LoginState::LoggingIn)))
+            .add_systems(
+                Update,
+                (
+                    login_status_ui.run_if(
+                        in_state(LoginState::LoggingIn).or_else(in_state(LoginState::Failed)),
+                    ),
+                    show_buttons.run_if(state_changed::<LoginState>()),
+                ),
+            )
             .add_systems(OnEnter(LoginState::LoggedIn), login_complete);
     }
 }
@@ -34,6 +56,8 @@ enum LoginState {
     #[default]
     Main,
     LoggingIn,
+    /// Signing in timed out or went wrong, see [`Session::error`].
+    Failed,
     LoggedIn,
 }
 
@@ -107,7 +131,120 @@ fn spawn_ui(mut menu_state: ResMut<NextState<LoginState>>, mut commands: Command
 }
 
 #[derive(Resource, Default)]
-struct Session(String);
+struct Session {
+    id: String,
+    /// Where to sign in, for when the browser did not open by itself.
+    url: String,
+    timeout: Timer,
+    poll: Timer,
+    error: String,
+    /// Asking tyche-auth for a session, which runs on the IO task pool.
+    opening: Option<Task<Result<String, String>>>,
+    /// Asking tyche-auth whether the browser is done.
+    polling: Option<Task<Poll>>,
+}
+
+/// What tyche-auth had to say about the session.
+enum Poll {
+    Waiting,
+    SignedIn(Box<User>),
+    Failed(String),
+}
+
+/// Opens a session with tyche-auth, the browser is sent off to sign it in
+/// once it is open.
+fn start_login(session: &mut Session) {
+    let opening = IoTaskPool::get().spawn(async move {
+        // In dev identity mode tyche-auth signs the session in right away, as
+        // whoever DEV_USER names.
+        let dev = JwkConfiguration::from_env().is_dev();
+        let mut request = reqwest::blocking::Client::new().get(auth_service!());
+        if let (true, Ok(name)) = (dev, env::var("DEV_USER")) {
+            request = request.query(&[("name", name)]);
+        }
+        request
+            .send()
+            .and_then(|response| response.error_for_status()?.text())
+            .map_err(|error| format!("Cannot reach the sign-in service: {error}"))
+    });
+
+    *session = Session {
+        timeout: Timer::new(LOGIN_TIMEOUT, TimerMode::Once),
+        poll: Timer::new(POLL_EVERY, TimerMode::Repeating),
+        opening: Some(opening),
+        ..default()
+    };
+}
+
+fn open_session(session: &mut Session, id: String) {
+    session.url = format!("https://tyche-vtt.web.app/?session={id}&mode=local");
+    session.id = id;
+    if !JwkConfiguration::from_env().is_dev() {
+        if let Err(error) = open::that(&session.url) {
+            warn!("Could not open a browser to sign in: {error}");
+        }
+    }
+}
+
+/// Login and Quit make way for the sign-in status while signing in.
+fn show_buttons(
+    state: Res<State<LoginState>>,
+    mut buttons: Query<&mut Visibility, With<ButtonAction>>,
+) {
+    for mut visibility in &mut buttons {
+        *visibility = match state.get() {
+            LoginState::Main => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+fn login_status_ui(
+    mut contexts: EguiContexts,
+    state: Res<State<LoginState>>,
+    mut session: ResMut<Session>,
+    mut menu_state: ResMut<NextState<LoginState>>,
+) {
+    Area::new("login")
+        .anchor(Align2::CENTER_CENTER, [0.0, 60.0])
+        .show(contexts.ctx_mut(), |ui| {
+            Frame::popup(ui.style()).show(ui, |ui| {
+                ui.set_max_width(400.0);
+                if *state.get() == LoginState::LoggingIn && session.url.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.add(Spinner::new());
+                        ui.label("Contacting the sign-in service…");
+                    });
+                } else if *state.get() == LoginState::LoggingIn {
+                    ui.horizontal(|ui| {
+                        ui.add(Spinner::new());
+                        ui.label("Waiting for browser sign-in…");
+                    });
+                    ui.label("If no browser opened, sign in at:");
+                    ui.horizontal(|ui| {
+                        let mut url = session.url.as_str();
+                        ui.add(TextEdit::singleline(&mut url).desired_width(300.0));
+                        if ui.button("Copy").clicked() {
+                            ui.output_mut(|output| output.copied_text = session.url.clone());
+                        }
+                    });
+                } else {
+                    ui.colored_label(ui.visuals().error_fg_color, &session.error);
+                }
+
+                ui.horizontal(|ui| {
+                    if *state.get() == LoginState::Failed && ui.button("Retry").clicked() {
+                        start_login(&mut session);
+                        menu_state.set(LoginState::LoggingIn);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        *session = Session::default();
+                        menu_state.set(LoginState::Main);
+                    }
+                });
+            });
+        });
+}
 
 fn spawn_button(parent: &mut ChildBuilder, menu_action: ButtonAction, text: impl Into<String>) {
     let button_text_style = TextStyle {
@@ -140,16 +277,7 @@ fn menu_action(
         match menu_button_action {
             ButtonAction::Quit => app_exit_events.send(AppExit),
             ButtonAction::Login => {
-                let content = reqwest::blocking::get(auth_service!())
-                    .unwrap()
-                    .text()
-                    .unwrap();
-
-                session.0 = content;
-                let _ = open::that(format!(
-                    "https://tyche-vtt.web.app/?session={}&mode=local",
-                    session.0
-                ));
+                start_login(&mut session);
                 menu_state.set(LoginState::LoggingIn);
             }
         }
@@ -157,19 +285,94 @@ fn menu_action(
 }
 
 fn fetch_token(
-    session: ResMut<Session>,
+    time: Res<Time>,
+    mut session: ResMut<Session>,
     mut user: ResMut<User>,
     mut menu_state: ResMut<NextState<LoginState>>,
 ) {
-    let request = reqwest::blocking::get(format!("{}/{}", auth_service!(), session.0)).unwrap();
-
-    if request.status() == StatusCode::OK {
-        let content = request.text().unwrap();
-        println!("{}", content);
-        let fire_user = firebase::verify_id_token_with_project_id(&content).unwrap();
-        menu_state.set(LoginState::LoggedIn);
-        user.name = fire_user.name.unwrap();
-        user.token = content;
+    if let Some(opening) = session.opening.take() {
+        if !opening.is_finished() {
+            session.opening = Some(opening);
+            return;
+        }
+        match block_on(opening) {
+            Ok(id) => open_session(&mut session, id),
+            Err(error) => {
+                session.error = error;
+                menu_state.set(LoginState::Failed);
+                return;
+            }
+        }
+    }
+
+    if session.timeout.tick(time.delta()).finished() {
+        session.error = "Signing in took too long, try again".to_owned();
+        menu_state.set(LoginState::Failed);
+        return;
+    }
+
+    if let Some(polling) = session.polling.take() {
+        if !polling.is_finished() {
+            session.polling = Some(polling);
+            return;
+        }
+        match block_on(polling) {
+            Poll::Waiting => {}
+            Poll::SignedIn(signed_in) => {
+                *user = *signed_in;
+                menu_state.set(LoginState::LoggedIn);
+                return;
+            }
+            Poll::Failed(error) => {
+                session.error = error;
+                menu_state.set(LoginState::Failed);
+                return;
+            }
+        }
+    }
+
+    if session.poll.tick(time.delta()).just_finished() {
+        let url = format!("{}/{}", auth_service!(), session.id);
+        session.polling = Some(IoTaskPool::get().spawn(async move { poll_token(&url) }));
+    }
+}
+
+/// Asks tyche-auth for the token of the session, and signs in with it once
+/// the browser is done.
+fn poll_token(url: &str) -> Poll {
+    // tyche-auth answers with something else until the browser is done, and
+    // a request that fails is simply tried again on the next poll.
+    let request = match reqwest::blocking::get(url) {
+        Ok(request) => request,
+        Err(error) => {
+            warn!("Could not ask for the sign-in token: {error}");
+            return Poll::Waiting;
+        }
+    };
+    if request.status() != StatusCode::OK {
+        return Poll::Waiting;
+    }
+    let Ok(content) = request.text() else {
+        return Poll::Waiting;
+    };
+
+    match tyche_identity::verify_id_token(&content) {
+        Ok(fire_user) => {
+            let profile = load_profile(&content).unwrap_or_else(|error| {
+                warn!("Could not load the profile, going by the sign-in name: {error}");
+                fallback_profile(&fire_user)
+            });
+
+            Poll::SignedIn(Box::new(User {
+                user_id: fire_user.user_id,
+                name: profile.display_name,
+                color: profile.color,
+                provider: fire_user.firebase.sign_in_provider,
+                token: content,
+                ..default()
+            }))
+        }
+        Err(error) => Poll::Failed(format!("Could not verify the sign-in: {error}")),
     }
 }
 
@@ -0,0 +1,255 @@
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, SystemTime},
+};
+
+use bevy::prelude::*;
+use bevy_renet::{
+    renet::{
+        transport::{ClientAuthentication, NetcodeClientTransport},
+        ConnectionConfig, DefaultChannel, RenetClient,
+    },
+    transport::NetcodeClientPlugin,
+    RenetClientPlugin,
+};
+use tyche_protocol::{ClientMessage, ServerMessage, PROTOCOL_ID};
+
+use crate::{host_service, user::User, GameState};
+
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+const MAX_RETRY: Duration = Duration::from_secs(30);
+
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((RenetClientPlugin, NetcodeClientPlugin))
+            .add_event::<HostMessage>()
+            .add_event::<ToHost>()
+            .insert_resource(CurrentRoom::default())
+            .insert_resource(Replication::default())
+            .add_systems(OnEnter(GameState::Main), start_connection)
+            .add_systems(
+                Update,
+                (receive_messages, send_messages).run_if(resource_exists::<RenetClient>()),
+            )
+            .add_systems(
+                Update,
+                watch_connection.run_if(resource_exists::<Connection>()),
+            )
+            .add_systems(Update, update_current_room);
+    }
+}
+
+/// A message received from tyche-host.
+#[derive(Event)]
+pub struct HostMessage(pub ServerMessage);
+
+/// A message to be sent to tyche-host.
+#[derive(Event)]
+pub struct ToHost(pub ClientMessage);
+
+/// The room this client has joined on the host.
+#[derive(Debug, Default, Resource)]
+pub struct CurrentRoom {
+    pub name: String,
+    /// The id the host knows this client by.
+    pub client_id: u64,
+    pub is_gm: bool,
+}
+
+/// Where this client is in the stream of deltas the host sends it.
+#[derive(Debug, Default, Resource)]
+pub struct Replication {
+    seq: u64,
+    /// The version of the room the client is caught up with.
+    pub version: u64,
+    resyncing: bool,
+}
+
+/// The state of the connection to the host, which is retried with a growing
+/// delay whenever it drops.
+#[derive(Debug, Resource)]
+pub enum Connection {
+    Connecting {
+        attempt: u32,
+    },
+    Connected,
+    /// Waiting before the next attempt.
+    Retrying {
+        attempt: u32,
+        timer: Timer,
+    },
+}
+
+impl Connection {
+    pub fn is_connected(&self) -> bool {
+        matches!(self, Connection::Connected)
+    }
+}
+
+fn start_connection(mut commands: Commands) {
+    connect(&mut commands);
+    commands.insert_resource(Connection::Connecting { attempt: 0 });
+}
+
+fn connect(commands: &mut Commands) {
+    let server_addr: SocketAddr = host_service!().parse().unwrap();
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+
+    let authentication = ClientAuthentication::Unsecure {
+        client_id: current_time.as_millis() as u64,
+        protocol_id: PROTOCOL_ID,
+        server_addr,
+        user_data: None,
+    };
+    let transport = NetcodeClientTransport::new(current_time, authentication, socket).unwrap();
+
+    commands.insert_resource(RenetClient::new(ConnectionConfig::default()));
+    commands.insert_resource(transport);
+}
+
+fn backoff(attempt: u32) -> Duration {
+    FIRST_RETRY
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_RETRY)
+}
+
+/// Notices when the host drops out, reconnects and then joins the room this
+/// client was in again, which gets it a fresh snapshot of the room.
+fn watch_connection(
+    time: Res<Time>,
+    mut connection: ResMut<Connection>,
+    client: Option<Res<RenetClient>>,
+    room: Res<CurrentRoom>,
+    user: Res<User>,
+    mut commands: Commands,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    let disconnected = client
+        .as_ref()
+        .is_some_and(|client| client.is_disconnected());
+    if let Connection::Retrying { timer, .. } = &mut *connection {
+        timer.tick(time.delta());
+    }
+
+    match &mut *connection {
+        Connection::Connected if disconnected => {
+            warn!("Lost the connection to the host");
+            commands.remove_resource::<RenetClient>();
+            commands.remove_resource::<NetcodeClientTransport>();
+            let timer = Timer::new(backoff(0), TimerMode::Once);
+            *connection = Connection::Retrying { attempt: 0, timer };
+        }
+        Connection::Connecting { attempt } if disconnected => {
+            let attempt = *attempt + 1;
+            let delay = backoff(attempt);
+            warn!("Could not reach the host, retrying in {delay:?}");
+            commands.remove_resource::<RenetClient>();
+            commands.remove_resource::<NetcodeClientTransport>();
+            let timer = Timer::new(delay, TimerMode::Once);
+            *connection = Connection::Retrying { attempt, timer };
+        }
+        Connection::Connecting { .. } if client.is_some_and(|client| client.is_connected()) => {
+            info!("Connected to the host");
+            *connection = Connection::Connected;
+            if !room.name.is_empty() {
+                ev_to_host.send(ToHost(ClientMessage::JoinRoom {
+                    room: room.name.clone(),
+                    token: user.token.clone(),
+                    character: user.character.as_ref().map(|character| character.info()),
+                }));
+            }
+        }
+        Connection::Retrying { attempt, timer } if timer.finished() => {
+            connect(&mut commands);
+            *connection = Connection::Connecting { attempt: *attempt };
+        }
+        _ => {}
+    }
+}
+
+/// Unpacks the deltas from the host, asking for a snapshot instead when one
+/// went missing.
+fn receive_messages(
+    mut client: ResMut<RenetClient>,
+    mut replication: ResMut<Replication>,
+    mut ev_host: EventWriter<HostMessage>,
+) {
+    while let Some(message) = client.receive_message(DefaultChannel::ReliableOrdered) {
+        let Some(message) = tyche_protocol::decode(&message) else {
+            warn!("Received a malformed message from the host");
+            continue;
+        };
+
+        match message {
+            ServerMessage::Joined { seq, version, .. } => {
+                *replication = Replication {
+                    seq,
+                    version,
+                    resyncing: false,
+                };
+                ev_host.send(HostMessage(message));
+            }
+            ServerMessage::Delta {
+                seq,
+                version,
+                changes,
+            } => {
+                if replication.resyncing {
+                    continue;
+                }
+                if seq != replication.seq + 1 {
+                    warn!(
+                        "Missed deltas {} to {}, asking for a snapshot",
+                        replication.seq + 1,
+                        seq.saturating_sub(1)
+                    );
+                    replication.resyncing = true;
+                    client.send_message(
+                        DefaultChannel::ReliableOrdered,
+                        tyche_protocol::encode(&ClientMessage::RequestSnapshot),
+                    );
+                    continue;
+                }
+
+                replication.seq = seq;
+                replication.version = version;
+                for change in changes {
+                    ev_host.send(HostMessage(change));
+                }
+            }
+            message => ev_host.send(HostMessage(message)),
+        }
+    }
+}
+
+fn send_messages(mut client: ResMut<RenetClient>, mut ev_to_host: EventReader<ToHost>) {
+    for ToHost(message) in ev_to_host.read() {
+        client.send_message(
+            DefaultChannel::ReliableOrdered,
+            tyche_protocol::encode(message),
+        );
+    }
+}
+
+fn update_current_room(mut ev_host: EventReader<HostMessage>, mut room: ResMut<CurrentRoom>) {
+    for HostMessage(message) in ev_host.read() {
+        if let ServerMessage::Joined {
+            room: name,
+            client_id,
+            is_gm,
+            ..
+        } = message
+        {
+            *room = CurrentRoom {
+                name: name.clone(),
+                client_id: *client_id,
+                is_gm: *is_gm,
+            };
+        }
+    }
+}
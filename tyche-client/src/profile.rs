@@ -0,0 +1,186 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bevy::{
+    prelude::*,
+    render::texture::{CompressedImageFormats, ImageSampler, ImageType},
+};
+use bevy_egui::{egui::TextureId, EguiContexts};
+use reqwest::{header::CONTENT_TYPE, StatusCode};
+use tyche_identity::FirebaseUser;
+use tyche_protocol::{PlayerInfo, ServerMessage};
+
+use crate::{
+    character::service_reply,
+    character_service,
+    network::HostMessage,
+    user::{Profile, User},
+};
+
+/// Used when tyche-character cannot be reached.
+const DEFAULT_COLOR: [u8; 3] = [38, 89, 204];
+
+pub struct ProfilePlugin;
+
+impl Plugin for ProfilePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Players::default())
+            .add_systems(Update, (update_players, load_avatars).chain());
+    }
+}
+
+/// The players in the current room.
+#[derive(Default, Resource)]
+pub struct Players {
+    pub players: BTreeMap<u64, PlayerInfo>,
+    /// The colors of everyone who was in the room since joining it, by user
+    /// id, so the tokens of players who left keep theirs.
+    colors: HashMap<String, [u8; 3]>,
+    /// Avatars by user id, `None` for players without one.
+    avatars: HashMap<String, Option<TextureId>>,
+}
+
+impl Players {
+    pub fn color_of(&self, user_id: &str) -> Option<[u8; 3]> {
+        self.colors.get(user_id).copied()
+    }
+
+    pub fn avatar_of(&self, user_id: &str) -> Option<TextureId> {
+        self.avatars.get(user_id).copied().flatten()
+    }
+
+    fn insert(&mut self, player: PlayerInfo) {
+        self.colors.insert(player.user_id.clone(), player.color);
+        self.players.insert(player.client_id, player);
+    }
+}
+
+fn update_players(mut ev_host: EventReader<HostMessage>, mut players: ResMut<Players>) {
+    for HostMessage(message) in ev_host.read() {
+        match message {
+            ServerMessage::Joined {
+                players: joined, ..
+            } => {
+                players.players.clear();
+                for player in joined {
+                    players.insert(player.clone());
+                }
+            }
+            ServerMessage::PlayerChanged(player) => players.insert(player.clone()),
+            ServerMessage::PlayerLeft(client_id) => {
+                players.players.remove(client_id);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Fetches the avatar of every player once, the first time they show up.
+fn load_avatars(
+    user: Res<User>,
+    mut players: ResMut<Players>,
+    mut images: ResMut<Assets<Image>>,
+    mut contexts: EguiContexts,
+) {
+    if !players.is_changed() {
+        return;
+    }
+
+    let missing: Vec<String> = players
+        .players
+        .values()
+        .map(|player| player.user_id.clone())
+        .filter(|user_id| !players.avatars.contains_key(user_id))
+        .collect();
+    for user_id in missing {
+        let avatar = match fetch_avatar(&user.token, &user_id) {
+            Ok(avatar) => avatar.map(|image| contexts.add_image(images.add(image))),
+            Err(error) => {
+                warn!("Could not load the avatar of {user_id}: {error}");
+                None
+            }
+        };
+        players.avatars.insert(user_id, avatar);
+    }
+}
+
+/// The profile of the player, made up from their sign-in until they save one.
+pub fn load_profile(token: &str) -> reqwest::Result<Profile> {
+    reqwest::blocking::Client::new()
+        .get(format!("{}/profile", character_service!()))
+        .bearer_auth(token)
+        .send()?
+        .error_for_status()?
+        .json()
+}
+
+/// What the player goes by when tyche-character cannot be reached.
+pub fn fallback_profile(user: &FirebaseUser) -> Profile {
+    let email_name = user
+        .email
+        .as_deref()
+        .and_then(|email| email.split('@').next());
+    let name = user
+        .name
+        .as_deref()
+        .or(email_name)
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or("Guest");
+
+    Profile {
+        display_name: name.to_owned(),
+        color: DEFAULT_COLOR,
+        avatar: None,
+    }
+}
+
+pub fn save_profile(token: &str, profile: &Profile) -> Result<Profile, String> {
+    let response = reqwest::blocking::Client::new()
+        .put(format!("{}/profile", character_service!()))
+        .bearer_auth(token)
+        .json(profile)
+        .send()
+        .map_err(|error| error.to_string())?;
+    service_reply(response)
+}
+
+pub fn upload_avatar(token: &str, image: Vec<u8>) -> Result<Profile, String> {
+    let response = reqwest::blocking::Client::new()
+        .put(format!("{}/profile/avatar", character_service!()))
+        .bearer_auth(token)
+        .body(image)
+        .send()
+        .map_err(|error| error.to_string())?;
+    service_reply(response)
+}
+
+fn fetch_avatar(token: &str, user_id: &str) -> Result<Option<Image>, String> {
+    let response = reqwest::blocking::Client::new()
+        .get(format!("{}/profile/{user_id}/avatar", character_service!()))
+        .bearer_auth(token)
+        .send()
+        .map_err(|error| error.to_string())?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    let response = response
+        .error_for_status()
+        .map_err(|error| error.to_string())?;
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("image/png")
+        .to_owned();
+    let bytes = response.bytes().map_err(|error| error.to_string())?;
+
+    Image::from_buffer(
+        &bytes,
+        ImageType::MimeType(&content_type),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+    )
+    .map(Some)
+    .map_err(|error| error.to_string())
+}
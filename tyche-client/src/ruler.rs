@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Id},
+    EguiContexts,
+};
+use tyche_protocol::grid::Grid;
+
+use crate::{
+    camera::Cursor,
+    map::{CurrentMap, MapTool},
+};
+
+const RULER_COLOR: Color = Color::rgb(0.95, 0.95, 0.95);
+
+pub struct RulerPlugin;
+
+impl Plugin for RulerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, measure);
+    }
+}
+
+/// Draws a line between two points, labelled with the distance in feet.
+pub fn draw_ruler(
+    gizmos: &mut Gizmos,
+    contexts: &mut EguiContexts,
+    grid: &Grid,
+    from: Vec2,
+    to: Vec2,
+) {
+    let (from, to) = (
+        Vec2::from(grid.snap(from.into())),
+        Vec2::from(grid.snap(to.into())),
+    );
+    let feet = grid.distance_feet(from.into(), to.into());
+
+    gizmos.line_2d(from, to, RULER_COLOR);
+    gizmos.circle_2d(to, 4.0, RULER_COLOR);
+    egui::show_tooltip_at_pointer(contexts.ctx_mut(), Id::new("ruler"), |ui| {
+        ui.label(format!("{feet} ft"));
+    });
+}
+
+fn measure(
+    mut contexts: EguiContexts,
+    buttons: Res<Input<MouseButton>>,
+    cursor: Res<Cursor>,
+    tool: Res<MapTool>,
+    map: Res<CurrentMap>,
+    mut start: Local<Option<Vec2>>,
+    mut gizmos: Gizmos,
+) {
+    let Some(cursor) = cursor.0 else {
+        return;
+    };
+    if *tool != MapTool::Measure || !buttons.pressed(MouseButton::Left) {
+        *start = None;
+        return;
+    }
+
+    let from = *start.get_or_insert(cursor);
+    draw_ruler(&mut gizmos, &mut contexts, &map.grid(), from, cursor);
+}
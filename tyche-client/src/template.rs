@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use tyche_protocol::{
+    character::Ability,
+    grid::{Grid, GridKind},
+    map::CELL_SIZE,
+    template::{Template, TemplateId, TemplateShape},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    camera::Cursor,
+    map::{cell_mesh, CurrentMap, MapTool},
+    network::{HostMessage, ToHost},
+    token::Token,
+};
+
+const TEMPLATE_COLOR: Color = Color::rgb(0.95, 0.55, 0.1);
+const CELL_COLOR: Color = Color::rgba(0.95, 0.55, 0.1, 0.3);
+const TEMPLATE_Z: f32 = 3.0;
+
+pub struct TemplatePlugin;
+
+impl Plugin for TemplatePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Templates::default())
+            .insert_resource(TemplateSettings::default())
+            .add_systems(
+                Update,
+                (
+                    handle_template_messages,
+                    place_template,
+                    highlight_cells,
+                    draw_templates,
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct Templates(pub BTreeMap<TemplateId, Template>);
+
+#[derive(Debug, Resource)]
+pub struct TemplateSettings {
+    pub shape: TemplateShape,
+    pub size_feet: u32,
+    pub selected: Option<TemplateId>,
+    pub ability: Ability,
+    pub dc: i64,
+}
+
+impl Default for TemplateSettings {
+    fn default() -> Self {
+        Self {
+            shape: TemplateShape::Sphere,
+            size_feet: 20,
+            selected: None,
+            ability: Ability::Dexterity,
+            dc: 13,
+        }
+    }
+}
+
+#[derive(Component)]
+struct TemplateCell;
+
+/// The tokens inside a template, by name.
+pub fn affected_tokens<'a>(
+    template: &Template,
+    tokens: impl IntoIterator<Item = (&'a Token, &'a Name, &'a GlobalTransform)>,
+) -> Vec<String> {
+    tokens
+        .into_iter()
+        .filter(|(_, _, transform)| {
+            let position = transform.translation().truncate();
+            template.contains(position.into())
+        })
+        .map(|(_, name, _)| name.to_string())
+        .collect()
+}
+
+fn handle_template_messages(
+    mut ev_host: EventReader<HostMessage>,
+    mut templates: ResMut<Templates>,
+    mut settings: ResMut<TemplateSettings>,
+) {
+    for HostMessage(message) in ev_host.read() {
+        match message {
+            ServerMessage::Joined {
+                templates: joined, ..
+            } => {
+                templates.0 = joined.iter().map(|t| (t.id, t.clone())).collect();
+                settings.selected = None;
+            }
+            ServerMessage::TemplateChanged(template) => {
+                templates.0.insert(template.id, template.clone());
+            }
+            ServerMessage::TemplateRemoved(id) => {
+                templates.0.remove(id);
+                if settings.selected == Some(*id) {
+                    settings.selected = None;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Templates start from a corner on squares and from the middle of a hex.
+fn snap_origin(grid: &Grid, point: Vec2) -> Vec2 {
+    match grid.kind {
+        GridKind::Square => grid.snap_to_corner(point.into()).into(),
+        GridKind::HexFlat | GridKind::HexPointy => grid.snap(point.into()).into(),
+        GridKind::Gridless => point,
+    }
+}
+
+/// Click to place a template on the grid, drag to point it somewhere.
+fn place_template(
+    buttons: Res<Input<MouseButton>>,
+    cursor: Res<Cursor>,
+    tool: Res<MapTool>,
+    map: Res<CurrentMap>,
+    settings: Res<TemplateSettings>,
+    mut origin: Local<Option<Vec2>>,
+    mut gizmos: Gizmos,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    let Some(cursor) = cursor.0 else {
+        return;
+    };
+    if *tool != MapTool::Template {
+        *origin = None;
+        return;
+    }
+    let grid = map.grid();
+
+    if buttons.just_pressed(MouseButton::Left) {
+        *origin = Some(snap_origin(&grid, cursor));
+    }
+    let Some(start) = *origin else {
+        return;
+    };
+
+    let direction = cursor - start;
+    let template = Template {
+        id: 0,
+        owner: String::new(),
+        shape: settings.shape,
+        size_feet: settings.size_feet,
+        origin: start.into(),
+        angle: direction.y.atan2(direction.x),
+    };
+    draw_template(&mut gizmos, &template);
+    for cell in template.cells(&grid) {
+        let center = Vec2::from(grid.center(cell));
+        let corners = grid.corners(cell).into_iter().map(|corner| {
+            let corner = Vec2::from(corner);
+            center + (corner - center) * 0.8
+        });
+        let first = corners.clone().next();
+        gizmos.linestrip_2d(corners.chain(first), CELL_COLOR);
+    }
+    if buttons.pressed(MouseButton::Left) {
+        return;
+    }
+
+    ev_to_host.send(ToHost(ClientMessage::PlaceTemplate {
+        id: None,
+        shape: template.shape,
+        size_feet: template.size_feet,
+        origin: template.origin,
+        angle: template.angle,
+    }));
+    *origin = None;
+}
+
+fn draw_template(gizmos: &mut Gizmos, template: &Template) {
+    let origin = Vec2::from(template.origin);
+    let size = template.size();
+    let direction = Vec2::from_angle(template.angle);
+    let side = direction.perp();
+
+    match template.shape {
+        TemplateShape::Sphere => {
+            gizmos.circle_2d(origin, size, TEMPLATE_COLOR);
+        }
+        TemplateShape::Cube => {
+            let center = origin + direction * size / 2.0;
+            gizmos.rect_2d(center, template.angle, Vec2::splat(size), TEMPLATE_COLOR);
+        }
+        TemplateShape::Cone => {
+            let end = origin + direction * size;
+            let corners = [
+                origin,
+                end + side * size / 2.0,
+                end - side * size / 2.0,
+                origin,
+            ];
+            gizmos.linestrip_2d(corners, TEMPLATE_COLOR);
+        }
+        TemplateShape::Line => {
+            let center = origin + direction * size / 2.0;
+            let extent = Vec2::new(size, CELL_SIZE);
+            gizmos.rect_2d(center, template.angle, extent, TEMPLATE_COLOR);
+        }
+    }
+}
+
+fn draw_templates(templates: Res<Templates>, mut gizmos: Gizmos) {
+    for template in templates.0.values() {
+        draw_template(&mut gizmos, template);
+    }
+}
+
+/// Fills in the grid cells covered by templates, whenever those or the grid
+/// change.
+fn highlight_cells(
+    templates: Res<Templates>,
+    map: Res<CurrentMap>,
+    cells: Query<Entity, With<TemplateCell>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut commands: Commands,
+) {
+    if !templates.is_changed() && !map.is_changed() {
+        return;
+    }
+
+    for entity in &cells {
+        commands.entity(entity).despawn();
+    }
+
+    let grid = map.grid();
+    let (mesh, rotation) = cell_mesh(&grid);
+    let mesh = meshes.add(mesh);
+    let material = materials.add(CELL_COLOR.into());
+    for cell in templates
+        .0
+        .values()
+        .flat_map(|template| template.cells(&grid))
+    {
+        let center = Vec2::from(grid.center(cell));
+        commands.spawn((
+            TemplateCell,
+            MaterialMesh2dBundle {
+                mesh: mesh.clone().into(),
+                material: material.clone(),
+                transform: Transform::from_translation(center.extend(TEMPLATE_Z))
+                    .with_rotation(rotation),
+                ..default()
+            },
+        ));
+    }
+}
@@ -0,0 +1,620 @@
+use std::collections::HashMap;
+
+use bevy::{
+    prelude::*,
+    render::texture::{CompressedImageFormats, ImageSampler, ImageType},
+    sprite::{Anchor, MaterialMesh2dBundle, Mesh2dHandle},
+};
+use bevy_egui::EguiContexts;
+use tyche_protocol::{
+    asset::{AssetHash, AssetInfo},
+    map::CELL_SIZE,
+    token::{CharacterId, HealthView, HealthVisibility, Status, Token as TokenState, TokenId},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    asset_cache::{AssetCache, AssetDownloaded},
+    camera::Cursor,
+    imgui::InitiativeTracker,
+    map::{CurrentMap, MapTool},
+    network::{CurrentRoom, HostMessage, ToHost},
+    profile::Players,
+    ruler::draw_ruler,
+    user::User,
+};
+
+pub const TOKEN_SIZE: f32 = CELL_SIZE;
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+/// For tokens of the GM and of players the client has not seen yet.
+const OTHER_TOKEN: Color = Color::rgb(0.8, 0.15, 0.15);
+/// How much of the owner's color rings the art of a token.
+const FRAME_WIDTH: f32 = 4.0;
+/// Shown inside the frame of tokens without art.
+const NO_ART: Color = Color::rgba(0.1, 0.1, 0.1, 0.6);
+const ACTIVE_TOKEN: Color = Color::GOLD;
+const SELECTED_TOKEN: Color = Color::WHITE;
+const BAR_HEIGHT: f32 = 6.0;
+const BAR_BACKGROUND: Color = Color::rgb(0.1, 0.1, 0.1);
+const HEALTHY: Color = Color::rgb(0.2, 0.7, 0.2);
+const BLOODIED: Color = Color::rgb(0.75, 0.1, 0.1);
+const TEMP_HP: Color = Color::rgb(0.3, 0.6, 0.95);
+const MARKER_SIZE: f32 = 16.0;
+const MARKER_COLOR: Color = Color::rgba(0.25, 0.1, 0.35, 0.9);
+/// How long, in seconds, a token takes to glide to where someone else moved it.
+const GLIDE_TIME: f32 = 0.2;
+
+pub struct TokenPlugin;
+
+impl Plugin for TokenPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SelectedToken::default())
+            .insert_resource(Prediction::default())
+            .add_systems(Startup, setup_token_art)
+            .add_systems(
+                Update,
+                (
+                    handle_token_messages,
+                    color_tokens.after(handle_token_messages),
+                    paint_tokens.after(handle_token_messages),
+                    drag_tokens,
+                    glide_tokens,
+                    render_status,
+                    highlight_active_token,
+                ),
+            );
+    }
+}
+
+#[derive(Component, Clone)]
+pub struct Token {
+    pub id: TokenId,
+    pub owner: Option<String>,
+    pub character: Option<CharacterId>,
+    pub health: HealthView,
+    pub health_visibility: HealthVisibility,
+    pub conditions: Vec<Status>,
+    pub image: Option<AssetInfo>,
+}
+
+impl Token {
+    fn update(&mut self, state: &TokenState) {
+        self.owner = state.owner.clone();
+        self.character = state.character;
+        self.health = state.health;
+        self.health_visibility = state.health_visibility;
+        self.conditions = state.conditions.clone();
+    }
+}
+
+/// The token last picked up, shown in the token window.
+#[derive(Default, Resource)]
+pub struct SelectedToken(pub Option<TokenId>);
+
+/// Moves this client made on its own and shows already, but the host has not
+/// answered yet. Only the answer to the latest move of a token settles where
+/// it is, anything else that arrives before then is out of date.
+#[derive(Default, Resource)]
+struct Prediction {
+    next_seq: u32,
+    pending: HashMap<TokenId, u32>,
+}
+
+impl Prediction {
+    fn predict(&mut self, token: TokenId) -> u32 {
+        self.next_seq = self.next_seq.wrapping_add(1);
+        self.pending.insert(token, self.next_seq);
+        self.next_seq
+    }
+}
+
+/// Eases a token from where it was to where it was moved.
+#[derive(Component)]
+struct Glide {
+    from: Vec2,
+    to: Vec2,
+    elapsed: f32,
+}
+
+impl Glide {
+    fn at(position: Vec2) -> Self {
+        Self {
+            from: position,
+            to: position,
+            elapsed: GLIDE_TIME,
+        }
+    }
+
+    fn start(&mut self, from: Vec2, to: Vec2) {
+        *self = Self {
+            from,
+            to,
+            elapsed: 0.0,
+        };
+    }
+}
+
+/// A token the player is dragging, it follows the cursor rather than the host.
+#[derive(Component)]
+struct Held;
+
+/// The inside of a token's frame, showing its art.
+#[derive(Component)]
+struct TokenFace;
+
+/// What all tokens are drawn with, and the art loaded so far by its hash.
+#[derive(Resource)]
+struct TokenArt {
+    frame: Mesh2dHandle,
+    face: Mesh2dHandle,
+    no_art: Handle<ColorMaterial>,
+    loaded: HashMap<AssetHash, Handle<ColorMaterial>>,
+}
+
+/// The health bar and condition markers of a token, rebuilt when they change.
+#[derive(Component)]
+struct StatusOverlay;
+
+#[derive(Bundle)]
+struct TokenBundle {
+    name: Name,
+    token: Token,
+    glide: Glide,
+    frame: MaterialMesh2dBundle<ColorMaterial>,
+}
+
+fn setup_token_art(
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut commands: Commands,
+) {
+    let radius = TOKEN_SIZE / 2.0;
+    commands.insert_resource(TokenArt {
+        frame: meshes.add(shape::Circle::new(radius).into()).into(),
+        face: meshes
+            .add(shape::Circle::new(radius - FRAME_WIDTH).into())
+            .into(),
+        no_art: materials.add(NO_ART.into()),
+        loaded: HashMap::new(),
+    });
+}
+
+/// Tokens are colored by [`color_tokens`] and their art is put in by
+/// [`paint_tokens`] once spawned.
+fn spawn_token(
+    commands: &mut Commands,
+    art: &TokenArt,
+    materials: &mut Assets<ColorMaterial>,
+    state: &TokenState,
+) {
+    commands
+        .spawn(TokenBundle {
+            name: Name::new(state.name.clone()),
+            token: Token {
+                id: state.id,
+                owner: state.owner.clone(),
+                character: state.character,
+                health: state.health,
+                health_visibility: state.health_visibility,
+                conditions: state.conditions.clone(),
+                image: state.image,
+            },
+            glide: Glide::at(Vec2::new(state.x, state.y)),
+            frame: MaterialMesh2dBundle {
+                mesh: art.frame.clone(),
+                // Every token has its own, as they are colored by owner.
+                material: materials.add(OTHER_TOKEN.into()),
+                transform: Transform::from_xyz(state.x, state.y, 1.0),
+                ..default()
+            },
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                TokenFace,
+                MaterialMesh2dBundle {
+                    mesh: art.face.clone(),
+                    material: art.no_art.clone(),
+                    transform: Transform::from_xyz(0.0, 0.0, 0.1),
+                    ..default()
+                },
+            ));
+            parent.spawn(Text2dBundle {
+                text: Text::from_section(
+                    &state.name,
+                    TextStyle {
+                        font_size: 20.0,
+                        color: TEXT_COLOR,
+                        ..default()
+                    },
+                ),
+                transform: Transform::from_xyz(0.0, -TOKEN_SIZE / 2.0 - 12.0, 1.0),
+                ..default()
+            });
+        });
+}
+
+fn handle_token_messages(
+    mut ev_host: EventReader<HostMessage>,
+    mut tokens: Query<(Entity, &mut Token, &Name, &Transform, &mut Glide)>,
+    mut prediction: ResMut<Prediction>,
+    room: Res<CurrentRoom>,
+    art: Res<TokenArt>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut commands: Commands,
+) {
+    for HostMessage(message) in ev_host.read() {
+        match message {
+            ServerMessage::Joined { tokens: states, .. } => {
+                prediction.pending.clear();
+                for (entity, ..) in &tokens {
+                    commands.entity(entity).despawn_recursive();
+                }
+                for state in states {
+                    spawn_token(&mut commands, &art, &mut materials, state);
+                }
+            }
+            ServerMessage::TokenSpawned(state)
+                if !tokens.iter().any(|(_, token, ..)| token.id == state.id) =>
+            {
+                spawn_token(&mut commands, &art, &mut materials, state);
+            }
+            ServerMessage::TokenRemoved(id) => {
+                for (entity, token, ..) in &tokens {
+                    if token.id == *id {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+            }
+            ServerMessage::TokenMoved {
+                token: id,
+                x,
+                y,
+                client_id,
+                seq,
+            } => {
+                if let Some(pending) = prediction.pending.get(id) {
+                    if *client_id != room.client_id || seq != pending {
+                        continue;
+                    }
+                    prediction.pending.remove(id);
+                }
+
+                // A move that was predicted right glides nowhere.
+                for (_, token, _, transform, mut glide) in &mut tokens {
+                    if token.id == *id {
+                        glide.start(transform.translation.truncate(), Vec2::new(*x, *y));
+                    }
+                }
+            }
+            ServerMessage::TokenChanged(state) => {
+                for (entity, mut token, name, ..) in &mut tokens {
+                    if token.id != state.id {
+                        continue;
+                    }
+                    // The label and art are baked into the token, tokens
+                    // with new ones are rebuilt.
+                    match name.as_str() == state.name && token.image == state.image {
+                        true => token.update(state),
+                        false => {
+                            commands.entity(entity).despawn_recursive();
+                            spawn_token(&mut commands, &art, &mut materials, state);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Tokens take the color their owner picked in their profile.
+fn color_tokens(
+    players: Res<Players>,
+    user: Res<User>,
+    tokens: Query<(Ref<Token>, &Handle<ColorMaterial>)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (token, material) in &tokens {
+        if !token.is_changed() && !players.is_changed() && !user.is_changed() {
+            continue;
+        }
+
+        let color = match token.owner.as_deref() {
+            Some(owner) if owner == user.user_id => Some(user.color),
+            Some(owner) => players.color_of(owner),
+            None => None,
+        };
+        if let Some(material) = materials.get_mut(material) {
+            material.color = color.map_or(OTHER_TOKEN, |[r, g, b]| Color::rgb_u8(r, g, b));
+        }
+    }
+}
+
+/// Puts the art of tokens inside their frame, downloading it from the host
+/// first if it is not cached yet.
+fn paint_tokens(
+    mut ev_downloaded: EventReader<AssetDownloaded>,
+    mut cache: ResMut<AssetCache>,
+    mut art: ResMut<TokenArt>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    tokens: Query<&Token>,
+    mut faces: Query<(Ref<TokenFace>, &Parent, &mut Handle<ColorMaterial>)>,
+) {
+    let mut downloaded = false;
+    for AssetDownloaded { hash, bytes } in ev_downloaded.read() {
+        let Some(info) = tokens
+            .iter()
+            .find_map(|t| t.image.filter(|i| i.hash == *hash))
+        else {
+            continue;
+        };
+        let material = load_art(&info, bytes, &art, &mut images, &mut materials);
+        art.loaded.insert(info.hash, material);
+        downloaded = true;
+    }
+
+    for (face, parent, mut material) in &mut faces {
+        if !face.is_added() && !downloaded {
+            continue;
+        }
+        let Some(info) = tokens.get(parent.get()).ok().and_then(|token| token.image) else {
+            continue;
+        };
+
+        if !art.loaded.contains_key(&info.hash) {
+            let Some(bytes) = cache.fetch(&info) else {
+                continue;
+            };
+            let loaded = load_art(&info, &bytes, &art, &mut images, &mut materials);
+            art.loaded.insert(info.hash, loaded);
+        }
+        *material = art.loaded[&info.hash].clone();
+    }
+}
+
+/// Art that cannot be read leaves the token looking like it has none.
+fn load_art(
+    info: &AssetInfo,
+    bytes: &[u8],
+    art: &TokenArt,
+    images: &mut Assets<Image>,
+    materials: &mut Assets<ColorMaterial>,
+) -> Handle<ColorMaterial> {
+    let image = Image::from_buffer(
+        bytes,
+        ImageType::Extension(info.format.extension()),
+        CompressedImageFormats::NONE,
+        true,
+        ImageSampler::Default,
+    );
+
+    match image {
+        Ok(image) => materials.add(images.add(image).into()),
+        Err(error) => {
+            warn!("Could not load token art {}: {error}", info.hash);
+            art.no_art.clone()
+        }
+    }
+}
+
+/// A token being dragged, with where it was picked up from.
+struct Dragging {
+    entity: Entity,
+    offset: Vec2,
+    start: Vec2,
+}
+
+/// Drags tokens the player controls with the left mouse button, snapping
+/// them to the grid and telling the host once they are dropped.
+fn drag_tokens(
+    mut contexts: EguiContexts,
+    buttons: Res<Input<MouseButton>>,
+    cursor: Res<Cursor>,
+    tool: Res<MapTool>,
+    map: Res<CurrentMap>,
+    mut tokens: Query<(Entity, &Token, &mut Transform, &mut Glide)>,
+    mut dragging: Local<Option<Dragging>>,
+    mut prediction: ResMut<Prediction>,
+    mut selected: ResMut<SelectedToken>,
+    mut gizmos: Gizmos,
+    room: Res<CurrentRoom>,
+    user: Res<User>,
+    mut commands: Commands,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    let Some(cursor) = cursor.0 else {
+        return;
+    };
+
+    if buttons.just_pressed(MouseButton::Left) && *tool == MapTool::Select {
+        *dragging = tokens
+            .iter()
+            .filter(|(_, token, ..)| room.is_gm || token.owner.as_ref() == Some(&user.user_id))
+            .map(|(entity, _, transform, _)| Dragging {
+                entity,
+                offset: transform.translation.truncate() - cursor,
+                start: transform.translation.truncate(),
+            })
+            .find(|dragging| dragging.offset.length() < TOKEN_SIZE / 2.0);
+        if let Some((entity, token, ..)) = dragging.as_ref().and_then(|d| tokens.get(d.entity).ok())
+        {
+            selected.0 = Some(token.id);
+            commands.entity(entity).insert(Held);
+        }
+    }
+
+    let Some(Dragging {
+        entity,
+        offset,
+        start,
+    }) = *dragging
+    else {
+        return;
+    };
+    let Ok((_, token, mut transform, mut glide)) = tokens.get_mut(entity) else {
+        *dragging = None;
+        return;
+    };
+
+    let grid = map.grid();
+    let position = cursor + offset;
+    transform.translation.x = position.x;
+    transform.translation.y = position.y;
+    draw_ruler(&mut gizmos, &mut contexts, &grid, start, position);
+
+    // The token is put down right away, the host only corrects it if it
+    // disagrees.
+    if !buttons.pressed(MouseButton::Left) {
+        let position = Vec2::from(grid.snap(position.into()));
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        *glide = Glide::at(position);
+        ev_to_host.send(ToHost(ClientMessage::MoveToken {
+            token: token.id,
+            x: position.x,
+            y: position.y,
+            seq: prediction.predict(token.id),
+        }));
+        commands.entity(entity).remove::<Held>();
+        *dragging = None;
+    }
+}
+
+fn glide_tokens(time: Res<Time>, mut tokens: Query<(&mut Glide, &mut Transform), Without<Held>>) {
+    for (mut glide, mut transform) in &mut tokens {
+        if glide.elapsed >= GLIDE_TIME {
+            continue;
+        }
+
+        glide.elapsed += time.delta_seconds();
+        let t = (glide.elapsed / GLIDE_TIME).min(1.0);
+        let position = glide.from.lerp(glide.to, t * t * (3.0 - 2.0 * t));
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+/// The filled part of the health bar, between 0 and 1, with its color and how
+/// much of the bar temporary hit points take up.
+fn health_bar(health: &HealthView) -> Option<(f32, Color, f32)> {
+    match health {
+        HealthView::Unknown => None,
+        HealthView::Bloodied(true) => Some((0.5, BLOODIED, 0.0)),
+        HealthView::Bloodied(false) => Some((1.0, HEALTHY, 0.0)),
+        HealthView::Full(health) => {
+            let max = health.max.max(1) as f32;
+            let filled = (health.current as f32 / max).clamp(0.0, 1.0);
+            let temp = (health.temp as f32 / max).min(1.0 - filled);
+            let color = match health.bloodied() {
+                true => BLOODIED,
+                false => HEALTHY,
+            };
+            Some((filled, color, temp))
+        }
+    }
+}
+
+fn bar_segment(parent: &mut ChildBuilder, color: Color, from: f32, width: f32, z: f32) {
+    parent.spawn(SpriteBundle {
+        sprite: Sprite {
+            color,
+            custom_size: Some(Vec2::new(width * TOKEN_SIZE, BAR_HEIGHT)),
+            anchor: Anchor::CenterLeft,
+            ..default()
+        },
+        transform: Transform::from_xyz((from - 0.5) * TOKEN_SIZE, 0.0, z),
+        ..default()
+    });
+}
+
+fn render_status(
+    tokens: Query<(Entity, &Token, Option<&Children>), Changed<Token>>,
+    overlays: Query<(), With<StatusOverlay>>,
+    mut commands: Commands,
+) {
+    for (entity, token, children) in &tokens {
+        for child in children.into_iter().flatten() {
+            if overlays.contains(*child) {
+                commands.entity(*child).despawn_recursive();
+            }
+        }
+
+        let overlay = commands
+            .spawn((
+                StatusOverlay,
+                SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.0, 0.5)),
+            ))
+            .with_children(|parent| {
+                if let Some((filled, color, temp)) = health_bar(&token.health) {
+                    parent
+                        .spawn(SpatialBundle::from_transform(Transform::from_xyz(
+                            0.0,
+                            TOKEN_SIZE / 2.0 + BAR_HEIGHT,
+                            0.0,
+                        )))
+                        .with_children(|bar| {
+                            bar_segment(bar, BAR_BACKGROUND, 0.0, 1.0, 0.0);
+                            bar_segment(bar, color, 0.0, filled, 0.1);
+                            bar_segment(bar, TEMP_HP, filled, temp, 0.1);
+                        });
+                }
+
+                for (i, status) in token.conditions.iter().enumerate() {
+                    let label = match status.rounds {
+                        Some(rounds) => format!("{}{rounds}", status.condition.abbreviation()),
+                        None => status.condition.abbreviation().to_owned(),
+                    };
+                    let width = MARKER_SIZE.max(label.len() as f32 * 7.0 + 4.0);
+                    let y = TOKEN_SIZE / 2.0 - MARKER_SIZE / 2.0 - i as f32 * (MARKER_SIZE + 2.0);
+
+                    parent
+                        .spawn(SpriteBundle {
+                            sprite: Sprite {
+                                color: MARKER_COLOR,
+                                custom_size: Some(Vec2::new(width, MARKER_SIZE)),
+                                anchor: Anchor::CenterLeft,
+                                ..default()
+                            },
+                            transform: Transform::from_xyz(TOKEN_SIZE / 2.0 + 2.0, y, 0.0),
+                            ..default()
+                        })
+                        .with_children(|marker| {
+                            marker.spawn(Text2dBundle {
+                                text: Text::from_section(
+                                    label,
+                                    TextStyle {
+                                        font_size: 12.0,
+                                        color: TEXT_COLOR,
+                                        ..default()
+                                    },
+                                ),
+                                transform: Transform::from_xyz(width / 2.0, 0.0, 0.1),
+                                ..default()
+                            });
+                        });
+                }
+            })
+            .id();
+        commands.entity(entity).add_child(overlay);
+    }
+}
+
+fn highlight_active_token(
+    tracker: Res<InitiativeTracker>,
+    selected: Res<SelectedToken>,
+    tokens: Query<(&Token, &GlobalTransform)>,
+    mut gizmos: Gizmos,
+) {
+    let active = tracker.0.active().map(|combatant| combatant.token);
+
+    for (token, transform) in &tokens {
+        let position = transform.translation().truncate();
+        if Some(token.id) == active {
+            gizmos.circle_2d(position, TOKEN_SIZE * 0.75, ACTIVE_TOKEN);
+        }
+        if Some(token.id) == selected.0 {
+            gizmos.rect_2d(position, 0.0, Vec2::splat(TOKEN_SIZE + 4.0), SELECTED_TOKEN);
+        }
+    }
+}
@@ -1,20 +1,39 @@
 use bevy::ecs::system::Resource;
 use serde::{Deserialize, Serialize};
+use tyche_protocol::character::Character;
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct Character {
-    pub name: String,
-}
-
-impl Character {
-    pub fn new(name: String) -> Self {
-        Self { name }
-    }
+/// How a player shows up to others, stored in tyche-character.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Profile {
+    pub display_name: String,
+    pub color: [u8; 3],
+    /// The content type of the avatar, if the player uploaded one.
+    #[serde(default)]
+    pub avatar: Option<String>,
 }
 
 #[derive(Debug, Default, Resource)]
 pub struct User {
+    pub user_id: String,
+    /// The display name from the player's profile.
     pub name: String,
+    pub color: [u8; 3],
     pub token: String,
+    /// How the user signed in, like `google.com` or `anonymous`.
+    pub provider: String,
     pub characters: Vec<Character>,
+    pub character: Option<Character>,
+}
+
+impl User {
+    pub fn provider_name(&self) -> &str {
+        match self.provider.as_str() {
+            "google.com" => "Google",
+            "github.com" => "GitHub",
+            "password" => "email and password",
+            "anonymous" => "a guest account",
+            "dev" => "dev identity",
+            provider => provider,
+        }
+    }
 }
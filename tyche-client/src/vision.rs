@@ -0,0 +1,289 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    sprite::Anchor,
+};
+use tyche_protocol::{
+    map::CELL_SIZE,
+    vision::{vision_polygon, Wall, WallId, WallKind},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    camera::Cursor,
+    map::{CurrentMap, MapTool},
+    network::{CurrentRoom, HostMessage, ToHost},
+    token::Token,
+    user::User,
+};
+
+const WALL_COLOR: Color = Color::rgb(0.9, 0.4, 0.1);
+const DOOR_COLOR: Color = Color::rgb(0.55, 0.3, 0.1);
+const OPEN_DOOR_COLOR: Color = Color::rgb(0.4, 0.8, 0.3);
+const WINDOW_COLOR: Color = Color::rgb(0.3, 0.7, 0.9);
+const FOG_PIXEL: [u8; 4] = [8, 8, 12, 255];
+/// Size of a fog texel in world units.
+const FOG_RESOLUTION: f32 = CELL_SIZE / 4.0;
+const FOG_Z: f32 = 5.0;
+/// How close, in world units, a click has to be to pick a wall.
+const PICK_DISTANCE: f32 = 8.0;
+
+pub struct VisionPlugin;
+
+impl Plugin for VisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Walls::default())
+            .add_systems(Startup, spawn_fog)
+            .add_systems(
+                Update,
+                (handle_wall_messages, edit_walls, draw_walls, render_fog),
+            );
+    }
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct Walls {
+    pub walls: BTreeMap<WallId, Wall>,
+    pub fog: bool,
+}
+
+#[derive(Component)]
+struct Fog;
+
+fn handle_wall_messages(mut ev_host: EventReader<HostMessage>, mut walls: ResMut<Walls>) {
+    for HostMessage(message) in ev_host.read() {
+        match message {
+            ServerMessage::Joined {
+                walls: joined, fog, ..
+            } => {
+                walls.walls = joined.iter().map(|wall| (wall.id, wall.clone())).collect();
+                walls.fog = *fog;
+            }
+            ServerMessage::WallChanged(wall) => {
+                walls.walls.insert(wall.id, wall.clone());
+            }
+            ServerMessage::WallRemoved(id) => {
+                walls.walls.remove(id);
+            }
+            ServerMessage::FogChanged(fog) => walls.fog = *fog,
+            _ => {}
+        }
+    }
+}
+
+fn distance_to_wall(point: Vec2, wall: &Wall) -> f32 {
+    let start = Vec2::from(wall.start);
+    let edge = Vec2::from(wall.end) - start;
+    let t = ((point - start).dot(edge) / edge.length_squared()).clamp(0.0, 1.0);
+    point.distance(start + edge * t)
+}
+
+fn pick_wall(walls: &Walls, point: Vec2, filter: impl Fn(&Wall) -> bool) -> Option<WallId> {
+    walls
+        .walls
+        .values()
+        .filter(|wall| filter(wall))
+        .map(|wall| (wall.id, distance_to_wall(point, wall)))
+        .filter(|(_, distance)| *distance < PICK_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(id, _)| id)
+}
+
+/// Lets the GM draw walls between grid corners, erase them and open doors.
+fn edit_walls(
+    buttons: Res<Input<MouseButton>>,
+    cursor: Res<Cursor>,
+    tool: Res<MapTool>,
+    walls: Res<Walls>,
+    map: Res<CurrentMap>,
+    mut drawing: Local<Option<Vec2>>,
+    mut gizmos: Gizmos,
+    mut ev_to_host: EventWriter<ToHost>,
+) {
+    let Some(cursor) = cursor.0 else {
+        return;
+    };
+    let snap_to_corner = |point: Vec2| Vec2::from(map.grid().snap_to_corner(point.into()));
+
+    match *tool {
+        MapTool::Wall(kind) => {
+            if buttons.just_pressed(MouseButton::Left) {
+                *drawing = Some(snap_to_corner(cursor));
+            }
+            let Some(start) = *drawing else {
+                return;
+            };
+
+            let end = snap_to_corner(cursor);
+            gizmos.line_2d(start, end, wall_color(kind));
+            if !buttons.pressed(MouseButton::Left) {
+                if start != end {
+                    ev_to_host.send(ToHost(ClientMessage::AddWall {
+                        kind,
+                        start: start.into(),
+                        end: end.into(),
+                    }));
+                }
+                *drawing = None;
+            }
+        }
+        MapTool::EraseWall if buttons.just_pressed(MouseButton::Left) => {
+            if let Some(id) = pick_wall(&walls, cursor, |_| true) {
+                ev_to_host.send(ToHost(ClientMessage::RemoveWall(id)));
+            }
+        }
+        MapTool::ToggleDoor if buttons.just_pressed(MouseButton::Left) => {
+            let is_door = |wall: &Wall| matches!(wall.kind, WallKind::Door { .. });
+            if let Some(id) = pick_wall(&walls, cursor, is_door) {
+                ev_to_host.send(ToHost(ClientMessage::ToggleDoor(id)));
+            }
+        }
+        _ => *drawing = None,
+    }
+}
+
+fn wall_color(kind: WallKind) -> Color {
+    match kind {
+        WallKind::Wall => WALL_COLOR,
+        WallKind::Door { open: false } => DOOR_COLOR,
+        WallKind::Door { open: true } => OPEN_DOOR_COLOR,
+        WallKind::Window => WINDOW_COLOR,
+    }
+}
+
+/// Only the GM gets to see the walls themselves.
+fn draw_walls(room: Res<CurrentRoom>, walls: Res<Walls>, mut gizmos: Gizmos) {
+    if !room.is_gm {
+        return;
+    }
+
+    for wall in walls.walls.values() {
+        let (start, end) = (Vec2::from(wall.start), Vec2::from(wall.end));
+        gizmos.line_2d(start, end, wall_color(wall.kind));
+        if let WallKind::Door { .. } = wall.kind {
+            gizmos.circle_2d(start.lerp(end, 0.5), 4.0, wall_color(wall.kind));
+        }
+    }
+}
+
+fn spawn_fog(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let image = Image::new_fill(
+        Extent3d::default(),
+        TextureDimension::D2,
+        &FOG_PIXEL,
+        TextureFormat::Rgba8UnormSrgb,
+    );
+
+    commands.spawn((
+        Name::new("Fog"),
+        Fog,
+        SpriteBundle {
+            sprite: Sprite {
+                anchor: Anchor::TopLeft,
+                ..default()
+            },
+            texture: images.add(image),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+}
+
+/// Clears the texels of a triangle, given in texel coordinates, from the fog.
+fn clear_triangle(data: &mut [u8], width: usize, height: usize, corners: [Vec2; 3]) {
+    let top = corners.iter().map(|c| c.y).fold(f32::INFINITY, f32::min);
+    let bottom = corners
+        .iter()
+        .map(|c| c.y)
+        .fold(f32::NEG_INFINITY, f32::max);
+    let first_row = (top - 0.5).ceil().max(0.0) as i64;
+    let last_row = (bottom - 0.5).floor().min(height as f32 - 1.0) as i64;
+
+    for row in first_row..=last_row {
+        let y = row as f32 + 0.5;
+        let (mut left, mut right) = (f32::INFINITY, f32::NEG_INFINITY);
+        for (a, b) in [(0, 1), (1, 2), (2, 0)].map(|(a, b)| (corners[a], corners[b])) {
+            if a.y == b.y || y < a.y.min(b.y) || y > a.y.max(b.y) {
+                continue;
+            }
+            let x = a.x + (y - a.y) / (b.y - a.y) * (b.x - a.x);
+            left = left.min(x);
+            right = right.max(x);
+        }
+
+        let first = (left - 0.5).ceil().max(0.0) as i64;
+        let last = (right - 0.5).floor().min(width as f32 - 1.0) as i64;
+        for column in first..=last {
+            let texel = (row as usize * width + column as usize) * 4;
+            data[texel..texel + 4].copy_from_slice(&[0; 4]);
+        }
+    }
+}
+
+/// Covers everything the player's tokens can't see, over the part of the
+/// map that is on screen.
+fn render_fog(
+    room: Res<CurrentRoom>,
+    user: Res<User>,
+    walls: Res<Walls>,
+    tokens: Query<(&Token, &GlobalTransform)>,
+    cameras: Query<(&OrthographicProjection, &GlobalTransform)>,
+    mut fog: Query<(&mut Transform, &mut Sprite, &mut Visibility, &Handle<Image>), With<Fog>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Ok((mut transform, mut sprite, mut visibility, handle)) = fog.get_single_mut() else {
+        return;
+    };
+    let Ok((projection, camera)) = cameras.get_single() else {
+        return;
+    };
+
+    if !walls.fog || room.is_gm {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Visible;
+
+    let center = camera.translation().truncate();
+    let min = ((projection.area.min + center) / FOG_RESOLUTION).floor() * FOG_RESOLUTION;
+    let max = ((projection.area.max + center) / FOG_RESOLUTION).ceil() * FOG_RESOLUTION;
+    let size = ((max - min) / FOG_RESOLUTION).as_uvec2().max(UVec2::ONE);
+    let (width, height) = (size.x as usize, size.y as usize);
+
+    transform.translation = Vec3::new(min.x, max.y, FOG_Z);
+    sprite.custom_size = Some(size.as_vec2() * FOG_RESOLUTION);
+
+    let Some(image) = images.get_mut(handle) else {
+        return;
+    };
+    image.resize(Extent3d {
+        width: size.x,
+        height: size.y,
+        depth_or_array_layers: 1,
+    });
+    for texel in image.data.chunks_exact_mut(4) {
+        texel.copy_from_slice(&FOG_PIXEL);
+    }
+
+    let to_texel = |point: Vec2| Vec2::new(point.x - min.x, max.y - point.y) / FOG_RESOLUTION;
+    let eyes = tokens
+        .iter()
+        .filter(|(token, _)| token.owner.as_ref() == Some(&user.user_id))
+        .map(|(_, transform)| transform.translation().truncate());
+
+    for eye in eyes {
+        let polygon = vision_polygon(walls.walls.values(), eye.into());
+        let origin = to_texel(eye);
+        for (i, point) in polygon.iter().enumerate() {
+            let next = polygon[(i + 1) % polygon.len()];
+            let corners = [
+                origin,
+                to_texel(Vec2::from(*point)),
+                to_texel(Vec2::from(next)),
+            ];
+            clear_triangle(&mut image.data, width, height, corners);
+        }
+    }
+}
@@ -0,0 +1,560 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{Cursor, Read, Write},
+    path::PathBuf,
+};
+
+use bevy::{
+    prelude::*,
+    tasks::{block_on, AsyncComputeTaskPool, Task},
+};
+use bevy_renet::renet::{ClientId, RenetServer, ServerEvent};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tyche_protocol::{
+    archive::MAX_ARCHIVE_SIZE,
+    asset::{AssetHash, AssetInfo},
+    map::CHUNK_SIZE,
+    ClientMessage, ServerMessage,
+};
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    asset_store::AssetStore,
+    network::{send, ClientEvent, Transfers},
+    room::{unix_time, Room, Rooms},
+    save::{map_file, SavedRoom},
+};
+
+/// Bumped when the layout of the archive changes, the room state inside it
+/// has its own version, see [`crate::save::SAVE_VERSION`].
+const ARCHIVE_VERSION: u64 = 2;
+const FORMAT: &str = "tyche";
+const MANIFEST: &str = "manifest.json";
+const MAX_MANIFEST_SIZE: usize = 64 * 1024;
+/// Distinct token images an archive may hold.
+const MAX_TOKEN_ART: usize = 500;
+/// The room state, its map image and the token art.
+const MAX_FILES: usize = 2 + MAX_TOKEN_ART;
+/// Everything read out of an archive together, so a few small files can't
+/// unpack into gigabytes.
+const MAX_UNPACKED_SIZE: usize = MAX_ARCHIVE_SIZE;
+const ROOM_FILE: &str = "room.json";
+const MAPS: &str = "maps";
+/// Token art, added in version 2.
+const ASSETS: &str = "assets";
+
+pub struct ArchivePlugin;
+
+impl Plugin for ArchivePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Imports::default())
+            .insert_resource(Unpacking::default())
+            .insert_resource(Packing::default())
+            .add_systems(
+                Update,
+                (
+                    handle_export,
+                    finish_exports,
+                    handle_import,
+                    finish_imports,
+                    drop_imports,
+                ),
+            );
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Manifest {
+    format: String,
+    version: u64,
+    room: String,
+    created: u64,
+    /// The SHA-256 of every other file in the archive, by path.
+    files: BTreeMap<String, String>,
+}
+
+fn sha256(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn asset_file(info: &AssetInfo) -> String {
+    format!("{ASSETS}/{}.{}", info.hash, info.format.extension())
+}
+
+fn too_large() -> String {
+    format!(
+        "the campaign would be over {} MB, too large to import again",
+        MAX_ARCHIVE_SIZE / 1024 / 1024
+    )
+}
+
+/// A room gathered for export, which [`Export::pack`] turns into an archive
+/// off the main thread.
+pub struct Export {
+    room: String,
+    files: Vec<(String, Vec<u8>, CompressionMethod)>,
+    /// Token art, read out of the asset store while packing.
+    art: Vec<(String, PathBuf)>,
+}
+
+/// Gathers a room, its map and the art of its tokens for a `.tyche` archive,
+/// refusing rooms whose archive could not be imported again.
+pub fn export(room: &Room, store: &AssetStore) -> Result<Export, String> {
+    let json = serde_json::to_vec(&SavedRoom::new(room)).map_err(|error| error.to_string())?;
+    let mut files = vec![(ROOM_FILE.to_owned(), json, CompressionMethod::Deflated)];
+    let mut art = room.assets();
+    if let Some(map) = &room.map {
+        let path = format!("{MAPS}/{}", map_file(&map.info));
+        // Images are compressed already.
+        files.push((path, map.image.clone(), CompressionMethod::Stored));
+        art.remove(&map.info.asset.hash);
+    }
+    if art.len() > MAX_TOKEN_ART {
+        return Err(format!("tokens use more than {MAX_TOKEN_ART} images"));
+    }
+    // Art the host lost is left out, the tokens lose it on import.
+    let art: Vec<_> = art
+        .iter()
+        .filter_map(|hash| Some((store.info(hash)?, store.path(hash)?)))
+        .collect();
+
+    // Images are stored as they are, so the archive is at least as large
+    // as they are together.
+    let size = files.iter().map(|(_, bytes, _)| bytes.len()).sum::<usize>()
+        + art.iter().map(|(info, _)| info.size).sum::<usize>();
+    if size > MAX_ARCHIVE_SIZE {
+        return Err(too_large());
+    }
+
+    Ok(Export {
+        room: room.name.clone(),
+        files,
+        art: art
+            .into_iter()
+            .map(|(info, path)| (asset_file(&info), path))
+            .collect(),
+    })
+}
+
+impl Export {
+    /// Reads the token art and zips everything up.
+    pub fn pack(self) -> Result<Vec<u8>, String> {
+        let mut files = self.files;
+        for (file, path) in self.art {
+            match fs::read(&path) {
+                Ok(bytes) => files.push((file, bytes, CompressionMethod::Stored)),
+                Err(error) => error!("Could not read asset {}: {error}", path.display()),
+            }
+        }
+
+        let manifest = Manifest {
+            format: FORMAT.to_owned(),
+            version: ARCHIVE_VERSION,
+            room: self.room,
+            created: unix_time(),
+            files: files
+                .iter()
+                .map(|(path, bytes, _)| (path.clone(), sha256(bytes)))
+                .collect(),
+        };
+        let manifest = serde_json::to_vec_pretty(&manifest).map_err(|error| error.to_string())?;
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let manifest = (MANIFEST.to_owned(), manifest, CompressionMethod::Deflated);
+        for (path, bytes, method) in std::iter::once(manifest).chain(files) {
+            let options = FileOptions::default().compression_method(method);
+            zip.start_file(path, options).map_err(|e| e.to_string())?;
+            zip.write_all(&bytes).map_err(|error| error.to_string())?;
+        }
+        let bytes = zip
+            .finish()
+            .map_err(|error| error.to_string())?
+            .into_inner();
+        if bytes.len() > MAX_ARCHIVE_SIZE {
+            return Err(too_large());
+        }
+        Ok(bytes)
+    }
+}
+
+/// Reads a file out of an archive, refusing ones that unpack to more than
+/// `limit` bytes.
+fn read_file(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    path: &str,
+    limit: usize,
+) -> Result<Vec<u8>, String> {
+    let file = archive
+        .by_name(path)
+        .map_err(|_| format!("{path} is missing"))?;
+    let mut bytes = Vec::new();
+    file.take(limit as u64 + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|error| format!("{path} could not be read: {error}"))?;
+    if bytes.len() > limit {
+        return Err(format!("{path} is too large"));
+    }
+    Ok(bytes)
+}
+
+/// Reads a file the manifest lists and checks its hash, taking its size out
+/// of what is left to unpack.
+fn read_listed(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    manifest: &Manifest,
+    path: &str,
+    budget: &mut usize,
+) -> Result<Vec<u8>, String> {
+    let hash = manifest
+        .files
+        .get(path)
+        .ok_or_else(|| format!("{path} is missing"))?;
+    let bytes = read_file(archive, path, *budget)?;
+    if sha256(&bytes) != *hash {
+        return Err(format!("{path} is corrupt, its hash does not match"));
+    }
+    *budget -= bytes.len();
+    Ok(bytes)
+}
+
+/// Restores a room from a `.tyche` archive under a new name, after checking
+/// its version and the hash of the files it needs. The art of its tokens
+/// comes back alongside, for the asset store.
+pub fn import(bytes: &[u8], name: String) -> Result<(Room, Vec<Vec<u8>>), String> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(|error| error.to_string())?;
+
+    let manifest = read_file(&mut archive, MANIFEST, MAX_MANIFEST_SIZE)?;
+    let manifest: Manifest = serde_json::from_slice(&manifest)
+        .map_err(|error| format!("the manifest is invalid: {error}"))?;
+    if manifest.format != FORMAT {
+        return Err("this is not a Tyche campaign".to_owned());
+    }
+    if manifest.version > ARCHIVE_VERSION {
+        return Err(format!(
+            "the archive is version {}, this host only reads up to {ARCHIVE_VERSION}",
+            manifest.version
+        ));
+    }
+
+    if manifest.files.len() > MAX_FILES {
+        return Err("the manifest lists more files than a campaign has".to_owned());
+    }
+    // Directories are fine, files that would go unchecked are not.
+    let listed = archive
+        .file_names()
+        .all(|path| path == MANIFEST || path.ends_with('/') || manifest.files.contains_key(path));
+    if !listed {
+        return Err("the archive has files its manifest does not list".to_owned());
+    }
+
+    let mut budget = MAX_UNPACKED_SIZE;
+    let json = read_listed(&mut archive, &manifest, ROOM_FILE, &mut budget)?;
+    let mut saved = SavedRoom::parse(&json)?;
+    let image = match &saved.map {
+        Some(map) => {
+            let path = format!("{MAPS}/{}", map_file(&map.info));
+            Some(read_listed(&mut archive, &manifest, &path, &mut budget)?)
+        }
+        None => None,
+    };
+
+    let art = saved
+        .tokens
+        .iter()
+        .filter_map(|token| token.image)
+        .map(|info| (info.hash, info))
+        .collect::<HashMap<_, _>>();
+    let mut assets = Vec::new();
+    for info in art.into_values() {
+        let path = asset_file(&info);
+        if !manifest.files.contains_key(&path) {
+            continue;
+        }
+        let bytes = read_listed(&mut archive, &manifest, &path, &mut budget)?;
+        if AssetHash::of(&bytes) != info.hash {
+            return Err(format!("{path} does not match its name"));
+        }
+        assets.push(bytes);
+    }
+
+    saved.name = name;
+    Ok((saved.restore(image)?, assets))
+}
+
+/// Archives that are still being uploaded, by the client uploading them.
+#[derive(Debug, Default, Resource)]
+struct Imports(HashMap<ClientId, Import>);
+
+#[derive(Debug)]
+struct Import {
+    room: String,
+    size: usize,
+    bytes: Vec<u8>,
+}
+
+/// Archives being packed off the main thread, by the GM who asked for them.
+#[derive(Default, Resource)]
+struct Packing(Vec<(ClientId, Task<Result<Vec<u8>, String>>)>);
+
+/// Uploaded archives being checked and unpacked off the main thread.
+#[derive(Default, Resource)]
+struct Unpacking(Vec<(ClientId, Task<Result<(Room, Vec<Vec<u8>>), String>>)>);
+
+fn handle_export(
+    mut ev_client: EventReader<ClientEvent>,
+    rooms: Res<Rooms>,
+    store: Res<AssetStore>,
+    mut packing: ResMut<Packing>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::ExportRoom = message else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of(*client_id) else {
+            continue;
+        };
+        if !room.is_gm(*client_id) {
+            let error = ServerMessage::Error("Only the GM can export the campaign".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+
+        let task = match export(room, &store) {
+            Ok(export) => AsyncComputeTaskPool::get().spawn(async move { export.pack() }),
+            Err(error) => {
+                error!("Could not export room {}: {error}", room.name);
+                let error = format!("Could not export the campaign: {error}");
+                send(&mut server, *client_id, &ServerMessage::Error(error));
+                continue;
+            }
+        };
+        packing.0.push((*client_id, task));
+    }
+}
+
+fn finish_exports(
+    mut packing: ResMut<Packing>,
+    mut transfers: ResMut<Transfers>,
+    mut server: ResMut<RenetServer>,
+) {
+    let (done, pending) = std::mem::take(&mut packing.0)
+        .into_iter()
+        .partition::<Vec<_>, _>(|(_, task)| task.is_finished());
+    packing.0 = pending;
+
+    for (client_id, task) in done {
+        let bytes = match block_on(task) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                error!("Could not export a room for {client_id}: {error}");
+                let error = format!("Could not export the campaign: {error}");
+                send(&mut server, client_id, &ServerMessage::Error(error));
+                continue;
+            }
+        };
+
+        let begin = ServerMessage::BeginExport { size: bytes.len() };
+        let chunks = bytes
+            .chunks(CHUNK_SIZE)
+            .map(|chunk| ServerMessage::ExportChunk(chunk.to_vec()))
+            .collect::<Vec<_>>();
+        transfers.queue(client_id, std::iter::once(begin).chain(chunks));
+    }
+}
+
+fn handle_import(
+    mut ev_client: EventReader<ClientEvent>,
+    rooms: Res<Rooms>,
+    mut imports: ResMut<Imports>,
+    mut unpacking: ResMut<Unpacking>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        match message {
+            ClientMessage::BeginImport { room: name, size } => {
+                let is_gm = rooms
+                    .room_of(*client_id)
+                    .is_some_and(|room| room.is_gm(*client_id));
+                let error = if !is_gm {
+                    Some("Only a GM can import a campaign".to_owned())
+                } else if name.trim().is_empty() || rooms.contains(name) {
+                    Some(format!("Choose a new room name, {name} is taken"))
+                } else if *size == 0 || *size > MAX_ARCHIVE_SIZE {
+                    Some(format!(
+                        "Campaigns can be at most {} MB",
+                        MAX_ARCHIVE_SIZE / 1024 / 1024
+                    ))
+                } else {
+                    None
+                };
+                if let Some(error) = error {
+                    send(&mut server, *client_id, &ServerMessage::Error(error));
+                    continue;
+                }
+
+                let import = Import {
+                    room: name.trim().to_owned(),
+                    size: *size,
+                    bytes: Vec::new(),
+                };
+                imports.0.insert(*client_id, import);
+            }
+            ClientMessage::ImportChunk(bytes) => {
+                let Some(import) = imports.0.get_mut(client_id) else {
+                    continue;
+                };
+                if import.bytes.len() + bytes.len() > import.size {
+                    imports.0.remove(client_id);
+                    let error = ServerMessage::Error("Import exceeded its size".to_owned());
+                    send(&mut server, *client_id, &error);
+                    continue;
+                }
+
+                import.bytes.extend_from_slice(bytes);
+                if import.bytes.len() < import.size {
+                    continue;
+                }
+
+                let import = imports.0.remove(client_id).unwrap();
+                let task = AsyncComputeTaskPool::get()
+                    .spawn(async move { self::import(&import.bytes, import.room) });
+                unpacking.0.push((*client_id, task));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn finish_imports(
+    mut unpacking: ResMut<Unpacking>,
+    mut rooms: ResMut<Rooms>,
+    mut store: ResMut<AssetStore>,
+    mut server: ResMut<RenetServer>,
+) {
+    let (done, pending) = std::mem::take(&mut unpacking.0)
+        .into_iter()
+        .partition::<Vec<_>, _>(|(_, task)| task.is_finished());
+    unpacking.0 = pending;
+
+    for (client_id, task) in done {
+        let room = block_on(task).and_then(|(mut room, assets)| {
+            // Someone else may have taken the name while this one unpacked.
+            if rooms.contains(&room.name) {
+                return Err(format!("{} is taken, choose a new room name", room.name));
+            }
+            if let Some(map) = &room.map {
+                store.insert(&map.image)?;
+            }
+            for bytes in &assets {
+                store.insert(bytes)?;
+            }
+            // Tokens never point at art nobody can download.
+            for token in room.tokens.values_mut() {
+                token.image = token
+                    .image
+                    .filter(|image| store.info(&image.hash).is_some());
+            }
+            Ok(room)
+        });
+        match room {
+            Ok(room) => {
+                info!("{client_id} imported a campaign as room {}", room.name);
+                let message = ServerMessage::Imported {
+                    room: room.name.clone(),
+                };
+                rooms.insert(room);
+                send(&mut server, client_id, &message);
+            }
+            Err(error) => {
+                let error = format!("Could not import the campaign: {error}");
+                send(&mut server, client_id, &ServerMessage::Error(error));
+            }
+        }
+    }
+}
+
+fn drop_imports(
+    mut server_events: EventReader<ServerEvent>,
+    mut imports: ResMut<Imports>,
+    mut packing: ResMut<Packing>,
+) {
+    for event in server_events.read() {
+        if let ServerEvent::ClientDisconnected { client_id, .. } = event {
+            imports.0.remove(client_id);
+            packing
+                .0
+                .retain(|(packing_for, _)| packing_for != client_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use tyche_protocol::{asset::MAX_ASSET_SIZE, token::Token};
+
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    fn store(name: &str) -> (PathBuf, AssetStore) {
+        let dir = env::temp_dir().join(format!("tyche-archive-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let store = AssetStore::open(dir.clone()).unwrap();
+        (dir, store)
+    }
+
+    fn room_with_art(art: &[AssetInfo]) -> Room {
+        let mut room = Room::new("Crypt".to_owned());
+        for info in art {
+            room.spawn_token(Token {
+                image: Some(*info),
+                ..Token::new("Goblin".to_owned(), 0.0, 0.0)
+            });
+        }
+        room
+    }
+
+    #[test]
+    fn token_art_comes_back_on_import() {
+        let (dir, mut store) = store("art");
+        let image = [PNG, b"goblin"].concat();
+        let info = store.insert(&image).unwrap();
+
+        let room = room_with_art(&[info]);
+        let bytes = export(&room, &store).unwrap().pack().unwrap();
+        let (room, assets) = import(&bytes, "Copy".to_owned()).unwrap();
+
+        assert_eq!(room.name, "Copy");
+        assert_eq!(assets, [image]);
+        assert_eq!(room.tokens[&0].image, Some(info));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn exports_too_large_to_import_are_refused() {
+        let (dir, _) = store("large");
+        // Sparse files, the store only looks at their start and size.
+        let count = MAX_ARCHIVE_SIZE / MAX_ASSET_SIZE + 1;
+        for i in 0..count {
+            let file = fs::File::create(dir.join(AssetHash::of(&[i as u8]).to_string())).unwrap();
+            (&file).write_all(PNG).unwrap();
+            file.set_len(MAX_ASSET_SIZE as u64).unwrap();
+        }
+        let store = AssetStore::open(dir.clone()).unwrap();
+        let art: Vec<_> = (0..count)
+            .filter_map(|i| store.info(&AssetHash::of(&[i as u8])))
+            .collect();
+        assert_eq!(art.len(), count);
+
+        let error = export(&room_with_art(&art), &store).err().unwrap();
+        assert_eq!(error, too_large());
+        assert!(export(&room_with_art(&art[1..]), &store).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer, ServerEvent};
+use tyche_protocol::{
+    asset::{AssetHash, AssetInfo, MAX_ASSET_SIZE},
+    map::{ImageFormat, CHUNK_SIZE},
+    CharacterInfo, ClientMessage, ServerMessage,
+};
+
+use crate::{
+    network::{send, ClientEvent, Transfers},
+    room::Rooms,
+};
+
+pub struct AssetStorePlugin;
+
+impl Plugin for AssetStorePlugin {
+    fn build(&self, app: &mut App) {
+        let dir = env::var("ASSET_DIR").unwrap_or("asset-store".to_owned());
+        let store = match AssetStore::open(dir.into()) {
+            Ok(store) => store,
+            Err(error) => panic!("Could not open the asset store: {error}"),
+        };
+
+        app.insert_resource(store)
+            .insert_resource(AssetUploads::default())
+            .add_systems(
+                Update,
+                (handle_asset_upload, handle_request_assets, drop_uploads),
+            );
+    }
+}
+
+/// Images on disk, named after their hash so each is only stored once no
+/// matter how many rooms use it.
+#[derive(Debug, Resource)]
+pub struct AssetStore {
+    dir: PathBuf,
+    assets: HashMap<AssetHash, AssetInfo>,
+}
+
+impl AssetStore {
+    pub fn open(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        let mut assets = HashMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some(hash) = AssetHash::parse(&name) else {
+                continue;
+            };
+
+            let mut magic = Vec::new();
+            fs::File::open(entry.path())?
+                .take(12)
+                .read_to_end(&mut magic)?;
+            let Some(format) = ImageFormat::detect(&magic) else {
+                warn!("Skipping asset {name}, it is not an image");
+                continue;
+            };
+            let size = entry.metadata()?.len() as usize;
+            assets.insert(hash, AssetInfo { hash, format, size });
+        }
+
+        info!("{} assets in {}", assets.len(), dir.display());
+        Ok(Self { dir, assets })
+    }
+
+    pub fn info(&self, hash: &AssetHash) -> Option<AssetInfo> {
+        self.assets.get(hash).copied()
+    }
+
+    /// Stores an asset unless it is already there.
+    pub fn insert(&mut self, bytes: &[u8]) -> Result<AssetInfo, String> {
+        let info = AssetInfo::of(bytes)?;
+        if self.assets.contains_key(&info.hash) {
+            return Ok(info);
+        }
+
+        let path = self.dir.join(info.hash.to_string());
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, bytes)
+            .and_then(|()| fs::rename(&temporary, &path))
+            .map_err(|error| format!("Could not store the asset: {error}"))?;
+        self.assets.insert(info.hash, info);
+        Ok(info)
+    }
+
+    /// Leaves out token art the host does not have yet, so tokens never
+    /// point at an asset nobody can download.
+    pub fn stored_art(&self, character: &CharacterInfo) -> CharacterInfo {
+        let token_image = character
+            .token_image
+            .filter(|image| self.assets.contains_key(&image.hash));
+        CharacterInfo {
+            token_image,
+            ..character.clone()
+        }
+    }
+
+    /// Where an asset is on disk, for reading it off the main thread.
+    pub fn path(&self, hash: &AssetHash) -> Option<PathBuf> {
+        self.assets.get(hash)?;
+        Some(self.dir.join(hash.to_string()))
+    }
+
+    pub fn read(&self, hash: &AssetHash) -> Option<Vec<u8>> {
+        self.assets.get(hash)?;
+        match fs::read(self.dir.join(hash.to_string())) {
+            Ok(bytes) => Some(bytes),
+            Err(error) => {
+                error!("Could not read asset {hash}: {error}");
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Upload {
+    hash: AssetHash,
+    size: usize,
+    bytes: Vec<u8>,
+}
+
+/// Assets that are still being uploaded, by the client uploading them.
+#[derive(Debug, Default, Resource)]
+struct AssetUploads(HashMap<ClientId, Upload>);
+
+fn handle_asset_upload(
+    mut ev_client: EventReader<ClientEvent>,
+    rooms: Res<Rooms>,
+    mut store: ResMut<AssetStore>,
+    mut uploads: ResMut<AssetUploads>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        match message {
+            ClientMessage::BeginAssetUpload { hash, size } => {
+                if rooms.room_of(*client_id).is_none() {
+                    continue;
+                }
+                // Nothing to upload if someone already did.
+                if let Some(info) = store.info(hash) {
+                    send(&mut server, *client_id, &ServerMessage::AssetStored(info));
+                    continue;
+                }
+                if *size == 0 || *size > MAX_ASSET_SIZE {
+                    let error =
+                        format!("Images can be at most {} MB", MAX_ASSET_SIZE / 1024 / 1024);
+                    send(&mut server, *client_id, &ServerMessage::Error(error));
+                    continue;
+                }
+
+                let upload = Upload {
+                    hash: *hash,
+                    size: *size,
+                    bytes: Vec::new(),
+                };
+                uploads.0.insert(*client_id, upload);
+            }
+            ClientMessage::AssetChunk(bytes) => {
+                let Some(upload) = uploads.0.get_mut(client_id) else {
+                    continue;
+                };
+                if upload.bytes.len() + bytes.len() > upload.size {
+                    uploads.0.remove(client_id);
+                    let error = ServerMessage::Error("Upload exceeded its size".to_owned());
+                    send(&mut server, *client_id, &error);
+                    continue;
+                }
+
+                upload.bytes.extend_from_slice(bytes);
+                if upload.bytes.len() < upload.size {
+                    continue;
+                }
+
+                let upload = uploads.0.remove(client_id).unwrap();
+                let message = match store.insert(&upload.bytes) {
+                    Ok(info) if info.hash == upload.hash => {
+                        info!("{client_id} uploaded asset {}", info.hash);
+                        ServerMessage::AssetStored(info)
+                    }
+                    Ok(_) => ServerMessage::Error("The upload arrived corrupted".to_owned()),
+                    Err(error) => ServerMessage::Error(error),
+                };
+                send(&mut server, *client_id, &message);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn handle_request_assets(
+    mut ev_client: EventReader<ClientEvent>,
+    rooms: Res<Rooms>,
+    store: Res<AssetStore>,
+    mut transfers: ResMut<Transfers>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::RequestAssets(hashes) = message else {
+            continue;
+        };
+        let Some(room) = rooms.room_of(*client_id) else {
+            continue;
+        };
+
+        // Clients only get the assets of the room they are in.
+        let used = room.assets();
+        let hashes: HashSet<_> = hashes.iter().filter(|hash| used.contains(hash)).collect();
+        for hash in hashes {
+            let Some(bytes) = store.read(hash) else {
+                continue;
+            };
+            let chunks = bytes
+                .chunks(CHUNK_SIZE)
+                .enumerate()
+                .map(|(index, bytes)| ServerMessage::AssetChunk {
+                    asset: *hash,
+                    index,
+                    bytes: bytes.to_vec(),
+                })
+                .collect::<Vec<_>>();
+            transfers.queue(*client_id, chunks);
+        }
+    }
+}
+
+fn drop_uploads(mut server_events: EventReader<ServerEvent>, mut uploads: ResMut<AssetUploads>) {
+    for event in server_events.read() {
+        if let ServerEvent::ClientDisconnected { client_id, .. } = event {
+            uploads.0.remove(client_id);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer};
+use tyche_protocol::{
+    chat::{ChatKind, ChatMessage},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    dice::publish_roll,
+    network::{send, ClientEvent},
+    room::{remember, unix_time, Room, Rooms},
+};
+
+const MAX_MESSAGE_LENGTH: usize = 500;
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, handle_chat);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Command<'a> {
+    Say(&'a str),
+    Emote(&'a str),
+    Whisper(&'a str),
+    Gm(&'a str),
+    Roll { expression: &'a str, secret: bool },
+    Unknown(&'a str),
+}
+
+fn parse_command(text: &str) -> Command<'_> {
+    let Some(command) = text.strip_prefix('/') else {
+        return Command::Say(text);
+    };
+
+    let (name, rest) = command.split_once(' ').unwrap_or((command, ""));
+    let rest = rest.trim();
+
+    match name {
+        "me" => Command::Emote(rest),
+        "w" | "whisper" => Command::Whisper(rest),
+        "gm" => Command::Gm(rest),
+        "r" | "roll" => Command::Roll {
+            expression: rest,
+            secret: false,
+        },
+        "gr" | "gmroll" => Command::Roll {
+            expression: rest,
+            secret: true,
+        },
+        _ => Command::Unknown(name),
+    }
+}
+
+fn too_long(text: &str) -> bool {
+    text.chars().count() > MAX_MESSAGE_LENGTH
+}
+
+/// Finds the player a whisper is addressed to by the longest player or
+/// character name the text starts with, and returns it with the message.
+fn whisper_target<'a>(room: &Room, text: &'a str) -> Option<(ChatKind, &'a str)> {
+    room.players
+        .values()
+        .flat_map(|player| {
+            let names = std::iter::once(&player.name).chain(player.character.as_ref());
+            names.map(move |name| (player, name))
+        })
+        .filter_map(|(player, name)| {
+            let prefix = text.get(..name.len())?;
+            let rest = &text[name.len()..];
+            let matches = prefix.to_lowercase() == name.to_lowercase()
+                && (rest.is_empty() || rest.starts_with(' '));
+            matches.then_some((player, name.len(), rest))
+        })
+        .max_by_key(|(_, length, _)| *length)
+        .map(|(player, _, rest)| {
+            let to = ChatKind::Whisper {
+                to: player.name.clone(),
+                to_user: player.user_id.clone(),
+            };
+            (to, rest.trim())
+        })
+}
+
+fn handle_chat(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::Chat(text) = message else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            let error = ServerMessage::Error("Join a room before chatting".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        };
+
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        if too_long(text) {
+            let error = format!("Messages can be at most {MAX_MESSAGE_LENGTH} characters long");
+            send(&mut server, *client_id, &ServerMessage::Error(error));
+            continue;
+        }
+
+        let (kind, text) = match parse_command(text) {
+            Command::Say(text) => (ChatKind::Say, text),
+            Command::Emote(text) => (ChatKind::Emote, text),
+            Command::Gm(text) => (ChatKind::Gm, text),
+            Command::Whisper(text) => match whisper_target(room, text) {
+                Some(whisper) => whisper,
+                None => {
+                    let error = ServerMessage::Error(format!("Nobody to whisper to in: {text}"));
+                    send(&mut server, *client_id, &error);
+                    continue;
+                }
+            },
+            Command::Roll { expression, secret } => {
+                publish_roll(room, &mut server, *client_id, expression, None, secret);
+                continue;
+            }
+            Command::Unknown(command) => {
+                let error = ServerMessage::Error(format!("Unknown command /{command}"));
+                send(&mut server, *client_id, &error);
+                continue;
+            }
+        };
+
+        let player = &room.players[client_id];
+        let message = ChatMessage {
+            room: room.name.clone(),
+            client_id: client_id.raw(),
+            user_id: player.user_id.clone(),
+            sender: player.name.clone(),
+            timestamp: unix_time(),
+            kind,
+            text: text.to_owned(),
+        };
+        publish_chat(room, message);
+    }
+}
+
+fn publish_chat(room: &mut Room, message: ChatMessage) {
+    let readers: Vec<ClientId> = room
+        .players
+        .keys()
+        .filter(|client_id| room.can_read(**client_id, &message))
+        .copied()
+        .collect();
+
+    let server_message = ServerMessage::Chat(message.clone());
+    for client_id in readers {
+        room.send(client_id, server_message.clone());
+    }
+    remember(&mut room.chat, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::room::Player;
+
+    fn room(players: &[(&str, &str, Option<&str>)]) -> Room {
+        let mut room = Room::new("Crypt".to_owned());
+        for (i, (user_id, name, character)) in players.iter().enumerate() {
+            let player = Player {
+                user_id: user_id.to_string(),
+                name: name.to_string(),
+                character: character.map(str::to_owned),
+                color: [0, 0, 0],
+            };
+            room.players.insert(ClientId::from_raw(i as u64), player);
+        }
+        room
+    }
+
+    fn whisper(to: &str, to_user: &str) -> ChatKind {
+        ChatKind::Whisper {
+            to: to.to_owned(),
+            to_user: to_user.to_owned(),
+        }
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse_command("hello /me"), Command::Say("hello /me"));
+        assert_eq!(parse_command("/me waves "), Command::Emote("waves"));
+        assert_eq!(parse_command("/w Bob hi"), Command::Whisper("Bob hi"));
+        assert_eq!(parse_command("/whisper Bob"), Command::Whisper("Bob"));
+        assert_eq!(parse_command("/gm psst"), Command::Gm("psst"));
+        assert_eq!(
+            parse_command("/r 1d20 + 2"),
+            Command::Roll {
+                expression: "1d20 + 2",
+                secret: false
+            }
+        );
+        assert_eq!(
+            parse_command("/gmroll 2d6"),
+            Command::Roll {
+                expression: "2d6",
+                secret: true
+            }
+        );
+        assert_eq!(parse_command("/me"), Command::Emote(""));
+        assert_eq!(parse_command("/dance now"), Command::Unknown("dance"));
+    }
+
+    #[test]
+    fn whispers_go_to_the_longest_matching_name() {
+        let room = room(&[
+            ("al", "Al", None),
+            ("smith", "Al Smith", None),
+            ("bob", "Bob", Some("Vex the Bold")),
+        ]);
+
+        assert_eq!(
+            whisper_target(&room, "Al Smith the door"),
+            Some((whisper("Al Smith", "smith"), "the door"))
+        );
+        assert_eq!(
+            whisper_target(&room, "al smithy is here"),
+            Some((whisper("Al", "al"), "smithy is here"))
+        );
+        assert_eq!(
+            whisper_target(&room, "vex the bold   run"),
+            Some((whisper("Bob", "bob"), "run"))
+        );
+        assert_eq!(
+            whisper_target(&room, "Bob"),
+            Some((whisper("Bob", "bob"), ""))
+        );
+        assert_eq!(whisper_target(&room, "Bobby hi"), None);
+        assert_eq!(whisper_target(&room, "Carol hi"), None);
+    }
+
+    #[test]
+    fn whispers_survive_names_that_are_not_ascii() {
+        let room = room(&[("zoe", "Zoë", None)]);
+        assert_eq!(whisper_target(&room, "Zo"), None);
+        assert_eq!(
+            whisper_target(&room, "ZOË hi"),
+            Some((whisper("Zoë", "zoe"), "hi"))
+        );
+    }
+
+    #[test]
+    fn limits_count_characters() {
+        assert!(!too_long(&"a".repeat(MAX_MESSAGE_LENGTH)));
+        assert!(too_long(&"a".repeat(MAX_MESSAGE_LENGTH + 1)));
+        assert!(!too_long(&"é".repeat(MAX_MESSAGE_LENGTH)));
+    }
+}
@@ -0,0 +1,500 @@
+use std::{fmt, iter::Peekable, str::Chars};
+
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer};
+use rand::{rngs::OsRng, Rng};
+use tyche_protocol::{
+    dice::{DieRoll, Roll, RollResult, TermResult},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    network::{send, ClientEvent},
+    room::{remember, unix_time, Room, Rooms},
+};
+
+const MAX_DICE: u32 = 100;
+const MAX_SIDES: u32 = 1000;
+const MAX_TERMS: usize = 20;
+
+pub struct DicePlugin;
+
+impl Plugin for DicePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, handle_roll);
+    }
+}
+
+fn handle_roll(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::Roll {
+            expression,
+            label,
+            secret,
+        } = message
+        else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            let error = ServerMessage::Error("Join a room before rolling".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        };
+
+        publish_roll(
+            room,
+            &mut server,
+            *client_id,
+            expression,
+            label.clone(),
+            *secret,
+        );
+    }
+}
+
+/// Rolls on behalf of a player and shares the result with the room, or with
+/// just the GM and the player when the roll is secret.
+pub fn publish_roll(
+    room: &mut Room,
+    server: &mut RenetServer,
+    client_id: ClientId,
+    expression: &str,
+    label: Option<String>,
+    secret: bool,
+) {
+    let result = match roll(expression, &mut OsRng) {
+        Ok(result) => result,
+        Err(error) => {
+            let error = ServerMessage::Error(format!("Cannot roll {expression}: {error}"));
+            send(server, client_id, &error);
+            return;
+        }
+    };
+
+    let player = &room.players[&client_id];
+    let roll = Roll {
+        client_id: client_id.raw(),
+        user_id: player.user_id.clone(),
+        player: player.name.clone(),
+        character: player.character.clone(),
+        label,
+        secret,
+        timestamp: unix_time(),
+        result,
+    };
+
+    share_roll(room, roll);
+}
+
+/// A d20 roll with a bonus, like `1d20 + 3`.
+pub fn d20(bonus: i32) -> String {
+    match bonus {
+        0 => "1d20".to_owned(),
+        bonus if bonus < 0 => format!("1d20 - {}", -bonus),
+        bonus => format!("1d20 + {bonus}"),
+    }
+}
+
+/// Sends a roll to everyone allowed to see it and keeps it in the history.
+pub fn share_roll(room: &mut Room, roll: Roll) {
+    let message = ServerMessage::Rolled(roll.clone());
+    if roll.secret {
+        let rollers: Vec<_> = room
+            .players
+            .keys()
+            .filter(|client_id| !room.is_gm(**client_id) && room.rolled(**client_id, &roll))
+            .copied()
+            .collect();
+        room.send_to_gm(&message);
+        for roller in rollers {
+            room.send(roller, message.clone());
+        }
+    } else {
+        room.broadcast(&message);
+    }
+    remember(&mut room.rolls, roll);
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DiceError {
+    Empty,
+    UnexpectedCharacter(char),
+    ExpectedNumber,
+    TooManyDice,
+    InvalidSides,
+    TooManyTerms,
+}
+
+impl fmt::Display for DiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiceError::Empty => write!(f, "the expression is empty"),
+            DiceError::UnexpectedCharacter(c) => write!(f, "unexpected character '{c}'"),
+            DiceError::ExpectedNumber => write!(f, "expected a number"),
+            DiceError::TooManyDice => write!(f, "at most {MAX_DICE} dice can be rolled at once"),
+            DiceError::InvalidSides => write!(f, "dice need between 1 and {MAX_SIDES} sides"),
+            DiceError::TooManyTerms => write!(f, "at most {MAX_TERMS} terms are allowed"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Keep {
+    Highest(u32),
+    Lowest(u32),
+}
+
+#[derive(Debug, PartialEq)]
+enum Term {
+    Dice {
+        negative: bool,
+        count: u32,
+        sides: u32,
+        keep: Option<Keep>,
+    },
+    Modifier(i64),
+}
+
+/// Rolls an expression such as `1d20 + 5`, `2d20kh1 - 1` or `8d6`.
+pub fn roll(expression: &str, rng: &mut impl Rng) -> Result<RollResult, DiceError> {
+    let terms = parse(expression)?
+        .into_iter()
+        .map(|term| evaluate(term, rng))
+        .collect::<Vec<_>>();
+
+    let total = terms
+        .iter()
+        .map(|term| match term {
+            TermResult::Dice {
+                negative, rolls, ..
+            } => {
+                let sum: i64 = rolls
+                    .iter()
+                    .filter(|roll| roll.kept)
+                    .map(|roll| roll.value as i64)
+                    .sum();
+                if *negative {
+                    -sum
+                } else {
+                    sum
+                }
+            }
+            TermResult::Modifier(value) => *value,
+        })
+        .sum();
+
+    Ok(RollResult {
+        expression: expression.to_owned(),
+        terms,
+        total,
+    })
+}
+
+fn evaluate(term: Term, rng: &mut impl Rng) -> TermResult {
+    match term {
+        Term::Dice {
+            negative,
+            count,
+            sides,
+            keep,
+        } => {
+            let mut rolls: Vec<DieRoll> = (0..count)
+                .map(|_| DieRoll {
+                    value: rng.gen_range(1..=sides),
+                    kept: true,
+                })
+                .collect();
+
+            if let Some(keep) = keep {
+                let mut order: Vec<usize> = (0..rolls.len()).collect();
+                let kept = match keep {
+                    Keep::Highest(kept) => {
+                        order.sort_by_key(|i| std::cmp::Reverse(rolls[*i].value));
+                        kept
+                    }
+                    Keep::Lowest(kept) => {
+                        order.sort_by_key(|i| rolls[*i].value);
+                        kept
+                    }
+                };
+                for i in order.into_iter().skip(kept as usize) {
+                    rolls[i].kept = false;
+                }
+            }
+
+            TermResult::Dice {
+                negative,
+                sides,
+                rolls,
+            }
+        }
+        Term::Modifier(value) => TermResult::Modifier(value),
+    }
+}
+
+fn parse(expression: &str) -> Result<Vec<Term>, DiceError> {
+    let expression = expression.to_lowercase().replace(char::is_whitespace, "");
+    let mut chars = expression.chars().peekable();
+    let mut terms = Vec::new();
+
+    if chars.peek().is_none() {
+        return Err(DiceError::Empty);
+    }
+
+    while chars.peek().is_some() {
+        if terms.len() == MAX_TERMS {
+            return Err(DiceError::TooManyTerms);
+        }
+
+        let negative = match chars.peek() {
+            Some('-') => {
+                chars.next();
+                true
+            }
+            Some('+') => {
+                chars.next();
+                false
+            }
+            Some(_) if terms.is_empty() => false,
+            Some(c) => return Err(DiceError::UnexpectedCharacter(*c)),
+            None => unreachable!(),
+        };
+
+        terms.push(parse_term(&mut chars, negative)?);
+    }
+
+    Ok(terms)
+}
+
+fn parse_term(chars: &mut Peekable<Chars>, negative: bool) -> Result<Term, DiceError> {
+    let count = parse_number(chars);
+
+    if chars.peek() != Some(&'d') {
+        let value = match (count, chars.peek()) {
+            (Some(count), _) => count as i64,
+            (None, Some(c)) => return Err(DiceError::UnexpectedCharacter(*c)),
+            (None, None) => return Err(DiceError::ExpectedNumber),
+        };
+        return Ok(Term::Modifier(if negative { -value } else { value }));
+    }
+    chars.next();
+
+    let count = count.unwrap_or(1);
+    let sides = match chars.peek() {
+        Some('%') => {
+            chars.next();
+            100
+        }
+        _ => parse_number(chars).ok_or(DiceError::ExpectedNumber)?,
+    };
+
+    if count > MAX_DICE {
+        return Err(DiceError::TooManyDice);
+    }
+    if sides == 0 || sides > MAX_SIDES {
+        return Err(DiceError::InvalidSides);
+    }
+
+    let keep = match chars.peek() {
+        Some('k') => {
+            chars.next();
+            let highest = match chars.next() {
+                Some('h') => true,
+                Some('l') => false,
+                Some(c) => return Err(DiceError::UnexpectedCharacter(c)),
+                None => return Err(DiceError::ExpectedNumber),
+            };
+            let kept = parse_number(chars).unwrap_or(1);
+            Some(if highest {
+                Keep::Highest(kept)
+            } else {
+                Keep::Lowest(kept)
+            })
+        }
+        _ => None,
+    };
+
+    Ok(Term::Dice {
+        negative,
+        count,
+        sides,
+        keep,
+    })
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let mut number: Option<u32> = None;
+
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        chars.next();
+        number = Some(number.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+    }
+
+    number
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::room::Player;
+
+    fn dice(negative: bool, count: u32, sides: u32, keep: Option<Keep>) -> Term {
+        Term::Dice {
+            negative,
+            count,
+            sides,
+            keep,
+        }
+    }
+
+    #[test]
+    fn parses_dice_and_modifiers() {
+        assert_eq!(
+            parse("1d20 + 5").unwrap(),
+            vec![dice(false, 1, 20, None), Term::Modifier(5)]
+        );
+        assert_eq!(
+            parse("D6-2").unwrap(),
+            vec![dice(false, 1, 6, None), Term::Modifier(-2)]
+        );
+        assert_eq!(parse("-3").unwrap(), vec![Term::Modifier(-3)]);
+        assert_eq!(parse("d%").unwrap(), vec![dice(false, 1, 100, None)]);
+        assert_eq!(
+            parse("2d20kh1 - 1d4").unwrap(),
+            vec![
+                dice(false, 2, 20, Some(Keep::Highest(1))),
+                dice(true, 1, 4, None)
+            ]
+        );
+        assert_eq!(
+            parse("4d6kl").unwrap(),
+            vec![dice(false, 4, 6, Some(Keep::Lowest(1)))]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        assert_eq!(parse(""), Err(DiceError::Empty));
+        assert_eq!(parse("   "), Err(DiceError::Empty));
+        assert_eq!(parse("1d"), Err(DiceError::ExpectedNumber));
+        assert_eq!(parse("1d20+"), Err(DiceError::ExpectedNumber));
+        assert_eq!(parse("1d20 * 2"), Err(DiceError::UnexpectedCharacter('*')));
+        assert_eq!(parse("x"), Err(DiceError::UnexpectedCharacter('x')));
+        assert_eq!(parse("2d20kx"), Err(DiceError::UnexpectedCharacter('x')));
+        assert_eq!(parse("2d20k"), Err(DiceError::ExpectedNumber));
+    }
+
+    #[test]
+    fn enforces_limits() {
+        assert!(parse("100d6").is_ok());
+        assert_eq!(parse("101d6"), Err(DiceError::TooManyDice));
+        assert_eq!(parse("99999999999d6"), Err(DiceError::TooManyDice));
+        assert!(parse("1d1000").is_ok());
+        assert_eq!(parse("1d1001"), Err(DiceError::InvalidSides));
+        assert_eq!(parse("1d0"), Err(DiceError::InvalidSides));
+
+        let terms = vec!["1"; MAX_TERMS].join("+");
+        assert!(parse(&terms).is_ok());
+        assert_eq!(parse(&(terms + "+1")), Err(DiceError::TooManyTerms));
+    }
+
+    #[test]
+    fn keeps_highest_and_lowest() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let result = roll("4d6kh3", &mut rng).unwrap();
+            let TermResult::Dice { rolls, .. } = &result.terms[0] else {
+                panic!("expected dice");
+            };
+            let dropped: Vec<_> = rolls.iter().filter(|roll| !roll.kept).collect();
+            assert_eq!(dropped.len(), 1);
+            assert!(rolls.iter().all(|roll| roll.value >= dropped[0].value));
+            let kept: i64 = rolls
+                .iter()
+                .filter(|r| r.kept)
+                .map(|r| r.value as i64)
+                .sum();
+            assert_eq!(result.total, kept);
+
+            let result = roll("2d20kl1 + 3", &mut rng).unwrap();
+            let TermResult::Dice { rolls, .. } = &result.terms[0] else {
+                panic!("expected dice");
+            };
+            let lowest = rolls.iter().map(|roll| roll.value).min().unwrap();
+            assert_eq!(result.total, lowest as i64 + 3);
+        }
+    }
+
+    #[test]
+    fn keeping_more_than_rolled_keeps_all() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let result = roll("2d6kh5", &mut rng).unwrap();
+        let TermResult::Dice { rolls, .. } = &result.terms[0] else {
+            panic!("expected dice");
+        };
+        assert!(rolls.iter().all(|roll| roll.kept));
+    }
+
+    #[test]
+    fn negative_dice_subtract() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let result = roll("-1d1 - 2", &mut rng).unwrap();
+        assert_eq!(result.total, -3);
+    }
+
+    fn player(user_id: &str) -> Player {
+        Player {
+            user_id: user_id.to_owned(),
+            name: user_id.to_owned(),
+            character: None,
+            color: [0, 0, 0],
+        }
+    }
+
+    #[test]
+    fn secret_rolls_follow_the_roller_across_connections() {
+        let [gm, alice, bob] = [1, 2, 3].map(ClientId::from_raw);
+        let mut room = Room::new("Crypt".to_owned());
+        room.players.insert(gm, player("gm"));
+        room.players.insert(alice, player("alice"));
+        room.players.insert(bob, player("bob"));
+        room.gm = Some(gm);
+
+        let mut rng = StdRng::seed_from_u64(5);
+        share_roll(
+            &mut room,
+            Roll {
+                client_id: alice.raw(),
+                user_id: "alice".to_owned(),
+                player: "alice".to_owned(),
+                character: None,
+                label: None,
+                secret: true,
+                timestamp: 0,
+                result: roll("1d20", &mut rng).unwrap(),
+            },
+        );
+        let rolled = |room: &Room, client_id| {
+            let queued = room.queued(client_id);
+            queued
+                .iter()
+                .any(|message| matches!(message, ServerMessage::Rolled(_)))
+        };
+        assert!(rolled(&room, gm) && rolled(&room, alice) && !rolled(&room, bob));
+
+        // Alice comes back on a new connection.
+        let again = ClientId::from_raw(4);
+        let alice = room.players.remove(&alice).unwrap();
+        room.players.insert(again, alice);
+        assert_eq!(room.visible_rolls(again).len(), 1);
+        assert_eq!(room.visible_rolls(gm).len(), 1);
+        assert!(room.visible_rolls(bob).is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+use bevy_renet::renet::RenetServer;
+use tyche_protocol::{drawing::Drawing, ClientMessage, ServerMessage};
+
+use crate::{
+    network::{send, ClientEvent},
+    room::Rooms,
+};
+
+/// Persistent drawings a single user may keep on the map.
+const MAX_DRAWINGS_PER_OWNER: usize = 200;
+const MAX_DRAWINGS_PER_ROOM: usize = 1000;
+
+pub struct DrawingPlugin;
+
+impl Plugin for DrawingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (handle_draw, handle_erase));
+    }
+}
+
+fn handle_draw(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::Draw {
+            shape,
+            color,
+            temporary,
+        } = message
+        else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        let Some(owner) = room.players.get(client_id).map(|p| p.user_id.clone()) else {
+            continue;
+        };
+        if !shape.is_valid() {
+            let error = ServerMessage::Error("That drawing is too big".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+        if !temporary {
+            let owned = room.drawings.values().filter(|d| d.owner == owner).count();
+            let full = if owned >= MAX_DRAWINGS_PER_OWNER {
+                Some("You have too many drawings, erase some first")
+            } else if room.drawings.len() >= MAX_DRAWINGS_PER_ROOM {
+                Some("The map has too many drawings, erase some first")
+            } else {
+                None
+            };
+            if let Some(full) = full {
+                let error = ServerMessage::Error(full.to_owned());
+                send(&mut server, *client_id, &error);
+                continue;
+            }
+        }
+
+        let drawing = Drawing {
+            id: room.next_drawing_id(),
+            owner,
+            color: *color,
+            temporary: *temporary,
+            shape: shape.clone(),
+        };
+        room.broadcast(&ServerMessage::Drawn(drawing.clone()));
+
+        // Temporary drawings fade away on their own, so nobody joining later needs them.
+        if !drawing.temporary {
+            room.drawings.insert(drawing.id, drawing);
+        }
+    }
+}
+
+fn handle_erase(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::Erase(id) = message else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        let Some(drawing) = room.drawings.get(id) else {
+            continue;
+        };
+
+        let is_owner = room
+            .players
+            .get(client_id)
+            .is_some_and(|player| player.user_id == drawing.owner);
+        if !is_owner && !room.is_gm(*client_id) {
+            let error = ServerMessage::Error("You can only erase your own drawings".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+
+        room.drawings.remove(id);
+        room.broadcast(&ServerMessage::Erased(*id));
+    }
+}
@@ -0,0 +1,126 @@
+use std::{env, sync::Arc};
+
+use bevy::{
+    prelude::*,
+    tasks::{IoTaskPool, Task},
+};
+use serde::Deserialize;
+use tyche_identity::{FirebaseUser, VerificationError, Verifier};
+
+/// For players whose profile can't be loaded.
+const FALLBACK_COLOR: [u8; 3] = [120, 120, 120];
+
+pub struct IdentityPlugin;
+
+impl Plugin for IdentityPlugin {
+    fn build(&self, app: &mut App) {
+        let identity = Identity {
+            verifier: Arc::new(Verifier::from_env()),
+            profiles: env::var("CHARACTER_SERVICE").ok(),
+        };
+        app.insert_resource(identity)
+            .add_systems(Startup, fetch_keys);
+    }
+}
+
+/// Checks the tokens players join with, against Firebase or the keys of
+/// tyche-auth in dev identity mode, and looks up how they show up to others
+/// in tyche-character.
+#[derive(Resource)]
+pub struct Identity {
+    verifier: Arc<Verifier>,
+    /// Where tyche-character is, without it players go by the name in their
+    /// token.
+    profiles: Option<String>,
+}
+
+/// A verified player, with the name and color from their profile.
+#[derive(Debug)]
+pub struct Member {
+    pub user_id: String,
+    pub name: String,
+    pub color: [u8; 3],
+}
+
+/// The part of a tyche-character profile the host cares about.
+#[derive(Deserialize)]
+struct Profile {
+    display_name: String,
+    color: [u8; 3],
+}
+
+impl Identity {
+    /// Verifies a token and loads the profile of its user on the IO task
+    /// pool, as fetching keys and profiles can take a while.
+    pub fn verify(&self, token: String) -> Task<Result<Member, VerificationError>> {
+        let verifier = self.verifier.clone();
+        let profiles = self.profiles.clone();
+        IoTaskPool::get().spawn(async move {
+            let user = verifier.verify(&token)?;
+            let profile = match profiles.map(|profiles| load_profile(&profiles, &token)) {
+                Some(Ok(profile)) => Some(profile),
+                Some(Err(error)) => {
+                    warn!("Could not load the profile of {}: {error}", user.user_id);
+                    None
+                }
+                None => None,
+            };
+            Ok(member(user, profile))
+        })
+    }
+}
+
+fn load_profile(profiles: &str, token: &str) -> reqwest::Result<Profile> {
+    reqwest::blocking::Client::new()
+        .get(format!("{}/profile", profiles.trim_end_matches('/')))
+        .bearer_auth(token)
+        .send()?
+        .error_for_status()?
+        .json()
+}
+
+/// Goes by the name in the token when there is no profile.
+fn member(user: FirebaseUser, profile: Option<Profile>) -> Member {
+    let (name, color) = match profile {
+        Some(profile) => (profile.display_name, profile.color),
+        None => {
+            let email_name = user
+                .email
+                .as_deref()
+                .and_then(|email| email.split('@').next());
+            let name = user
+                .name
+                .as_deref()
+                .or(email_name)
+                .filter(|name| !name.trim().is_empty())
+                .unwrap_or("Guest");
+            (name.to_owned(), FALLBACK_COLOR)
+        }
+    };
+
+    Member {
+        user_id: user.user_id,
+        name,
+        color,
+    }
+}
+
+/// Gets the keys up front, so the first player to join does not wait for
+/// them to be fetched.
+fn fetch_keys(identity: Res<Identity>) {
+    let config = identity.verifier.config();
+    if config.is_dev() {
+        info!("Dev identity mode, trusting {}", config.jwk_url);
+    }
+    if identity.profiles.is_none() {
+        warn!("CHARACTER_SERVICE is not set, players go by the names in their tokens");
+    }
+    let verifier = identity.verifier.clone();
+    IoTaskPool::get()
+        .spawn(async move {
+            if let Err(error) = verifier.refresh() {
+                warn!("Could not fetch the keys to verify players with: {error:?}");
+            }
+        })
+        .detach();
+}
@@ -0,0 +1,431 @@
+use std::{cmp::Ordering, collections::BTreeMap};
+
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer};
+use rand::{rngs::OsRng, Rng};
+use tyche_protocol::{
+    dice::Roll,
+    initiative::{Combatant, Initiative, InitiativeCommand, TieBreak},
+    token::{Token, TokenId},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    dice::{d20, roll, share_roll},
+    network::{send, ClientEvent},
+    room::{unix_time, Room, Rooms},
+    vision::share_token,
+};
+
+pub struct InitiativePlugin;
+
+impl Plugin for InitiativePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, handle_initiative);
+    }
+}
+
+fn handle_initiative(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::Initiative(command) = message else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        if !may_run(room, *client_id, command) {
+            let error = ServerMessage::Error("Only the GM can do that".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+
+        let round = room.initiative.round;
+        run(room, command);
+        if round != 0 && room.initiative.round > round {
+            next_round(room);
+        }
+        let initiative = ServerMessage::Initiative(room.initiative.clone());
+        room.broadcast(&initiative);
+    }
+}
+
+/// Condition durations count down as rounds go by.
+fn next_round(room: &mut Room) {
+    let changed: Vec<TokenId> = room
+        .tokens
+        .values_mut()
+        .filter_map(|token| token.next_round().then_some(token.id))
+        .collect();
+
+    for token in changed {
+        share_token(room, token);
+    }
+}
+
+/// Players may end their own turn and delay their own tokens, everything else
+/// is up to the GM.
+fn may_run(room: &Room, client_id: ClientId, command: &InitiativeCommand) -> bool {
+    if room.is_gm(client_id) {
+        return true;
+    }
+
+    match command {
+        InitiativeCommand::Next => room
+            .initiative
+            .active()
+            .is_some_and(|combatant| room.controls(client_id, combatant.token)),
+        InitiativeCommand::Delay(token) | InitiativeCommand::Resume(token) => {
+            room.controls(client_id, *token)
+        }
+        _ => false,
+    }
+}
+
+fn run(room: &mut Room, command: &InitiativeCommand) {
+    match command {
+        InitiativeCommand::Add(token) => {
+            let in_tracker = room.initiative.combatants.iter().any(|c| c.token == *token);
+            if let (Some(token), false) = (room.tokens.get(token), in_tracker) {
+                room.initiative.combatants.push(Combatant {
+                    token: token.id,
+                    name: token.name.clone(),
+                    bonus: token.initiative_bonus,
+                    initiative: None,
+                    delayed: false,
+                    tie: OsRng.gen(),
+                });
+            }
+        }
+        InitiativeCommand::Remove(token) => {
+            remove_combatant(&mut room.initiative, *token);
+        }
+        InitiativeCommand::Set { token, initiative } => {
+            if let Some(combatant) = combatant_mut(&mut room.initiative, *token) {
+                combatant.initiative = Some(*initiative);
+            }
+        }
+        InitiativeCommand::RollAll => {
+            roll_all(room);
+            sort(&mut room.initiative, &room.tokens);
+        }
+        InitiativeCommand::Sort => sort(&mut room.initiative, &room.tokens),
+        InitiativeCommand::SetTieBreak(tie_break) => {
+            room.initiative.tie_break = *tie_break;
+            sort(&mut room.initiative, &room.tokens);
+        }
+        InitiativeCommand::Start => {
+            room.initiative.round = 1;
+            room.initiative.turn = 0;
+            if room.initiative.active().is_some_and(|c| c.delayed) {
+                advance(&mut room.initiative);
+            }
+        }
+        InitiativeCommand::Next => advance(&mut room.initiative),
+        InitiativeCommand::Delay(token) => {
+            let active = room.initiative.active().map(|c| c.token);
+            if let Some(combatant) = combatant_mut(&mut room.initiative, *token) {
+                combatant.delayed = true;
+            }
+            if active == Some(*token) {
+                advance(&mut room.initiative);
+            }
+        }
+        InitiativeCommand::Resume(token) => resume(&mut room.initiative, *token),
+        InitiativeCommand::Clear => {
+            room.initiative = Initiative {
+                tie_break: room.initiative.tie_break,
+                ..default()
+            };
+        }
+    }
+}
+
+fn combatant_mut(initiative: &mut Initiative, token: TokenId) -> Option<&mut Combatant> {
+    initiative
+        .combatants
+        .iter_mut()
+        .find(|combatant| combatant.token == token)
+}
+
+/// Returns whether the token was part of the tracker.
+pub fn remove_combatant(initiative: &mut Initiative, token: TokenId) -> bool {
+    let Some(index) = initiative.combatants.iter().position(|c| c.token == token) else {
+        return false;
+    };
+
+    initiative.combatants.remove(index);
+    if index < initiative.turn {
+        initiative.turn -= 1;
+    }
+    if initiative.turn >= initiative.combatants.len() {
+        initiative.turn = 0;
+    }
+    true
+}
+
+/// Rolls initiative for every combatant that does not have one yet.
+fn roll_all(room: &mut Room) {
+    for index in 0..room.initiative.combatants.len() {
+        let combatant = &room.initiative.combatants[index];
+        if combatant.initiative.is_some() {
+            continue;
+        }
+
+        let Ok(result) = roll(&d20(combatant.bonus), &mut OsRng) else {
+            continue;
+        };
+
+        let roll = Roll {
+            client_id: 0,
+            user_id: String::new(),
+            player: room.controller_name(combatant.token),
+            character: Some(combatant.name.clone()),
+            label: Some("Initiative".to_owned()),
+            secret: false,
+            timestamp: unix_time(),
+            result,
+        };
+
+        room.initiative.combatants[index].initiative = Some(roll.result.total);
+        share_roll(room, roll);
+    }
+}
+
+fn sort(initiative: &mut Initiative, tokens: &BTreeMap<TokenId, Token>) {
+    let active = initiative.active().map(|combatant| combatant.token);
+    let is_player = |combatant: &Combatant| {
+        tokens
+            .get(&combatant.token)
+            .is_some_and(|token| token.owner.is_some())
+    };
+    let mode = initiative.tie_break;
+    let tie_break = |a: &Combatant, b: &Combatant| -> Ordering {
+        match mode {
+            TieBreak::Bonus => b.bonus.cmp(&a.bonus).then(b.tie.cmp(&a.tie)),
+            TieBreak::PlayersFirst => is_player(b).cmp(&is_player(a)).then(b.bonus.cmp(&a.bonus)),
+            TieBreak::Random => b.tie.cmp(&a.tie),
+        }
+    };
+
+    initiative
+        .combatants
+        .sort_by(|a, b| b.initiative.cmp(&a.initiative).then(tie_break(a, b)));
+
+    // Sorting mid-combat should not change whose turn it is.
+    if let Some(active) = active {
+        initiative.turn = initiative
+            .combatants
+            .iter()
+            .position(|combatant| combatant.token == active)
+            .unwrap_or(0);
+    }
+}
+
+/// Moves to the next combatant that is not delaying, starting a new round
+/// when the end of the order is reached.
+fn advance(initiative: &mut Initiative) {
+    if initiative.combatants.is_empty() {
+        return;
+    }
+    if initiative.round == 0 {
+        initiative.round = 1;
+        initiative.turn = 0;
+        return;
+    }
+
+    for _ in 0..initiative.combatants.len() {
+        initiative.turn += 1;
+        if initiative.turn >= initiative.combatants.len() {
+            initiative.turn = 0;
+            initiative.round += 1;
+        }
+        if !initiative.combatants[initiative.turn].delayed {
+            return;
+        }
+    }
+}
+
+/// A delayed combatant rejoins the order by acting right now.
+fn resume(initiative: &mut Initiative, token: TokenId) {
+    let Some(index) = initiative.combatants.iter().position(|c| c.token == token) else {
+        return;
+    };
+
+    let mut combatant = initiative.combatants.remove(index);
+    combatant.delayed = false;
+    if index < initiative.turn {
+        initiative.turn -= 1;
+    }
+
+    // Take over the initiative of whoever is up so sorting keeps the new order.
+    let turn = initiative.turn.min(initiative.combatants.len());
+    if let Some(current) = initiative.combatants.get(turn) {
+        combatant.initiative = current.initiative;
+    }
+    initiative.combatants.insert(turn, combatant);
+    initiative.turn = turn;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn combatant(token: TokenId, initiative: i64, bonus: i32, tie: u32) -> Combatant {
+        Combatant {
+            token,
+            name: format!("Token {token}"),
+            bonus,
+            initiative: Some(initiative),
+            delayed: false,
+            tie,
+        }
+    }
+
+    fn order(initiative: &Initiative) -> Vec<TokenId> {
+        initiative.combatants.iter().map(|c| c.token).collect()
+    }
+
+    fn tracker(combatants: Vec<Combatant>, tie_break: TieBreak) -> Initiative {
+        Initiative {
+            combatants,
+            tie_break,
+            ..default()
+        }
+    }
+
+    #[test]
+    fn sorts_highest_first() {
+        let mut initiative = tracker(
+            vec![
+                combatant(1, 8, 0, 0),
+                combatant(2, 20, 0, 0),
+                combatant(3, 13, 0, 0),
+            ],
+            TieBreak::Bonus,
+        );
+        sort(&mut initiative, &BTreeMap::new());
+        assert_eq!(order(&initiative), [2, 3, 1]);
+    }
+
+    #[test]
+    fn unrolled_combatants_go_last() {
+        let mut unrolled = combatant(1, 0, 5, 0);
+        unrolled.initiative = None;
+        let mut initiative = tracker(vec![unrolled, combatant(2, 1, 0, 0)], TieBreak::Bonus);
+        sort(&mut initiative, &BTreeMap::new());
+        assert_eq!(order(&initiative), [2, 1]);
+    }
+
+    #[test]
+    fn ties_break_on_bonus() {
+        let mut initiative = tracker(
+            vec![combatant(1, 15, 1, 9), combatant(2, 15, 4, 0)],
+            TieBreak::Bonus,
+        );
+        sort(&mut initiative, &BTreeMap::new());
+        assert_eq!(order(&initiative), [2, 1]);
+    }
+
+    #[test]
+    fn ties_break_for_players_first() {
+        let mut tokens = BTreeMap::new();
+        let mut player = Token::new("Player".to_owned(), 0.0, 0.0);
+        player.owner = Some("alice".to_owned());
+        tokens.insert(1, player);
+        tokens.insert(2, Token::new("Goblin".to_owned(), 0.0, 0.0));
+
+        let mut initiative = tracker(
+            vec![combatant(2, 12, 3, 0), combatant(1, 12, 0, 0)],
+            TieBreak::PlayersFirst,
+        );
+        sort(&mut initiative, &tokens);
+        assert_eq!(order(&initiative), [1, 2]);
+    }
+
+    #[test]
+    fn ties_break_at_random() {
+        let mut initiative = tracker(
+            vec![combatant(1, 10, 5, 3), combatant(2, 10, 0, 7)],
+            TieBreak::Random,
+        );
+        sort(&mut initiative, &BTreeMap::new());
+        assert_eq!(order(&initiative), [2, 1]);
+    }
+
+    #[test]
+    fn sorting_keeps_the_active_turn() {
+        let mut initiative = tracker(
+            vec![combatant(1, 5, 0, 0), combatant(2, 18, 0, 0)],
+            TieBreak::Bonus,
+        );
+        initiative.round = 1;
+        initiative.turn = 0;
+        sort(&mut initiative, &BTreeMap::new());
+        assert_eq!(initiative.active().map(|c| c.token), Some(1));
+        assert_eq!(initiative.turn, 1);
+    }
+
+    #[test]
+    fn advance_starts_the_first_round() {
+        let mut initiative = tracker(vec![combatant(1, 5, 0, 0)], TieBreak::Bonus);
+        advance(&mut initiative);
+        assert_eq!((initiative.round, initiative.turn), (1, 0));
+    }
+
+    #[test]
+    fn advance_wraps_into_the_next_round() {
+        let mut initiative = tracker(
+            vec![combatant(1, 20, 0, 0), combatant(2, 10, 0, 0)],
+            TieBreak::Bonus,
+        );
+        initiative.round = 1;
+        advance(&mut initiative);
+        assert_eq!((initiative.round, initiative.turn), (1, 1));
+        advance(&mut initiative);
+        assert_eq!((initiative.round, initiative.turn), (2, 0));
+    }
+
+    #[test]
+    fn advance_skips_delayed_combatants() {
+        let mut delayed = combatant(2, 15, 0, 0);
+        delayed.delayed = true;
+        let mut initiative = tracker(
+            vec![combatant(1, 20, 0, 0), delayed, combatant(3, 10, 0, 0)],
+            TieBreak::Bonus,
+        );
+        initiative.round = 1;
+        advance(&mut initiative);
+        assert_eq!(initiative.active().map(|c| c.token), Some(3));
+    }
+
+    #[test]
+    fn advance_with_everyone_delayed_stops() {
+        let mut initiative = tracker(vec![combatant(1, 20, 0, 0)], TieBreak::Bonus);
+        initiative.combatants[0].delayed = true;
+        initiative.round = 1;
+        advance(&mut initiative);
+        assert_eq!((initiative.round, initiative.turn), (2, 0));
+    }
+
+    #[test]
+    fn resume_acts_immediately() {
+        let mut delayed = combatant(1, 20, 0, 0);
+        delayed.delayed = true;
+        let mut initiative = tracker(
+            vec![delayed, combatant(2, 15, 0, 0), combatant(3, 10, 0, 0)],
+            TieBreak::Bonus,
+        );
+        initiative.round = 1;
+        initiative.turn = 2;
+        resume(&mut initiative, 1);
+        assert_eq!(order(&initiative), [2, 1, 3]);
+        assert_eq!(initiative.active().map(|c| c.token), Some(1));
+        assert_eq!(initiative.combatants[1].initiative, Some(10));
+        assert!(!initiative.combatants[1].delayed);
+    }
+}
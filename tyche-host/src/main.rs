@@ -1,28 +1,67 @@
 #![allow(clippy::type_complexity)]
+mod archive;
+mod asset_store;
+mod chat;
+mod dice;
+mod drawing;
+mod identity;
+mod initiative;
+mod map;
+mod network;
+mod room;
+mod save;
+mod template;
+mod token;
+mod vision;
+
+use archive::ArchivePlugin;
+use asset_store::AssetStorePlugin;
 use bevy::{log::LogPlugin, prelude::*};
-use bevy_renet::{renet::*, *};
+use bevy_renet::{
+    renet::{ConnectionConfig, RenetServer, ServerEvent},
+    transport::NetcodeServerPlugin,
+    RenetServerPlugin,
+};
+use chat::ChatPlugin;
+use dice::DicePlugin;
+use drawing::DrawingPlugin;
+use identity::IdentityPlugin;
+use initiative::InitiativePlugin;
+use map::MapPlugin;
+use network::NetworkPlugin;
+use room::RoomPlugin;
+use save::SavePlugin;
+use template::TemplatePlugin;
+use token::TokenPlugin;
+use vision::VisionPlugin;
 
 fn main() {
     App::new()
         .add_plugins(MinimalPlugins)
         .add_plugins(LogPlugin::default())
-        .add_plugins(RenetServerPlugin)
+        .add_plugins((RenetServerPlugin, NetcodeServerPlugin))
         .insert_resource(RenetServer::new(ConnectionConfig::default()))
+        .insert_resource(network::create_transport())
+        .add_plugins((
+            NetworkPlugin,
+            IdentityPlugin,
+            AssetStorePlugin,
+            RoomPlugin,
+            DicePlugin,
+            ChatPlugin,
+            TokenPlugin,
+            InitiativePlugin,
+            MapPlugin,
+            VisionPlugin,
+            DrawingPlugin,
+            TemplatePlugin,
+            SavePlugin,
+            ArchivePlugin,
+        ))
         .add_systems(Update, handle_events_system)
-        .add_systems(Update, receive_message_system)
         .run();
 }
 
-fn receive_message_system(mut server: ResMut<RenetServer>) {
-    // Receive message from all clients
-    for client_id in server.clients_id() {
-        while let Some(message) = server.receive_message(client_id, DefaultChannel::ReliableOrdered)
-        {
-            // Handle received message
-        }
-    }
-}
-
 fn handle_events_system(mut server_events: EventReader<ServerEvent>) {
     for event in server_events.read() {
         match event {
@@ -0,0 +1,177 @@
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer};
+use tyche_protocol::{
+    fog::FogMask,
+    map::{Calibration, MapId, MapInfo},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    asset_store::AssetStore,
+    network::{send, ClientEvent},
+    room::{Room, Rooms},
+    vision::update_vision,
+};
+
+pub struct MapPlugin;
+
+impl Plugin for MapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (handle_set_map, handle_calibrate_map, handle_paint_fog),
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct Map {
+    pub info: MapInfo,
+    pub image: Vec<u8>,
+    pub fog: FogMask,
+}
+
+impl Map {
+    pub fn fog_message(&self) -> ServerMessage {
+        ServerMessage::FogMask {
+            map: self.info.id,
+            mask: self.fog.compress(),
+        }
+    }
+}
+
+/// Sends the fog of the room's map to a client, which requests the image
+/// itself unless it has it cached.
+pub fn share_map(room: &mut Room, client_id: ClientId) {
+    if let Some(message) = room.map.as_ref().map(Map::fog_message) {
+        room.send(client_id, message);
+    }
+}
+
+fn handle_set_map(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    store: Res<AssetStore>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::SetMap { name, asset } = message else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        if !room.is_gm(*client_id) {
+            let error = ServerMessage::Error("Only the GM can change the map".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+        let Some((info, image)) = store.info(asset).zip(store.read(asset)) else {
+            let error = ServerMessage::Error("Upload the map image first".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        };
+
+        let map = Map {
+            info: MapInfo {
+                id: next_map_id(room),
+                name: name.clone(),
+                asset: info,
+                calibration: Calibration::default(),
+            },
+            image,
+            fog: FogMask::default(),
+        };
+        info!(
+            "{} changed the map of {} to {}",
+            client_id, room.name, map.info.name
+        );
+
+        room.broadcast(&ServerMessage::MapChanged(map.info.clone()));
+        room.map = Some(map);
+        let clients: Vec<ClientId> = room.players.keys().copied().collect();
+        for client_id in clients {
+            share_map(room, client_id);
+        }
+        update_vision(room);
+    }
+}
+
+fn next_map_id(room: &Room) -> MapId {
+    room.map.as_ref().map_or(0, |map| map.info.id + 1)
+}
+
+fn handle_calibrate_map(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::CalibrateMap(calibration) = message else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        if !room.is_gm(*client_id) {
+            let error = ServerMessage::Error("Only the GM can calibrate the map".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+        if !calibration.is_valid() {
+            let error = ServerMessage::Error("Invalid grid calibration".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+
+        let Some(map) = &mut room.map else {
+            continue;
+        };
+        map.info.calibration = *calibration;
+
+        let message = ServerMessage::MapCalibrated {
+            map: map.info.id,
+            calibration: *calibration,
+        };
+        room.broadcast(&message);
+    }
+}
+
+fn handle_paint_fog(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::PaintFog(stroke) = message else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        if !room.is_gm(*client_id) {
+            let error = ServerMessage::Error("Only the GM can paint fog".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+        if !stroke.is_valid() {
+            continue;
+        }
+        let Some(map) = &mut room.map else {
+            let error = ServerMessage::Error("There is no map to paint fog on".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        };
+
+        map.fog.apply(stroke);
+        let message = ServerMessage::FogPainted {
+            map: map.info.id,
+            stroke: stroke.clone(),
+        };
+        room.broadcast(&message);
+        update_vision(room);
+    }
+}
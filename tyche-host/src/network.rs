@@ -0,0 +1,112 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    net::{SocketAddr, UdpSocket},
+    time::SystemTime,
+};
+
+use bevy::prelude::*;
+use bevy_renet::renet::{
+    transport::{NetcodeServerTransport, ServerAuthentication, ServerConfig},
+    ClientId, DefaultChannel, RenetServer, ServerEvent,
+};
+use tyche_protocol::{ClientMessage, ServerMessage, PROTOCOL_ID};
+
+const MAX_CLIENTS: usize = 64;
+/// How many queued transfer messages each client gets per fixed tick.
+const TRANSFERS_PER_TICK: usize = 2;
+
+pub struct NetworkPlugin;
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ClientEvent>()
+            .insert_resource(Transfers::default())
+            .add_systems(Update, (receive_message_system, drop_transfers))
+            .add_systems(FixedUpdate, flush_transfers);
+    }
+}
+
+#[derive(Event)]
+pub struct ClientEvent {
+    pub client_id: ClientId,
+    pub message: ClientMessage,
+}
+
+/// Bulk messages, like map chunks, that are trickled out to clients
+/// instead of flooding the channel in a single frame.
+#[derive(Debug, Default, Resource)]
+pub struct Transfers(HashMap<ClientId, VecDeque<ServerMessage>>);
+
+impl Transfers {
+    pub fn queue(
+        &mut self,
+        client_id: ClientId,
+        messages: impl IntoIterator<Item = ServerMessage>,
+    ) {
+        self.0.entry(client_id).or_default().extend(messages);
+    }
+}
+
+pub fn create_transport() -> NetcodeServerTransport {
+    let public_addr: SocketAddr = env::var("HOST_ADDR")
+        .unwrap_or("127.0.0.1:5000".to_string())
+        .parse()
+        .unwrap();
+    let socket = UdpSocket::bind(public_addr).unwrap();
+    let current_time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+
+    let server_config = ServerConfig {
+        current_time,
+        max_clients: MAX_CLIENTS,
+        protocol_id: PROTOCOL_ID,
+        public_addresses: vec![public_addr],
+        authentication: ServerAuthentication::Unsecure,
+    };
+
+    NetcodeServerTransport::new(server_config, socket).unwrap()
+}
+
+pub fn send(server: &mut RenetServer, client_id: ClientId, message: &ServerMessage) {
+    server.send_message(
+        client_id,
+        DefaultChannel::ReliableOrdered,
+        tyche_protocol::encode(message),
+    );
+}
+
+fn receive_message_system(
+    mut server: ResMut<RenetServer>,
+    mut ev_client: EventWriter<ClientEvent>,
+) {
+    // Receive message from all clients
+    for client_id in server.clients_id() {
+        while let Some(message) = server.receive_message(client_id, DefaultChannel::ReliableOrdered)
+        {
+            match tyche_protocol::decode(&message) {
+                Some(message) => ev_client.send(ClientEvent { client_id, message }),
+                None => warn!("Client {client_id} sent a malformed message"),
+            }
+        }
+    }
+}
+
+fn flush_transfers(mut server: ResMut<RenetServer>, mut transfers: ResMut<Transfers>) {
+    for (client_id, queue) in transfers.0.iter_mut() {
+        let count = queue.len().min(TRANSFERS_PER_TICK);
+        for message in queue.drain(..count) {
+            send(&mut server, *client_id, &message);
+        }
+    }
+    transfers.0.retain(|_, queue| !queue.is_empty());
+}
+
+fn drop_transfers(mut server_events: EventReader<ServerEvent>, mut transfers: ResMut<Transfers>) {
+    for event in server_events.read() {
+        if let ServerEvent::ClientDisconnected { client_id, .. } = event {
+            transfers.0.remove(client_id);
+        }
+    }
+}
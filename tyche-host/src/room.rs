@@ -0,0 +1,750 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    time::{Duration, Instant, SystemTime},
+};
+
+use bevy::{
+    prelude::*,
+    tasks::{block_on, Task},
+};
+use bevy_renet::renet::{ClientId, RenetServer, ServerEvent};
+use tyche_identity::VerificationError;
+use tyche_protocol::{
+    asset::AssetHash,
+    chat::{ChatKind, ChatMessage},
+    dice::Roll,
+    drawing::{Drawing, DrawingId},
+    grid::Grid,
+    initiative::Initiative,
+    map::CELL_SIZE,
+    template::{Template, TemplateId},
+    token::{HealthView, HealthVisibility, Token, TokenId},
+    vision::{Point, Wall, WallId, WallKind},
+    CharacterInfo, ClientMessage, PlayerInfo, ServerMessage,
+};
+
+use crate::{
+    asset_store::AssetStore,
+    identity::{Identity, Member},
+    map::{share_map, Map},
+    network::{send, ClientEvent},
+    vision::{share_token, update_vision, visible_tokens},
+};
+
+/// How long the GM role is kept for a GM who dropped out, so a Wi-Fi blip
+/// does not hand it to whoever joins next.
+const GM_RESERVED_FOR: Duration = Duration::from_secs(5 * 60);
+/// How many rolls and chat messages a room keeps, and sends to players who
+/// join it.
+const HISTORY_LENGTH: usize = 200;
+
+pub struct RoomPlugin;
+
+impl Plugin for RoomPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Rooms::default())
+            .insert_resource(Joining::default())
+            .add_systems(
+                Update,
+                (
+                    handle_join_room,
+                    finish_joins,
+                    handle_request_snapshot,
+                    handle_disconnect,
+                ),
+            )
+            .add_systems(PostUpdate, flush_deltas);
+    }
+}
+
+#[derive(Debug)]
+pub struct Player {
+    pub user_id: String,
+    pub name: String,
+    pub character: Option<String>,
+    pub color: [u8; 3],
+}
+
+#[derive(Debug)]
+pub struct Room {
+    pub name: String,
+    pub gm: Option<ClientId>,
+    /// The user the GM role belongs to, kept while they are away.
+    gm_user: Option<String>,
+    gm_left: Option<Instant>,
+    pub players: HashMap<ClientId, Player>,
+    pub rolls: Vec<Roll>,
+    pub chat: Vec<ChatMessage>,
+    pub tokens: BTreeMap<TokenId, Token>,
+    pub initiative: Initiative,
+    pub map: Option<Map>,
+    pub walls: BTreeMap<WallId, Wall>,
+    pub fog: bool,
+    /// The tokens each player has been told about, see [`update_vision`].
+    pub known_tokens: HashMap<ClientId, HashSet<TokenId>>,
+    pub drawings: BTreeMap<DrawingId, Drawing>,
+    pub templates: BTreeMap<TemplateId, Template>,
+    /// Bumped at the end of every tick in which the room changed.
+    pub version: u64,
+    /// Changes waiting to be sent to each player at the end of the tick.
+    deltas: HashMap<ClientId, Vec<ServerMessage>>,
+    /// The sequence number of the last delta each player was sent.
+    sequences: HashMap<ClientId, u64>,
+    next_token_id: TokenId,
+    next_wall_id: WallId,
+    next_drawing_id: DrawingId,
+    next_template_id: TemplateId,
+}
+
+impl Room {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            gm: None,
+            gm_user: None,
+            gm_left: None,
+            players: HashMap::new(),
+            rolls: Vec::new(),
+            chat: Vec::new(),
+            tokens: BTreeMap::new(),
+            initiative: Initiative::default(),
+            map: None,
+            walls: BTreeMap::new(),
+            fog: false,
+            known_tokens: HashMap::new(),
+            drawings: BTreeMap::new(),
+            templates: BTreeMap::new(),
+            version: 0,
+            deltas: HashMap::new(),
+            sequences: HashMap::new(),
+            next_token_id: 0,
+            next_wall_id: 0,
+            next_drawing_id: 0,
+            next_template_id: 0,
+        }
+    }
+
+    /// Continues numbering after the highest ids in use, for rooms that were
+    /// filled in from a save.
+    pub fn renumber(&mut self) {
+        let next = |last: Option<&u64>| last.map_or(0, |id| id + 1);
+        self.next_token_id = next(self.tokens.keys().last());
+        self.next_wall_id = next(self.walls.keys().last());
+        self.next_drawing_id = next(self.drawings.keys().last());
+        self.next_template_id = next(self.templates.keys().last());
+    }
+
+    pub fn is_gm(&self, client_id: ClientId) -> bool {
+        self.gm == Some(client_id)
+    }
+
+    /// Whether a user joining the room gets to be its GM, either because
+    /// it is theirs or because nobody has claimed it lately.
+    fn may_become_gm(&self, user_id: &str) -> bool {
+        let reserved = self.gm_user.as_ref().is_some_and(|gm| gm != user_id)
+            && self
+                .gm_left
+                .is_some_and(|left| left.elapsed() < GM_RESERVED_FOR);
+        self.gm.is_none() && !reserved
+    }
+
+    /// The GM controls every token, players only those they own.
+    pub fn controls(&self, client_id: ClientId, token: TokenId) -> bool {
+        if self.is_gm(client_id) {
+            return true;
+        }
+
+        let Some(player) = self.players.get(&client_id) else {
+            return false;
+        };
+        self.tokens
+            .get(&token)
+            .is_some_and(|token| token.owner.as_ref() == Some(&player.user_id))
+    }
+
+    /// A token as a client gets to see it, only the GM and its owner know
+    /// everything about it.
+    pub fn token_for(&self, client_id: ClientId, token: &Token) -> Token {
+        match self.controls(client_id, token.id) {
+            true => token.clone(),
+            false => token.redacted(),
+        }
+    }
+
+    pub fn spawn_token(&mut self, token: Token) -> Token {
+        let [x, y] = self.grid().snap([token.x, token.y]);
+        let token = Token {
+            id: self.next_token_id,
+            x,
+            y,
+            ..token
+        };
+
+        self.next_token_id += 1;
+        self.tokens.insert(token.id, token.clone());
+        token
+    }
+
+    /// Copies the stats of a player's character onto the tokens that follow its
+    /// sheet, returning those tokens. Hit points that are out of range are
+    /// left as they were.
+    pub fn update_linked_tokens(
+        &mut self,
+        owner: &str,
+        character: &CharacterInfo,
+        health: bool,
+    ) -> Vec<TokenId> {
+        self.tokens
+            .values_mut()
+            .filter(|token| {
+                token.owner.as_deref() == Some(owner) && token.character == Some(character.id)
+            })
+            .map(|token| {
+                token.name = character.name.clone();
+                token.initiative_bonus = character.initiative_bonus;
+                token.saves = character.saves;
+                token.image = character.token_image;
+                if health && character.health.is_valid() {
+                    token.health = HealthView::Full(character.health);
+                }
+                token.id
+            })
+            .collect()
+    }
+
+    pub fn add_wall(&mut self, kind: WallKind, start: Point, end: Point) -> Wall {
+        let wall = Wall {
+            id: self.next_wall_id,
+            kind,
+            start,
+            end,
+        };
+
+        self.next_wall_id += 1;
+        self.walls.insert(wall.id, wall.clone());
+        wall
+    }
+
+    pub fn next_drawing_id(&mut self) -> DrawingId {
+        self.next_drawing_id += 1;
+        self.next_drawing_id - 1
+    }
+
+    pub fn next_template_id(&mut self) -> TemplateId {
+        self.next_template_id += 1;
+        self.next_template_id - 1
+    }
+
+    pub fn grid(&self) -> Grid {
+        self.map
+            .as_ref()
+            .map_or(Grid::default(), |map| map.info.calibration.grid)
+    }
+
+    /// The assets the room uses, which its players may download.
+    pub fn assets(&self) -> HashSet<AssetHash> {
+        let tokens = self.tokens.values().filter_map(|token| token.image);
+        self.map
+            .iter()
+            .map(|map| map.info.asset)
+            .chain(tokens)
+            .map(|asset| asset.hash)
+            .collect()
+    }
+
+    pub fn player_info(&self, client_id: ClientId) -> Option<PlayerInfo> {
+        let player = self.players.get(&client_id)?;
+        Some(PlayerInfo {
+            client_id: client_id.raw(),
+            user_id: player.user_id.clone(),
+            name: player.name.clone(),
+            character: player.character.clone(),
+            color: player.color,
+            is_gm: self.is_gm(client_id),
+        })
+    }
+
+    /// The name of the player who owns a token, tokens without one belong to the GM.
+    pub fn controller_name(&self, token: TokenId) -> String {
+        self.tokens
+            .get(&token)
+            .and_then(|token| token.owner.as_ref())
+            .and_then(|owner| self.players.values().find(|p| p.user_id == *owner))
+            .map_or("GM".to_owned(), |player| player.name.clone())
+    }
+
+    /// Queues a change for a player, all of a tick's changes are sent
+    /// together as one delta.
+    pub fn send(&mut self, client_id: ClientId, message: ServerMessage) {
+        self.deltas.entry(client_id).or_default().push(message);
+    }
+
+    /// The changes queued for a player this tick.
+    #[cfg(test)]
+    pub fn queued(&self, client_id: ClientId) -> &[ServerMessage] {
+        self.deltas.get(&client_id).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn broadcast(&mut self, message: &ServerMessage) {
+        for client_id in self.players.keys() {
+            let deltas = self.deltas.entry(*client_id).or_default();
+            deltas.push(message.clone());
+        }
+    }
+
+    pub fn send_to_gm(&mut self, message: &ServerMessage) {
+        if let Some(gm) = self.gm {
+            self.send(gm, message.clone());
+        }
+    }
+
+    /// Everything a player may know about the room, which later deltas
+    /// build on.
+    pub fn snapshot(&mut self, client_id: ClientId) -> ServerMessage {
+        let visible = visible_tokens(self, client_id);
+        let tokens = visible
+            .iter()
+            .map(|id| self.token_for(client_id, &self.tokens[id]))
+            .collect();
+        self.known_tokens.insert(client_id, visible);
+        // Whatever was queued for the player is part of the snapshot.
+        self.deltas.remove(&client_id);
+
+        ServerMessage::Joined {
+            room: self.name.clone(),
+            client_id: client_id.raw(),
+            is_gm: self.is_gm(client_id),
+            players: self
+                .players
+                .keys()
+                .filter_map(|client_id| self.player_info(*client_id))
+                .collect(),
+            version: self.version,
+            seq: self.sequences.get(&client_id).copied().unwrap_or(0),
+            rolls: self.visible_rolls(client_id),
+            chat: self.visible_chat(client_id),
+            tokens,
+            initiative: self.initiative.clone(),
+            map: self.map.as_ref().map(|map| map.info.clone()),
+            walls: self.walls.values().cloned().collect(),
+            fog: self.fog,
+            drawings: self.drawings.values().cloned().collect(),
+            templates: self.templates.values().cloned().collect(),
+        }
+    }
+
+    /// Sends every player the changes queued for them this tick.
+    fn flush(&mut self, server: &mut RenetServer) {
+        if self.deltas.is_empty() {
+            return;
+        }
+
+        self.version += 1;
+        for (client_id, changes) in self.deltas.drain() {
+            let seq = self.sequences.entry(client_id).or_default();
+            *seq += 1;
+            let delta = ServerMessage::Delta {
+                seq: *seq,
+                version: self.version,
+                changes: compact(changes),
+            };
+            send(server, client_id, &delta);
+        }
+    }
+
+    /// Secret rolls are only ever shown to the GM and whoever rolled them.
+    pub fn visible_rolls(&self, client_id: ClientId) -> Vec<Roll> {
+        self.rolls
+            .iter()
+            .filter(|roll| !roll.secret || self.rolled(client_id, roll))
+            .cloned()
+            .collect()
+    }
+
+    /// Whether a client may see a secret roll, as the GM or as whoever
+    /// rolled it, on any connection.
+    pub fn rolled(&self, client_id: ClientId, roll: &Roll) -> bool {
+        let Some(player) = self.players.get(&client_id) else {
+            return false;
+        };
+        self.is_gm(client_id) || (!roll.user_id.is_empty() && roll.user_id == player.user_id)
+    }
+
+    pub fn can_read(&self, client_id: ClientId, message: &ChatMessage) -> bool {
+        let Some(player) = self.players.get(&client_id) else {
+            return false;
+        };
+
+        let sent = message.user_id == player.user_id;
+        match &message.kind {
+            ChatKind::Say | ChatKind::Emote => true,
+            ChatKind::Whisper { to_user, .. } => sent || *to_user == player.user_id,
+            ChatKind::Gm => sent || self.is_gm(client_id),
+        }
+    }
+
+    pub fn visible_chat(&self, client_id: ClientId) -> Vec<ChatMessage> {
+        self.chat
+            .iter()
+            .filter(|message| self.can_read(client_id, message))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Adds to the roll or chat history of a room, forgetting the oldest entries
+/// past [`HISTORY_LENGTH`].
+pub fn remember<T>(history: &mut Vec<T>, entry: T) {
+    history.push(entry);
+    forget_old(history);
+}
+
+pub fn forget_old<T>(history: &mut Vec<T>) {
+    let excess = history.len().saturating_sub(HISTORY_LENGTH);
+    history.drain(..excess);
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct Rooms {
+    rooms: HashMap<String, Room>,
+    members: HashMap<ClientId, String>,
+}
+
+impl Rooms {
+    pub fn iter(&self) -> impl Iterator<Item = &Room> {
+        self.rooms.values()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.rooms.contains_key(name)
+    }
+
+    /// Adds a room nobody is in yet, replacing any room with the same name.
+    pub fn insert(&mut self, room: Room) {
+        self.rooms.insert(room.name.clone(), room);
+    }
+
+    pub fn room_of(&self, client_id: ClientId) -> Option<&Room> {
+        self.rooms.get(self.members.get(&client_id)?)
+    }
+
+    pub fn room_of_mut(&mut self, client_id: ClientId) -> Option<&mut Room> {
+        self.rooms.get_mut(self.members.get(&client_id)?)
+    }
+
+    fn leave(&mut self, client_id: ClientId) {
+        let Some(room) = self.room_of_mut(client_id) else {
+            return;
+        };
+
+        room.players.remove(&client_id);
+        room.known_tokens.remove(&client_id);
+        room.deltas.remove(&client_id);
+        room.sequences.remove(&client_id);
+        if room.is_gm(client_id) {
+            room.gm = None;
+            room.gm_left = Some(Instant::now());
+        }
+        room.broadcast(&ServerMessage::PlayerLeft(client_id.raw()));
+        self.members.remove(&client_id);
+    }
+
+    /// The clients a user is connected as, there is more than one when the
+    /// host has not noticed yet that an earlier connection dropped.
+    fn clients_of(&self, user_id: &str) -> Vec<ClientId> {
+        self.rooms
+            .values()
+            .flat_map(|room| &room.players)
+            .filter(|(_, player)| player.user_id == user_id)
+            .map(|(client_id, _)| *client_id)
+            .collect()
+    }
+}
+
+/// A player waiting for the token they joined with to be verified.
+struct Join {
+    client_id: ClientId,
+    room: String,
+    character: Option<CharacterInfo>,
+    member: Task<Result<Member, VerificationError>>,
+}
+
+/// Joins are verified off the main thread, so a slow key fetch does not
+/// stall the whole host.
+#[derive(Default, Resource)]
+struct Joining(Vec<Join>);
+
+fn handle_join_room(
+    mut ev_client: EventReader<ClientEvent>,
+    identity: Res<Identity>,
+    mut joining: ResMut<Joining>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::JoinRoom {
+            room,
+            token,
+            character,
+        } = message
+        else {
+            continue;
+        };
+
+        // Only the latest room a client asked for counts.
+        joining.0.retain(|join| join.client_id != *client_id);
+        joining.0.push(Join {
+            client_id: *client_id,
+            room: room.clone(),
+            character: character.clone(),
+            member: identity.verify(token.clone()),
+        });
+    }
+}
+
+fn finish_joins(
+    mut joining: ResMut<Joining>,
+    mut rooms: ResMut<Rooms>,
+    store: Res<AssetStore>,
+    mut server: ResMut<RenetServer>,
+) {
+    let (done, pending) = std::mem::take(&mut joining.0)
+        .into_iter()
+        .partition::<Vec<_>, _>(|join| join.member.is_finished());
+    joining.0 = pending;
+
+    for join in done {
+        let Join {
+            client_id,
+            room,
+            character,
+            member,
+        } = join;
+
+        let Member {
+            user_id,
+            name,
+            color,
+        } = match block_on(member) {
+            Ok(member) => member,
+            Err(error) => {
+                warn!("{client_id} could not be verified: {error:?}");
+                let error = ServerMessage::Error("Please sign in again".to_owned());
+                send(&mut server, client_id, &error);
+                continue;
+            }
+        };
+        let user_id = &user_id;
+        if character
+            .as_ref()
+            .is_some_and(|character| !character.health.is_valid())
+        {
+            let error = ServerMessage::Error("Those hit points are not valid".to_owned());
+            send(&mut server, client_id, &error);
+            continue;
+        }
+
+        // A user who reconnects takes over from their stale connection.
+        for stale in rooms.clients_of(user_id) {
+            if stale != client_id {
+                info!("{user_id} reconnected as {client_id}, dropping {stale}");
+                rooms.leave(stale);
+                server.disconnect(stale);
+            }
+        }
+
+        rooms.leave(client_id);
+        rooms.members.insert(client_id, room.clone());
+        let room = rooms
+            .rooms
+            .entry(room.clone())
+            .or_insert_with(|| Room::new(room));
+
+        // Whoever joins a room without a GM takes over that role, unless
+        // the GM it had is expected back.
+        if room.may_become_gm(user_id) {
+            room.gm = Some(client_id);
+            room.gm_user = Some(user_id.clone());
+            room.gm_left = None;
+        }
+
+        let character = character.map(|character| store.stored_art(&character));
+        let mut linked = Vec::new();
+        if let Some(character) = &character {
+            // Tokens from an earlier session keep the damage they took, the
+            // player's client catches the sheet up with them.
+            linked = room.update_linked_tokens(user_id, character, false);
+
+            if linked.is_empty() {
+                // Line new tokens up along the top row, in the middle of a cell.
+                let x = ((room.tokens.len() % 10) as f32 + 0.5) * CELL_SIZE;
+                room.spawn_token(Token {
+                    owner: Some(user_id.clone()),
+                    character: Some(character.id),
+                    initiative_bonus: character.initiative_bonus,
+                    saves: character.saves,
+                    health: HealthView::Full(character.health),
+                    health_visibility: HealthVisibility::Everyone,
+                    image: character.token_image,
+                    ..Token::new(character.name.clone(), x, -CELL_SIZE / 2.0)
+                });
+            }
+        }
+
+        room.players.insert(
+            client_id,
+            Player {
+                user_id: user_id.clone(),
+                name: name.clone(),
+                character: character.as_ref().map(|character| character.name.clone()),
+                color,
+            },
+        );
+        if let Some(player) = room.player_info(client_id) {
+            room.broadcast(&ServerMessage::PlayerChanged(player));
+        }
+
+        info!("{name} joined room {}", room.name);
+        let snapshot = room.snapshot(client_id);
+        send(&mut server, client_id, &snapshot);
+        share_map(room, client_id);
+        // Other players may be able to see the token of whoever just joined.
+        update_vision(room);
+        for token in linked {
+            share_token(room, token);
+        }
+    }
+}
+
+/// Sent when a client missed a delta, the snapshot gets it back in sync.
+fn handle_request_snapshot(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::RequestSnapshot = message else {
+            continue;
+        };
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+
+        warn!("{client_id} fell out of sync with {}", room.name);
+        let snapshot = room.snapshot(*client_id);
+        send(&mut server, *client_id, &snapshot);
+        share_map(room, *client_id);
+    }
+}
+
+fn flush_deltas(mut rooms: ResMut<Rooms>, mut server: ResMut<RenetServer>) {
+    for room in rooms.rooms.values_mut() {
+        room.flush(&mut server);
+    }
+}
+
+/// Only the last position of a token that moved several times in a tick
+/// matters.
+fn compact(changes: Vec<ServerMessage>) -> Vec<ServerMessage> {
+    let mut last_move = HashMap::new();
+    for (index, change) in changes.iter().enumerate() {
+        if let ServerMessage::TokenMoved { token, .. } = change {
+            last_move.insert(*token, index);
+        }
+    }
+
+    changes
+        .into_iter()
+        .enumerate()
+        .filter(|(index, change)| match change {
+            ServerMessage::TokenMoved { token, .. } => last_move[token] == *index,
+            _ => true,
+        })
+        .map(|(_, change)| change)
+        .collect()
+}
+
+fn handle_disconnect(
+    mut server_events: EventReader<ServerEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut joining: ResMut<Joining>,
+) {
+    for event in server_events.read() {
+        if let ServerEvent::ClientDisconnected { client_id, .. } = event {
+            rooms.leave(*client_id);
+            joining.0.retain(|join| join.client_id != *client_id);
+        }
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use tyche_protocol::token::Health;
+
+    use super::*;
+
+    fn character(health: Health) -> CharacterInfo {
+        CharacterInfo {
+            id: 3,
+            name: "Vex".to_owned(),
+            initiative_bonus: 2,
+            saves: [0; 6],
+            health,
+            token_image: None,
+        }
+    }
+
+    fn health(current: i32, max: i32, temp: i32) -> Health {
+        Health { current, max, temp }
+    }
+
+    #[test]
+    fn linked_tokens_only_take_valid_health() {
+        let mut room = Room::new("Crypt".to_owned());
+        let token = room.spawn_token(Token {
+            owner: Some("alice".to_owned()),
+            character: Some(3),
+            health: HealthView::Full(health(7, 10, 0)),
+            ..Token::new("Vex".to_owned(), 0.0, 0.0)
+        });
+
+        let updated = room.update_linked_tokens("alice", &character(health(4, 10, 2)), true);
+        assert_eq!(updated, [token.id]);
+        assert_eq!(
+            room.tokens[&token.id].health,
+            HealthView::Full(health(4, 10, 2))
+        );
+
+        for invalid in [
+            health(5, 0, 0),
+            health(11, 10, 0),
+            health(-1, 10, 0),
+            health(5, 10, -3),
+        ] {
+            room.update_linked_tokens("alice", &character(invalid), true);
+            assert_eq!(
+                room.tokens[&token.id].health,
+                HealthView::Full(health(4, 10, 2))
+            );
+        }
+    }
+
+    #[test]
+    fn other_players_tokens_are_left_alone() {
+        let mut room = Room::new("Crypt".to_owned());
+        let token = room.spawn_token(Token {
+            owner: Some("bob".to_owned()),
+            character: Some(3),
+            ..Token::new("Bob".to_owned(), 0.0, 0.0)
+        });
+
+        let updated = room.update_linked_tokens("alice", &character(health(4, 10, 0)), true);
+        assert!(updated.is_empty());
+        assert_eq!(room.tokens[&token.id].name, "Bob");
+    }
+}
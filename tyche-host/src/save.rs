@@ -0,0 +1,505 @@
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    tasks::{block_on, IoTaskPool, Task},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tyche_protocol::{
+    asset::{AssetHash, AssetInfo},
+    chat::ChatMessage,
+    dice::Roll,
+    drawing::Drawing,
+    fog::FogMask,
+    initiative::Initiative,
+    map::{MapId, MapInfo},
+    template::Template,
+    token::Token,
+    vision::Wall,
+};
+
+use crate::{
+    asset_store::AssetStore,
+    map::Map,
+    room::{forget_old, Room, Rooms},
+};
+
+/// Bumped whenever [`SavedRoom`] changes, together with a new entry in
+/// [`MIGRATIONS`].
+pub const SAVE_VERSION: u64 = 3;
+/// Upgrades saves one version at a time, the first entry turns a version 1
+/// save into a version 2 one and so on.
+const MIGRATIONS: &[fn(&mut Value)] = &[move_map_format_into_asset, add_user_ids];
+const ROOM_FILE: &str = "room.json";
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        let settings = SaveSettings::from_env();
+        let shutdown = Shutdown::default();
+        let flag = shutdown.0.clone();
+        if let Err(error) = ctrlc::set_handler(move || flag.store(true, Ordering::SeqCst)) {
+            warn!("Rooms will not be saved on shutdown: {error}");
+        }
+
+        let timer = settings
+            .interval
+            .map(|interval| Timer::new(interval, TimerMode::Repeating));
+
+        app.insert_resource(Autosave(timer))
+            .insert_resource(settings)
+            .insert_resource(shutdown)
+            .insert_resource(WrittenMaps::default())
+            .insert_resource(Saving::default())
+            .add_systems(Startup, load_rooms)
+            .add_systems(Update, (autosave, finish_saves, save_on_shutdown));
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct SaveSettings {
+    pub dir: PathBuf,
+    /// How often rooms are saved, never if `None`.
+    pub interval: Option<Duration>,
+    /// Whether the rooms in `dir` are loaded when the host starts.
+    pub load: bool,
+}
+
+impl SaveSettings {
+    fn from_env() -> Self {
+        let seconds = env::var("AUTOSAVE_SECONDS")
+            .ok()
+            .and_then(|seconds| seconds.parse().ok())
+            .unwrap_or(60);
+
+        Self {
+            dir: env::var("SAVE_DIR").unwrap_or("saves".to_owned()).into(),
+            interval: (seconds > 0).then(|| Duration::from_secs(seconds)),
+            load: env::var("LOAD_ROOMS").is_ok_and(|load| load == "1" || load == "true"),
+        }
+    }
+}
+
+#[derive(Debug, Resource)]
+struct Autosave(Option<Timer>);
+
+/// The map each room has on disk, so images are only written once.
+#[derive(Debug, Default, Resource)]
+struct WrittenMaps(HashMap<String, MapId>);
+
+/// Rooms being written on the IO task pool, with the map each one writes.
+#[derive(Default, Resource)]
+struct Saving(Vec<(String, Option<MapId>, Task<io::Result<()>>)>);
+
+/// Set by the Ctrl-C handler, so the rooms get saved before the host exits.
+#[derive(Debug, Default, Resource)]
+struct Shutdown(Arc<AtomicBool>);
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SavedMap {
+    pub info: MapInfo,
+    /// Compressed with [`FogMask::compress`].
+    pub fog: Vec<u8>,
+}
+
+/// Everything about a room that outlives the players in it.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SavedRoom {
+    pub version: u64,
+    pub name: String,
+    pub rolls: Vec<Roll>,
+    pub chat: Vec<ChatMessage>,
+    pub tokens: Vec<Token>,
+    pub initiative: Initiative,
+    pub map: Option<SavedMap>,
+    pub walls: Vec<Wall>,
+    pub fog: bool,
+    pub drawings: Vec<Drawing>,
+    pub templates: Vec<Template>,
+}
+
+impl SavedRoom {
+    pub fn new(room: &Room) -> Self {
+        Self {
+            version: SAVE_VERSION,
+            name: room.name.clone(),
+            rolls: room.rolls.clone(),
+            chat: room.chat.clone(),
+            tokens: room.tokens.values().cloned().collect(),
+            initiative: room.initiative.clone(),
+            map: room.map.as_ref().map(|map| SavedMap {
+                info: map.info.clone(),
+                fog: map.fog.compress(),
+            }),
+            walls: room.walls.values().cloned().collect(),
+            fog: room.fog,
+            drawings: room.drawings.values().cloned().collect(),
+            templates: room.templates.values().cloned().collect(),
+        }
+    }
+
+    /// Parses a save of any version up to [`SAVE_VERSION`], migrating older
+    /// ones.
+    pub fn parse(json: &[u8]) -> Result<Self, String> {
+        let mut value: Value = serde_json::from_slice(json).map_err(|error| error.to_string())?;
+        let version = value
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or("the save has no version")?;
+        if version == 0 || version > SAVE_VERSION {
+            return Err(format!(
+                "version {version} saves are not supported, the newest is {SAVE_VERSION}"
+            ));
+        }
+
+        for migrate in &MIGRATIONS[version as usize - 1..] {
+            migrate(&mut value);
+        }
+        value["version"] = SAVE_VERSION.into();
+        serde_json::from_value(value).map_err(|error| error.to_string())
+    }
+
+    /// Turns the save back into a room, with the map image it refers to.
+    pub fn restore(self, image: Option<Vec<u8>>) -> Result<Room, String> {
+        let map = match (self.map, image) {
+            (Some(mut map), Some(image)) => {
+                let fog = FogMask::decompress(&map.fog).ok_or("the fog mask is corrupt")?;
+                map.info.asset = AssetInfo::of(&image)?;
+                Some(Map {
+                    info: map.info,
+                    image,
+                    fog,
+                })
+            }
+            (Some(_), None) => return Err("the map image is missing".to_owned()),
+            (None, _) => None,
+        };
+
+        let mut room = Room::new(self.name);
+        room.rolls = self.rolls;
+        room.chat = self.chat;
+        forget_old(&mut room.rolls);
+        forget_old(&mut room.chat);
+        room.tokens = self.tokens.into_iter().map(|t| (t.id, t)).collect();
+        room.initiative = self.initiative;
+        room.map = map;
+        room.walls = self.walls.into_iter().map(|w| (w.id, w)).collect();
+        room.fog = self.fog;
+        room.drawings = self.drawings.into_iter().map(|d| (d.id, d)).collect();
+        room.templates = self.templates.into_iter().map(|t| (t.id, t)).collect();
+        room.renumber();
+        Ok(room)
+    }
+}
+
+/// Version 2 keeps the format and size of the map image in its asset info.
+/// The hash is left empty, restoring a save always hashes the image again.
+fn move_map_format_into_asset(save: &mut Value) {
+    let Some(info) = save.pointer_mut("/map/info").and_then(Value::as_object_mut) else {
+        return;
+    };
+    let asset = json!({
+        "hash": AssetHash::default(),
+        "format": info.remove("format"),
+        "size": info.remove("size"),
+    });
+    info.insert("asset".to_owned(), asset);
+}
+
+/// Version 3 keeps the user id of whoever sent a chat message or made a roll,
+/// and of whom a whisper is for. Entries from before only know client ids,
+/// which do not outlive a connection, so they get no user and their secrets
+/// stay with the GM.
+fn add_user_ids(save: &mut Value) {
+    for history in ["chat", "rolls"] {
+        let Some(entries) = save.get_mut(history).and_then(Value::as_array_mut) else {
+            continue;
+        };
+        for entry in entries.iter_mut().filter_map(Value::as_object_mut) {
+            entry.entry("user_id").or_insert("".into());
+            if let Some(whisper) = entry
+                .get_mut("kind")
+                .and_then(|kind| kind.get_mut("Whisper"))
+                .and_then(Value::as_object_mut)
+            {
+                whisper.entry("to_user").or_insert("".into());
+            }
+        }
+    }
+}
+
+/// Room names can be anything, so the directories are named after an
+/// escaped version of them.
+fn room_dir(dir: &Path, name: &str) -> PathBuf {
+    let escaped: String = name
+        .bytes()
+        .map(|byte| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => (byte as char).to_string(),
+            _ => format!("%{byte:02X}"),
+        })
+        .collect();
+    dir.join(escaped)
+}
+
+pub fn map_file(info: &MapInfo) -> String {
+    format!("map-{}.{}", info.id, info.asset.format.extension())
+}
+
+/// Writes to a temporary file first, so a crash never leaves half a save.
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, bytes)?;
+    fs::rename(temporary, path)
+}
+
+/// A room ready to be written to disk. It is serialized on the main thread,
+/// so the room can go on changing while the files are written.
+struct RoomFiles {
+    dir: PathBuf,
+    json: Vec<u8>,
+    /// The file of the map image, with the image unless it is already on disk.
+    map: Option<(String, Option<Vec<u8>>)>,
+}
+
+impl RoomFiles {
+    fn new(dir: &Path, room: &Room, write_map: bool) -> serde_json::Result<Self> {
+        let map = room.map.as_ref().map(|map| {
+            let image = write_map.then(|| map.image.clone());
+            (map_file(&map.info), image)
+        });
+
+        Ok(Self {
+            dir: room_dir(dir, &room.name),
+            json: serde_json::to_vec(&SavedRoom::new(room))?,
+            map,
+        })
+    }
+
+    /// Saves the room, along with its map image if it has one to write.
+    fn write(self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        if let Some((file, Some(image))) = &self.map {
+            write_atomic(&self.dir.join(file), image)?;
+        }
+        write_atomic(&self.dir.join(ROOM_FILE), &self.json)?;
+
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let current = self.map.as_ref().is_some_and(|(file, _)| *file == name);
+            if name.starts_with("map-") && !current {
+                fs::remove_file(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn load_room(dir: &Path) -> Result<Room, String> {
+    let json = fs::read(dir.join(ROOM_FILE)).map_err(|error| error.to_string())?;
+    let saved = SavedRoom::parse(&json)?;
+    let image = match &saved.map {
+        Some(map) => Some(fs::read(dir.join(map_file(&map.info))).map_err(|e| e.to_string())?),
+        None => None,
+    };
+    saved.restore(image)
+}
+
+/// Starts writing every room that is not still being written from last time.
+fn save_rooms(settings: &SaveSettings, rooms: &Rooms, written: &WrittenMaps, saving: &mut Saving) {
+    for room in rooms.iter() {
+        if saving.0.iter().any(|(name, ..)| *name == room.name) {
+            continue;
+        }
+        let map = room.map.as_ref().map(|map| map.info.id);
+        let write_map = map.is_some() && written.0.get(&room.name) != map.as_ref();
+
+        let files = match RoomFiles::new(&settings.dir, room, write_map) {
+            Ok(files) => files,
+            Err(error) => {
+                error!("Could not save room {}: {error}", room.name);
+                continue;
+            }
+        };
+        let task = IoTaskPool::get().spawn(async move { files.write() });
+        saving.0.push((room.name.clone(), map, task));
+    }
+}
+
+fn saved(written: &mut WrittenMaps, name: String, map: Option<MapId>, result: io::Result<()>) {
+    match result {
+        Ok(()) => {
+            if let Some(map) = map {
+                written.0.insert(name, map);
+            }
+        }
+        Err(error) => error!("Could not save room {name}: {error}"),
+    }
+}
+
+fn load_rooms(
+    settings: Res<SaveSettings>,
+    mut rooms: ResMut<Rooms>,
+    mut store: ResMut<AssetStore>,
+    mut written: ResMut<WrittenMaps>,
+) {
+    if !settings.load {
+        return;
+    }
+    let entries = match fs::read_dir(&settings.dir) {
+        Ok(entries) => entries,
+        Err(error) => {
+            warn!(
+                "Could not read saves in {}: {error}",
+                settings.dir.display()
+            );
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match load_room(&path) {
+            Ok(room) => {
+                info!("Loaded room {}", room.name);
+                if let Some(map) = &room.map {
+                    written.0.insert(room.name.clone(), map.info.id);
+                    if let Err(error) = store.insert(&map.image) {
+                        error!("Could not store the map of {}: {error}", room.name);
+                    }
+                }
+                rooms.insert(room);
+            }
+            Err(error) => error!("Could not load {}: {error}", path.display()),
+        }
+    }
+}
+
+fn autosave(
+    time: Res<Time>,
+    settings: Res<SaveSettings>,
+    mut timer: ResMut<Autosave>,
+    rooms: Res<Rooms>,
+    written: Res<WrittenMaps>,
+    mut saving: ResMut<Saving>,
+) {
+    let Some(timer) = &mut timer.0 else {
+        return;
+    };
+    if timer.tick(time.delta()).just_finished() {
+        save_rooms(&settings, &rooms, &written, &mut saving);
+    }
+}
+
+fn finish_saves(mut saving: ResMut<Saving>, mut written: ResMut<WrittenMaps>) {
+    let (done, pending) = std::mem::take(&mut saving.0)
+        .into_iter()
+        .partition::<Vec<_>, _>(|(.., task)| task.is_finished());
+    saving.0 = pending;
+
+    for (name, map, task) in done {
+        saved(&mut written, name, map, block_on(task));
+    }
+}
+
+/// Waits for every room that is being written.
+fn wait_for_saves(saving: &mut Saving, written: &mut WrittenMaps) {
+    for (name, map, task) in saving.0.drain(..) {
+        saved(written, name, map, block_on(task));
+    }
+}
+
+fn save_on_shutdown(
+    settings: Res<SaveSettings>,
+    shutdown: Res<Shutdown>,
+    rooms: Res<Rooms>,
+    mut written: ResMut<WrittenMaps>,
+    mut saving: ResMut<Saving>,
+    mut ev_exit: EventWriter<AppExit>,
+) {
+    if shutdown.0.load(Ordering::SeqCst) {
+        info!("Saving rooms before shutting down");
+        // Saves still running hold older state, the rooms are saved again
+        // once they are done.
+        wait_for_saves(&mut saving, &mut written);
+        save_rooms(&settings, &rooms, &written, &mut saving);
+        wait_for_saves(&mut saving, &mut written);
+        ev_exit.send(AppExit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tyche_protocol::chat::ChatKind;
+
+    use super::*;
+
+    #[test]
+    fn version_2_saves_get_empty_user_ids() {
+        let mut save =
+            serde_json::to_value(SavedRoom::new(&Room::new("Crypt".to_owned()))).unwrap();
+        save["version"] = 2.into();
+        save["rolls"] = json!([{
+            "client_id": 1,
+            "player": "alice",
+            "character": null,
+            "label": null,
+            "secret": true,
+            "timestamp": 0,
+            "result": { "expression": "1", "terms": [{ "Modifier": 1 }], "total": 1 },
+        }]);
+        save["chat"] = json!([
+            {
+                "room": "Crypt",
+                "client_id": 1,
+                "sender": "alice",
+                "timestamp": 0,
+                "kind": { "Whisper": { "to": "Bob" } },
+                "text": "psst",
+            },
+            {
+                "room": "Crypt",
+                "client_id": 2,
+                "user_id": "bob",
+                "sender": "Bob",
+                "timestamp": 1,
+                "kind": { "Whisper": { "to": "Alice", "to_user": "alice" } },
+                "text": "what",
+            },
+        ]);
+
+        let saved = SavedRoom::parse(&serde_json::to_vec(&save).unwrap()).unwrap();
+        assert_eq!(saved.version, SAVE_VERSION);
+        assert_eq!(saved.rolls[0].user_id, "");
+        assert_eq!(saved.chat[0].user_id, "");
+        assert_eq!(
+            saved.chat[0].kind,
+            ChatKind::Whisper {
+                to: "Bob".to_owned(),
+                to_user: String::new()
+            }
+        );
+        // Saves written since the fields were added keep them.
+        assert_eq!(saved.chat[1].user_id, "bob");
+        assert_eq!(
+            saved.chat[1].kind,
+            ChatKind::Whisper {
+                to: "Alice".to_owned(),
+                to_user: "alice".to_owned()
+            }
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer};
+use rand::rngs::OsRng;
+use tyche_protocol::{
+    character::Ability,
+    dice::Roll,
+    template::{Template, TemplateId},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    dice::{d20, roll, share_roll},
+    network::{send, ClientEvent},
+    room::{unix_time, Room, Rooms},
+};
+
+pub struct TemplatePlugin;
+
+impl Plugin for TemplatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                handle_place_template,
+                handle_remove_template,
+                handle_roll_saves,
+            ),
+        );
+    }
+}
+
+/// Templates can be changed by whoever placed them, and by the GM.
+fn may_change(room: &Room, client_id: ClientId, template: TemplateId) -> bool {
+    let Some(template) = room.templates.get(&template) else {
+        return false;
+    };
+
+    room.is_gm(client_id)
+        || room
+            .players
+            .get(&client_id)
+            .is_some_and(|player| player.user_id == template.owner)
+}
+
+fn handle_place_template(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::PlaceTemplate {
+            id,
+            shape,
+            size_feet,
+            origin,
+            angle,
+        } = message
+        else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        let Some(player) = room.players.get(client_id) else {
+            continue;
+        };
+
+        let owner = match id {
+            Some(id) if !may_change(room, *client_id, *id) => {
+                let error = ServerMessage::Error("You cannot move that template".to_owned());
+                send(&mut server, *client_id, &error);
+                continue;
+            }
+            Some(id) => room.templates[id].owner.clone(),
+            None => player.user_id.clone(),
+        };
+        let mut template = Template {
+            id: 0,
+            owner,
+            shape: *shape,
+            size_feet: *size_feet,
+            origin: *origin,
+            angle: *angle,
+        };
+        if !template.is_valid() {
+            let error = ServerMessage::Error("That template is not valid".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+
+        template.id = id.unwrap_or_else(|| room.next_template_id());
+        room.broadcast(&ServerMessage::TemplateChanged(template.clone()));
+        room.templates.insert(template.id, template);
+    }
+}
+
+fn handle_remove_template(mut ev_client: EventReader<ClientEvent>, mut rooms: ResMut<Rooms>) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::RemoveTemplate(id) = message else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        if !may_change(room, *client_id, *id) {
+            continue;
+        }
+
+        room.templates.remove(id);
+        room.broadcast(&ServerMessage::TemplateRemoved(*id));
+    }
+}
+
+/// Rolls a saving throw for every token inside a template. Tokens without a
+/// player roll in secret, so only the GM learns how their monsters did.
+fn handle_roll_saves(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::RollSaves {
+            template,
+            ability,
+            dc,
+        } = message
+        else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        if !may_change(room, *client_id, *template) {
+            let error = ServerMessage::Error("You cannot roll saves for that template".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+
+        roll_saves(room, *client_id, *template, *ability, *dc);
+    }
+}
+
+fn roll_saves(
+    room: &mut Room,
+    client_id: ClientId,
+    template: TemplateId,
+    ability: Ability,
+    dc: Option<i64>,
+) {
+    let affected: Vec<_> = room
+        .tokens
+        .values()
+        .filter(|token| room.templates[&template].contains([token.x, token.y]))
+        .cloned()
+        .collect();
+
+    for token in affected {
+        let Ok(result) = roll(&d20(token.save(ability)), &mut OsRng) else {
+            continue;
+        };
+
+        let outcome = match dc {
+            Some(dc) if result.total >= dc => format!(" (DC {dc}, saved)"),
+            Some(dc) => format!(" (DC {dc}, failed)"),
+            None => String::new(),
+        };
+        // A monster's save is rolled by the host, like initiative, whoever
+        // asked for it, so the player who placed the template never learns
+        // how it went.
+        let secret = token.owner.is_none();
+        let (roller, user_id) = match (secret, room.players.get(&client_id)) {
+            (false, Some(player)) => (client_id.raw(), player.user_id.clone()),
+            _ => (0, String::new()),
+        };
+        let roll = Roll {
+            client_id: roller,
+            user_id,
+            player: room.controller_name(token.id),
+            character: Some(token.name.clone()),
+            label: Some(format!("{} save{outcome}", ability.short_name())),
+            secret,
+            timestamp: unix_time(),
+            result,
+        };
+        share_roll(room, roll);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tyche_protocol::{template::TemplateShape, token::Token};
+
+    use super::*;
+    use crate::room::Player;
+
+    fn player(user_id: &str) -> Player {
+        Player {
+            user_id: user_id.to_owned(),
+            name: user_id.to_owned(),
+            character: None,
+            color: [0, 0, 0],
+        }
+    }
+
+    fn saw_roll(room: &Room, client_id: ClientId) -> bool {
+        let queued = room.queued(client_id);
+        queued
+            .iter()
+            .any(|message| matches!(message, ServerMessage::Rolled(_)))
+    }
+
+    #[test]
+    fn monster_saves_stay_with_the_gm() {
+        let (gm, alice) = (ClientId::from_raw(1), ClientId::from_raw(2));
+        let mut room = Room::new("Crypt".to_owned());
+        room.players.insert(gm, player("gm"));
+        room.players.insert(alice, player("alice"));
+        room.gm = Some(gm);
+
+        let mut hero = Token::new("Hero".to_owned(), 10.0, 10.0);
+        hero.owner = Some("alice".to_owned());
+        room.spawn_token(hero);
+        room.spawn_token(Token::new("Goblin".to_owned(), 20.0, 20.0));
+        let template = room.next_template_id();
+        room.templates.insert(
+            template,
+            Template {
+                id: template,
+                owner: "alice".to_owned(),
+                shape: TemplateShape::Sphere,
+                size_feet: 20,
+                origin: [0.0, 0.0],
+                angle: 0.0,
+            },
+        );
+
+        roll_saves(&mut room, alice, template, Ability::Dexterity, Some(12));
+
+        assert_eq!(room.rolls.len(), 2);
+        let visible = room.visible_rolls(alice);
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].character.as_deref(), Some("Hero"));
+        let queued = room.queued(alice);
+        let rolled: Vec<_> = queued
+            .iter()
+            .filter_map(|message| match message {
+                ServerMessage::Rolled(roll) => roll.character.as_deref(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(rolled, ["Hero"]);
+        assert_eq!(room.visible_rolls(gm).len(), 2);
+        assert!(saw_roll(&room, gm));
+    }
+
+    #[test]
+    fn monster_saves_without_a_gm_reach_nobody() {
+        let alice = ClientId::from_raw(2);
+        let mut room = Room::new("Crypt".to_owned());
+        room.players.insert(alice, player("alice"));
+        room.spawn_token(Token::new("Goblin".to_owned(), 20.0, 20.0));
+        room.templates.insert(
+            0,
+            Template {
+                id: 0,
+                owner: "alice".to_owned(),
+                shape: TemplateShape::Sphere,
+                size_feet: 20,
+                origin: [0.0, 0.0],
+                angle: 0.0,
+            },
+        );
+
+        roll_saves(&mut room, alice, 0, Ability::Wisdom, None);
+
+        assert_eq!(room.rolls.len(), 1);
+        assert!(room.visible_rolls(alice).is_empty());
+        assert!(!saw_roll(&room, alice));
+    }
+}
@@ -0,0 +1,345 @@
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer};
+use tyche_protocol::{
+    map::CELL_SIZE,
+    token::{HealthVisibility, StatusChange, Token, TokenId},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    asset_store::AssetStore,
+    initiative::remove_combatant,
+    network::{send, ClientEvent},
+    room::{Room, Rooms},
+    vision::{broadcast_token, share_token, update_vision},
+};
+
+/// How far from the origin tokens may be put, well past the edge of any map.
+const MAX_DISTANCE: f32 = 1_000_000.0;
+
+pub struct TokenPlugin;
+
+impl Plugin for TokenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                handle_spawn_token,
+                handle_remove_token,
+                handle_move_token,
+                handle_change_status,
+                handle_duplicate_token,
+                handle_unlink_token,
+                handle_update_character,
+            ),
+        );
+    }
+}
+
+fn handle_spawn_token(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::SpawnToken {
+            name,
+            x,
+            y,
+            initiative_bonus,
+        } = message
+        else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        if !room.is_gm(*client_id) {
+            let error = ServerMessage::Error("Only the GM can add tokens".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+        if !on_the_table(*x, *y) {
+            let error = ServerMessage::Error("Tokens cannot go there".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+
+        room.spawn_token(Token {
+            initiative_bonus: *initiative_bonus,
+            ..Token::new(name.clone(), *x, *y)
+        });
+        update_vision(room);
+    }
+}
+
+fn handle_remove_token(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::RemoveToken(token) = message else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        if !room.controls(*client_id, *token) {
+            let error = ServerMessage::Error("You do not control that token".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+
+        if room.tokens.remove(token).is_none() {
+            continue;
+        }
+        for known in room.known_tokens.values_mut() {
+            known.remove(token);
+        }
+        room.broadcast(&ServerMessage::TokenRemoved(*token));
+
+        if remove_combatant(&mut room.initiative, *token) {
+            let initiative = ServerMessage::Initiative(room.initiative.clone());
+            room.broadcast(&initiative);
+        }
+    }
+}
+
+fn handle_move_token(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::MoveToken { token, x, y, seq } = *message else {
+            continue;
+        };
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        // Snap the mover back rather than leave their prediction dangling.
+        if !on_the_table(x, y) {
+            reject_move(room, *client_id, token, seq);
+            continue;
+        }
+        if !room.controls(*client_id, token) {
+            let error = ServerMessage::Error("You do not control that token".to_owned());
+            send(&mut server, *client_id, &error);
+            reject_move(room, *client_id, token, seq);
+            continue;
+        }
+
+        let Some(state) = room.tokens.get_mut(&token) else {
+            continue;
+        };
+        state.x = x;
+        state.y = y;
+
+        // Reveal and hide tokens first, so nobody learns where a token went
+        // after it left their sight.
+        update_vision(room);
+        let message = ServerMessage::TokenMoved {
+            token,
+            x,
+            y,
+            client_id: client_id.raw(),
+            seq,
+        };
+        broadcast_token(room, token, &message);
+    }
+}
+
+/// Whether a token may be put at a position, NaN would not survive saving
+/// the room.
+fn on_the_table(x: f32, y: f32) -> bool {
+    [x, y]
+        .iter()
+        .all(|value| value.is_finite() && value.abs() <= MAX_DISTANCE)
+}
+
+/// Puts a token the client already moved on its side back where it is.
+fn reject_move(room: &mut Room, client_id: ClientId, token: TokenId, seq: u32) {
+    let Some(state) = room.tokens.get(&token) else {
+        return;
+    };
+    let message = ServerMessage::TokenMoved {
+        token,
+        x: state.x,
+        y: state.y,
+        client_id: client_id.raw(),
+        seq,
+    };
+    room.send(client_id, message);
+}
+
+fn is_valid(change: &StatusChange) -> bool {
+    match change {
+        StatusChange::SetHealth(Some(health)) => health.is_valid(),
+        StatusChange::AddCondition(status) => status.rounds != Some(0),
+        _ => true,
+    }
+}
+
+fn handle_change_status(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::ChangeStatus { token, change } = message else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        // Players look after their own tokens, but only the GM decides who
+        // gets to see how hurt they are.
+        let allowed = match change {
+            StatusChange::SetHealthVisibility(_) => room.is_gm(*client_id),
+            _ => room.controls(*client_id, *token),
+        };
+        if !allowed {
+            let error = ServerMessage::Error("You do not control that token".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+        if !is_valid(change) {
+            continue;
+        }
+
+        let Some(state) = room.tokens.get_mut(token) else {
+            continue;
+        };
+        if state.apply(change) {
+            share_token(room, *token);
+        }
+    }
+}
+
+fn handle_duplicate_token(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::DuplicateToken(token) = message else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        if !room.is_gm(*client_id) {
+            let error = ServerMessage::Error("Only the GM can add tokens".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+        let Some(original) = room.tokens.get(token) else {
+            continue;
+        };
+
+        // Number the copies, so "Goblin" is followed by "Goblin 2".
+        let base = original
+            .name
+            .trim_end_matches(|c: char| c.is_ascii_digit() || c == ' ');
+        let copies = room
+            .tokens
+            .values()
+            .filter(|token| token.name.starts_with(base))
+            .count();
+        let copy = Token {
+            name: format!("{base} {}", copies + 1),
+            owner: None,
+            character: None,
+            x: original.x + CELL_SIZE,
+            health_visibility: HealthVisibility::default(),
+            conditions: Vec::new(),
+            ..original.clone()
+        };
+
+        room.spawn_token(copy);
+        update_vision(room);
+    }
+}
+
+fn handle_unlink_token(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::UnlinkToken(token) = message else {
+            continue;
+        };
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        if !room.is_gm(*client_id) {
+            let error = ServerMessage::Error("Only the GM can unlink tokens".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+
+        let Some(state) = room.tokens.get_mut(token) else {
+            continue;
+        };
+        if state.character.take().is_some() {
+            share_token(room, *token);
+        }
+    }
+}
+
+fn handle_update_character(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    store: Res<AssetStore>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        let ClientMessage::UpdateCharacter(character) = message else {
+            continue;
+        };
+        if !character.health.is_valid() {
+            let error = ServerMessage::Error("Those hit points are not valid".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+        let character = &store.stored_art(character);
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        let Some(player) = room.players.get_mut(client_id) else {
+            continue;
+        };
+        player.character = Some(character.name.clone());
+        let user_id = player.user_id.clone();
+        if let Some(player) = room.player_info(*client_id) {
+            room.broadcast(&ServerMessage::PlayerChanged(player));
+        }
+
+        for token in room.update_linked_tokens(&user_id, character, true) {
+            share_token(room, token);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_stay_on_the_table() {
+        assert!(on_the_table(0.0, 0.0));
+        assert!(on_the_table(-MAX_DISTANCE, MAX_DISTANCE));
+        assert!(!on_the_table(f32::NAN, 0.0));
+        assert!(!on_the_table(0.0, f32::INFINITY));
+        assert!(!on_the_table(f32::NEG_INFINITY, 0.0));
+        assert!(!on_the_table(MAX_DISTANCE * 2.0, 0.0));
+    }
+}
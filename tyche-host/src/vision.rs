@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy_renet::renet::{ClientId, RenetServer};
+use tyche_protocol::{
+    token::TokenId,
+    vision::{can_see, Point, WallKind},
+    ClientMessage, ServerMessage,
+};
+
+use crate::{
+    network::{send, ClientEvent},
+    room::{Room, Rooms},
+};
+
+pub struct VisionPlugin;
+
+impl Plugin for VisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, handle_walls);
+    }
+}
+
+/// The tokens a client is allowed to know the position of. With fog enabled
+/// players only see what the tokens they own have a line of sight to, and
+/// never what the GM has hidden under the fog of the map.
+pub fn visible_tokens(room: &Room, client_id: ClientId) -> HashSet<TokenId> {
+    if room.is_gm(client_id) {
+        return room.tokens.keys().copied().collect();
+    }
+
+    let Some(player) = room.players.get(&client_id) else {
+        return HashSet::new();
+    };
+    let hidden = |point: Point| {
+        room.map
+            .as_ref()
+            .is_some_and(|map| map.fog.is_hidden(point))
+    };
+    let is_owned = |owner: &Option<String>| owner.as_ref() == Some(&player.user_id);
+    let eyes: Vec<Point> = room
+        .tokens
+        .values()
+        .filter(|token| is_owned(&token.owner))
+        .map(|token| [token.x, token.y])
+        .collect();
+
+    room.tokens
+        .values()
+        .filter(|token| {
+            let position = [token.x, token.y];
+            let in_sight = !room.fog
+                || eyes
+                    .iter()
+                    .any(|eye| can_see(room.walls.values(), *eye, position));
+            is_owned(&token.owner) || (in_sight && !hidden(position))
+        })
+        .map(|token| token.id)
+        .collect()
+}
+
+/// Reveals the tokens that came into view of each player and hides the ones
+/// that went out of it.
+pub fn update_vision(room: &mut Room) {
+    let clients: Vec<ClientId> = room.players.keys().copied().collect();
+    for client_id in clients {
+        let visible = visible_tokens(room, client_id);
+        let known = room.known_tokens.remove(&client_id).unwrap_or_default();
+
+        for id in visible.difference(&known) {
+            let token = room.token_for(client_id, &room.tokens[id]);
+            room.send(client_id, ServerMessage::TokenSpawned(token));
+        }
+        // Removed tokens have already been announced to everyone.
+        for id in known.difference(&visible) {
+            if room.tokens.contains_key(id) {
+                room.send(client_id, ServerMessage::TokenRemoved(*id));
+            }
+        }
+        room.known_tokens.insert(client_id, visible);
+    }
+}
+
+/// Sends a message about a token only to the players that know where it is.
+pub fn broadcast_token(room: &mut Room, token: TokenId, message: &ServerMessage) {
+    let clients: Vec<ClientId> = room
+        .known_tokens
+        .iter()
+        .filter(|(_, known)| known.contains(&token))
+        .map(|(client_id, _)| *client_id)
+        .collect();
+    for client_id in clients {
+        room.send(client_id, message.clone());
+    }
+}
+
+/// Sends the new state of a token to the players that know where it is, with
+/// as much as each of them may see.
+pub fn share_token(room: &mut Room, token: TokenId) {
+    let Some(state) = room.tokens.get(&token) else {
+        return;
+    };
+
+    let messages: Vec<(ClientId, ServerMessage)> = room
+        .known_tokens
+        .iter()
+        .filter(|(_, known)| known.contains(&token))
+        .map(|(client_id, _)| {
+            let message = ServerMessage::TokenChanged(room.token_for(*client_id, state));
+            (*client_id, message)
+        })
+        .collect();
+    for (client_id, message) in messages {
+        room.send(client_id, message);
+    }
+}
+
+fn handle_walls(
+    mut ev_client: EventReader<ClientEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<RenetServer>,
+) {
+    for ClientEvent { client_id, message } in ev_client.read() {
+        if !matches!(
+            message,
+            ClientMessage::AddWall { .. }
+                | ClientMessage::RemoveWall(_)
+                | ClientMessage::ToggleDoor(_)
+                | ClientMessage::SetFog(_)
+        ) {
+            continue;
+        }
+
+        let Some(room) = rooms.room_of_mut(*client_id) else {
+            continue;
+        };
+        if !room.is_gm(*client_id) {
+            let error = ServerMessage::Error("Only the GM can change walls and fog".to_owned());
+            send(&mut server, *client_id, &error);
+            continue;
+        }
+
+        match message {
+            ClientMessage::AddWall { kind, start, end } => {
+                if !start.iter().chain(end).all(|value| value.is_finite()) || start == end {
+                    continue;
+                }
+
+                let wall = room.add_wall(*kind, *start, *end);
+                room.broadcast(&ServerMessage::WallChanged(wall));
+            }
+            ClientMessage::RemoveWall(id) => {
+                if room.walls.remove(id).is_none() {
+                    continue;
+                }
+                room.broadcast(&ServerMessage::WallRemoved(*id));
+            }
+            ClientMessage::ToggleDoor(id) => {
+                let Some(wall) = room.walls.get_mut(id) else {
+                    continue;
+                };
+                let WallKind::Door { open } = &mut wall.kind else {
+                    continue;
+                };
+
+                *open = !*open;
+                let message = ServerMessage::WallChanged(wall.clone());
+                room.broadcast(&message);
+            }
+            ClientMessage::SetFog(fog) => {
+                room.fog = *fog;
+                room.broadcast(&ServerMessage::FogChanged(*fog));
+            }
+            _ => continue,
+        }
+
+        update_vision(room);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tyche_protocol::{token::Token, vision::VISION_RANGE};
+
+    use super::*;
+    use crate::room::Player;
+
+    const GM: ClientId = ClientId::from_raw(1);
+    const ALICE: ClientId = ClientId::from_raw(2);
+
+    /// Alice's hero at the origin, behind a wall at x = 100 with a fogged
+    /// room around it.
+    fn room() -> Room {
+        let mut room = Room::new("Crypt".to_owned());
+        for (client_id, user_id) in [(GM, "gm"), (ALICE, "alice")] {
+            let player = Player {
+                user_id: user_id.to_owned(),
+                name: user_id.to_owned(),
+                character: None,
+                color: [0, 0, 0],
+            };
+            room.players.insert(client_id, player);
+        }
+        room.gm = Some(GM);
+        room.fog = true;
+        room.add_wall(WallKind::Wall, [100.0, -50.0], [100.0, 50.0]);
+        place(&mut room, 0, "alice", [0.0, 0.0]);
+        room
+    }
+
+    /// Puts a token exactly where asked, without snapping it to the grid.
+    fn place(room: &mut Room, id: TokenId, owner: &str, [x, y]: Point) {
+        let token = Token {
+            id,
+            owner: (!owner.is_empty()).then(|| owner.to_owned()),
+            ..Token::new(format!("Token {id}"), x, y)
+        };
+        room.tokens.insert(id, token);
+    }
+
+    fn sorted(visible: HashSet<TokenId>) -> Vec<TokenId> {
+        let mut visible: Vec<_> = visible.into_iter().collect();
+        visible.sort();
+        visible
+    }
+
+    #[test]
+    fn walls_hide_tokens_behind_them() {
+        let mut room = room();
+        place(&mut room, 1, "", [50.0, 0.0]);
+        place(&mut room, 2, "", [200.0, 0.0]);
+        place(&mut room, 3, "", [200.0, 200.0]);
+
+        assert_eq!(sorted(visible_tokens(&room, ALICE)), [0, 1, 3]);
+        assert_eq!(sorted(visible_tokens(&room, GM)), [0, 1, 2, 3]);
+
+        room.fog = false;
+        assert_eq!(sorted(visible_tokens(&room, ALICE)), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn tokens_on_a_wall_are_seen() {
+        let mut room = room();
+        place(&mut room, 1, "", [100.0, 0.0]);
+        place(&mut room, 2, "", [100.0, 50.0]);
+        assert_eq!(sorted(visible_tokens(&room, ALICE)), [0, 1, 2]);
+    }
+
+    #[test]
+    fn tokens_at_the_edge_of_vision_are_seen() {
+        let mut room = room();
+        place(&mut room, 1, "", [0.0, VISION_RANGE]);
+        place(&mut room, 2, "", [0.0, -VISION_RANGE - 1.0]);
+        assert_eq!(sorted(visible_tokens(&room, ALICE)), [0, 1]);
+    }
+
+    #[test]
+    fn own_tokens_are_always_known() {
+        let mut room = room();
+        place(&mut room, 1, "alice", [200.0, 0.0]);
+        place(&mut room, 2, "", [300.0, 0.0]);
+        // The second hero sees the token the first one cannot.
+        assert_eq!(sorted(visible_tokens(&room, ALICE)), [0, 1, 2]);
+        assert!(visible_tokens(&room, ClientId::from_raw(9)).is_empty());
+    }
+}
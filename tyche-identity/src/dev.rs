@@ -0,0 +1,74 @@
+use std::time::{Duration, SystemTime};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use rsa::{pkcs1::EncodeRsaPrivateKey, traits::PublicKeyParts, RsaPrivateKey};
+use serde_json::Map;
+
+use crate::{FirebaseProvider, FirebaseUser, JwkKey, KeyResponse, DEV_ISSUER, PROJECT_ID};
+
+/// As long as Firebase tokens last.
+const TOKEN_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Signs tokens with the same claims as Firebase, using a key generated on
+/// start that is only published through [`DevIssuer::jwks`].
+pub struct DevIssuer {
+    key: EncodingKey,
+    jwk: JwkKey,
+}
+
+impl DevIssuer {
+    pub fn generate() -> Self {
+        let mut rng = rand::thread_rng();
+        let key = RsaPrivateKey::new(&mut rng, 2048).expect("a 2048 bit key can be generated");
+        let der = key.to_pkcs1_der().expect("a generated key can be encoded");
+
+        let jwk = JwkKey {
+            e: URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+            alg: "RS256".to_owned(),
+            kty: "RSA".to_owned(),
+            kid: format!("{:016x}", rand::random::<u64>()),
+            n: URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+        };
+
+        Self {
+            key: EncodingKey::from_rsa_der(der.as_bytes()),
+            jwk,
+        }
+    }
+
+    pub fn jwks(&self) -> KeyResponse {
+        KeyResponse {
+            keys: vec![self.jwk.clone()],
+        }
+    }
+
+    pub fn sign(&self, user_id: &str, name: &str, sign_in_provider: &str) -> String {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let user = FirebaseUser {
+            provider_id: None,
+            name: Some(name.to_owned()),
+            picture: None,
+            iss: DEV_ISSUER.to_owned(),
+            aud: PROJECT_ID.to_owned(),
+            auth_time: now,
+            user_id: user_id.to_owned(),
+            sub: user_id.to_owned(),
+            iat: now,
+            exp: now + TOKEN_LIFETIME.as_secs(),
+            email: None,
+            email_verified: None,
+            firebase: FirebaseProvider {
+                sign_in_provider: sign_in_provider.to_owned(),
+                identities: Map::new(),
+            },
+        };
+
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(self.jwk.kid.clone());
+        encode(&header, &user, &self.key).expect("dev tokens are always encodable")
+    }
+}
@@ -0,0 +1,318 @@
+//! Verifying the tokens players sign in with, shared by every service.
+//!
+//! Tokens normally come from Firebase and are checked against Google's public
+//! keys. Setting `DEV_IDENTITY` to `1` makes services check them against the
+//! key a tyche-auth running in dev identity mode generated instead, so the
+//! whole stack runs without a network. `DEV_AUTH_SERVICE` is the address of
+//! that tyche-auth, [`DEFAULT_DEV_AUTH_SERVICE`] unless set.
+
+#[cfg(feature = "dev")]
+pub mod dev;
+
+use std::{
+    env, fmt,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+const JWK_URL: &str =
+    "https://www.googleapis.com/service_accounts/v1/jwk/securetoken@system.gserviceaccount.com";
+const PROJECT_ID: &str = "tyche-vtt";
+/// Who signed a token in dev identity mode.
+pub const DEV_ISSUER: &str = "tyche-dev";
+/// Where tyche-auth listens when run locally.
+pub const DEFAULT_DEV_AUTH_SERVICE: &str = "http://localhost:3000";
+/// How often tokens naming a key we do not have may make us fetch the keys
+/// again, so made up key ids can't keep the verifier busy fetching.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct JwkConfiguration {
+    pub jwk_url: String,
+    pub audience: String,
+    pub issuer: String,
+}
+
+impl JwkConfiguration {
+    pub fn from_env() -> Self {
+        match dev_auth_service() {
+            Some(auth_service) => Self::dev(&auth_service),
+            None => Self::firebase(),
+        }
+    }
+
+    pub fn firebase() -> Self {
+        JwkConfiguration {
+            jwk_url: JWK_URL.to_owned(),
+            audience: PROJECT_ID.to_owned(),
+            issuer: format!("https://securetoken.google.com/{}", PROJECT_ID),
+        }
+    }
+
+    pub fn dev(auth_service: &str) -> Self {
+        JwkConfiguration {
+            jwk_url: format!("{}/dev/jwks", auth_service.trim_end_matches('/')),
+            audience: PROJECT_ID.to_owned(),
+            issuer: DEV_ISSUER.to_owned(),
+        }
+    }
+
+    pub fn is_dev(&self) -> bool {
+        self.issuer == DEV_ISSUER
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct KeyResponse {
+    pub keys: Vec<JwkKey>,
+}
+
+#[derive(Debug, Clone)]
+pub struct JwkKeys {
+    pub keys: Vec<JwkKey>,
+    pub max_age: Duration,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JwkKey {
+    pub e: String,
+    pub alg: String,
+    pub kty: String,
+    pub kid: String,
+    pub n: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FirebaseUser {
+    pub provider_id: Option<String>,
+    pub name: Option<String>,
+    pub picture: Option<String>,
+    pub iss: String,
+    pub aud: String,
+    pub auth_time: u64,
+    pub user_id: String,
+    pub sub: String,
+    pub iat: u64,
+    pub exp: u64,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub firebase: FirebaseProvider,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FirebaseProvider {
+    pub sign_in_provider: String,
+    pub identities: Map<String, Value>,
+}
+
+/// Checks tokens against the public keys of the configured issuer, fetching
+/// them again once they expire or a token names a key it does not know, at
+/// most once every [`MIN_REFRESH_INTERVAL`].
+///
+/// Verifying only takes a read lock, so it can be shared between threads
+/// without one slow fetch holding up every other token.
+#[derive(Debug)]
+pub struct Verifier {
+    config: JwkConfiguration,
+    keys: RwLock<Option<(JwkKeys, Instant)>>,
+    /// When the keys were last fetched, whether that worked or not.
+    attempted: Mutex<Option<Instant>>,
+}
+
+impl Verifier {
+    pub fn new(config: JwkConfiguration) -> Self {
+        Self {
+            config,
+            keys: RwLock::new(None),
+            attempted: Mutex::new(None),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(JwkConfiguration::from_env())
+    }
+
+    pub fn config(&self) -> &JwkConfiguration {
+        &self.config
+    }
+
+    pub fn refresh(&self) -> Result<(), PublicKeysError> {
+        *self.attempted.lock().unwrap() = Some(Instant::now());
+        let keys = get_public_keys(&self.config.jwk_url)?;
+        *self.keys.write().unwrap() = Some((keys, Instant::now()));
+        Ok(())
+    }
+
+    /// Claims the next refresh, unless one happened too recently.
+    fn may_refresh(&self) -> bool {
+        let mut attempted = self.attempted.lock().unwrap();
+        if attempted.is_some_and(|at| at.elapsed() < MIN_REFRESH_INTERVAL) {
+            return false;
+        }
+        *attempted = Some(Instant::now());
+        true
+    }
+
+    pub fn verify(&self, token: &str) -> Result<FirebaseUser, VerificationError> {
+        let header = decode_header(token).map_err(|_| VerificationError::UnkownKeyAlgorithm)?;
+
+        if header.alg != Algorithm::RS256 {
+            return Err(VerificationError::UnkownKeyAlgorithm);
+        }
+
+        let kid = match header.kid {
+            Some(v) => v,
+            None => return Err(VerificationError::NoKidHeader),
+        };
+
+        let fresh = match &*self.keys.read().unwrap() {
+            Some((keys, fetched)) => {
+                fetched.elapsed() < keys.max_age && keys.keys.iter().any(|v| v.kid == kid)
+            }
+            None => false,
+        };
+        // Expired keys keep working until the next refresh is allowed.
+        if !fresh && self.may_refresh() {
+            self.refresh()
+                .map_err(VerificationError::CannotFetchPublicKeys)?;
+        }
+
+        let decoding_key = {
+            let keys = self.keys.read().unwrap();
+            let public_key = keys
+                .as_ref()
+                .and_then(|(keys, _)| keys.keys.iter().find(|v| v.kid == kid))
+                .ok_or(VerificationError::NotfoundMatchKid)?;
+            DecodingKey::from_rsa_components(&public_key.n, &public_key.e)
+                .map_err(|_| VerificationError::CannotDecodePublicKeys)?
+        };
+
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_audience(&[self.config.audience.to_owned()]);
+        validation.set_issuer(&[self.config.issuer.to_owned()]);
+
+        let user = decode::<FirebaseUser>(token, &decoding_key, &validation)
+            .map_err(|_| VerificationError::InvalidSignature)?
+            .claims;
+        Ok(user)
+    }
+}
+
+/// Verifies a single token with the configuration from the environment.
+pub fn verify_id_token(token: &str) -> Result<FirebaseUser, VerificationError> {
+    Verifier::from_env().verify(token)
+}
+
+/// Whether `DEV_IDENTITY` turns dev identity mode on, with `1` or `true`.
+pub fn dev_identity() -> bool {
+    env::var("DEV_IDENTITY").is_ok_and(|dev| dev == "1" || dev == "true")
+}
+
+/// The address of tyche-auth in dev identity mode, `None` outside of it.
+pub fn dev_auth_service() -> Option<String> {
+    dev_identity()
+        .then(|| env::var("DEV_AUTH_SERVICE").unwrap_or(DEFAULT_DEV_AUTH_SERVICE.to_owned()))
+}
+
+/// Asks a tyche-auth in dev identity mode for a token, for tests and bots
+/// that cannot sign in through a browser.
+pub fn dev_token(auth_service: &str, user_id: &str, name: &str) -> reqwest::Result<String> {
+    reqwest::blocking::Client::new()
+        .get(format!("{}/dev/token", auth_service.trim_end_matches('/')))
+        .query(&[("user_id", user_id), ("name", name)])
+        .send()?
+        .error_for_status()?
+        .text()
+}
+
+fn get_public_keys(jwk_url: &str) -> Result<JwkKeys, PublicKeysError> {
+    let response = reqwest::blocking::get(jwk_url)
+        .and_then(|response| response.error_for_status())
+        .map_err(PublicKeysError::Fetch)?;
+
+    let cache_control = match response.headers().get("Cache-Control") {
+        Some(header_value) => header_value.to_str(),
+        None => return Err(PublicKeysError::NoCacheControlHeader),
+    };
+
+    let max_age = match cache_control {
+        Ok(v) => parse_max_age_value(v),
+        Err(_) => return Err(PublicKeysError::MaxAgeValueEmpty),
+    };
+
+    let public_keys = response
+        .json::<KeyResponse>()
+        .map_err(|_| PublicKeysError::CannotParsePublicKey)?;
+
+    Ok(JwkKeys {
+        keys: public_keys.keys,
+        max_age: max_age.unwrap_or(Duration::from_secs(60)),
+    })
+}
+
+fn parse_max_age_value(cache_control_value: &str) -> Result<Duration, PublicKeysError> {
+    let tokens: Vec<(&str, &str)> = cache_control_value
+        .split(',')
+        .map(|s| s.split('=').map(|ss| ss.trim()).collect::<Vec<&str>>())
+        .map(|ss| {
+            let key = ss.first().unwrap_or(&"");
+            let val = ss.get(1).unwrap_or(&"");
+            (*key, *val)
+        })
+        .collect();
+    match tokens
+        .iter()
+        .find(|(key, _)| key.to_lowercase() == *"max-age")
+    {
+        None => Err(PublicKeysError::NoMaxAgeSpecified),
+        Some((_, str_val)) => Ok(Duration::from_secs(
+            str_val
+                .parse()
+                .map_err(|_| PublicKeysError::NonNumericMaxAge)?,
+        )),
+    }
+}
+
+#[derive(Debug)]
+pub enum VerificationError {
+    InvalidSignature,
+    UnkownKeyAlgorithm,
+    NoKidHeader,
+    NotfoundMatchKid,
+    CannotDecodePublicKeys,
+    CannotFetchPublicKeys(PublicKeysError),
+}
+
+#[derive(Debug)]
+pub enum PublicKeysError {
+    /// The keys could not be downloaded at all.
+    Fetch(reqwest::Error),
+    NoCacheControlHeader,
+    MaxAgeValueEmpty,
+    NonNumericMaxAge,
+    NoMaxAgeSpecified,
+    CannotParsePublicKey,
+}
+
+impl fmt::Display for VerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerificationError::InvalidSignature => write!(f, "the token is not valid"),
+            VerificationError::UnkownKeyAlgorithm => {
+                write!(f, "the token is not signed with RS256")
+            }
+            VerificationError::NoKidHeader => write!(f, "the token does not name its key"),
+            VerificationError::NotfoundMatchKid => {
+                write!(f, "the token is signed with an unknown key")
+            }
+            VerificationError::CannotDecodePublicKeys => write!(f, "the public keys are malformed"),
+            VerificationError::CannotFetchPublicKeys(error) => {
+                write!(f, "cannot fetch the public keys: {error:?}")
+            }
+        }
+    }
+}
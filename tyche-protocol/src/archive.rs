@@ -0,0 +1,5 @@
+//! Campaigns are exported as zip archives holding a manifest, the room's state
+//! and the images it refers to.
+
+pub const EXTENSION: &str = "tyche";
+pub const MAX_ARCHIVE_SIZE: usize = 64 * 1024 * 1024;
@@ -0,0 +1,72 @@
+//! Images are stored and sent by the SHA-256 of their contents, so the same
+//! file never has to be uploaded or downloaded twice.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::map::{ImageFormat, CHUNK_SIZE};
+
+pub const MAX_ASSET_SIZE: usize = 20 * 1024 * 1024;
+/// The media types assets may have, checked against their contents.
+pub const ALLOWED_TYPES: &[&str] = &["image/png", "image/jpeg", "image/webp"];
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct AssetHash(pub [u8; 32]);
+
+impl AssetHash {
+    pub fn of(bytes: &[u8]) -> Self {
+        Self(Sha256::digest(bytes).into())
+    }
+
+    /// Parses the lowercase hex form the hash is displayed in.
+    pub fn parse(hex: &str) -> Option<Self> {
+        if hex.len() != 64 {
+            return None;
+        }
+        let mut hash = [0; 32];
+        for (index, byte) in hash.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(hex.get(index * 2..index * 2 + 2)?, 16).ok()?;
+        }
+        Some(Self(hash))
+    }
+}
+
+impl fmt::Display for AssetHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AssetInfo {
+    pub hash: AssetHash,
+    pub format: ImageFormat,
+    pub size: usize,
+}
+
+impl AssetInfo {
+    /// Checks the size and type of an asset before hashing it.
+    pub fn of(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.is_empty() || bytes.len() > MAX_ASSET_SIZE {
+            return Err(format!(
+                "Images can be at most {} MB",
+                MAX_ASSET_SIZE / 1024 / 1024
+            ));
+        }
+        let format = ImageFormat::detect(bytes)
+            .filter(|format| ALLOWED_TYPES.contains(&format.mime()))
+            .ok_or("Images must be PNG, JPEG or WebP")?;
+
+        Ok(Self {
+            hash: AssetHash::of(bytes),
+            format,
+            size: bytes.len(),
+        })
+    }
+
+    pub fn chunks(&self) -> usize {
+        self.size.div_ceil(CHUNK_SIZE)
+    }
+}
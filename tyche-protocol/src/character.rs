@@ -0,0 +1,284 @@
+//! The character sheet, shared by tyche-character, which stores it, and the
+//! client, which edits it. The host only ever sees [`CharacterInfo`].
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    asset::AssetInfo,
+    token::{CharacterId, Health},
+    CharacterInfo,
+};
+
+/// A character sheet as tyche-character stores it and the client edits it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Character {
+    /// Assigned when the character is created, tokens on the map link to it.
+    #[serde(default)]
+    pub id: CharacterId,
+    pub name: String,
+    #[serde(default)]
+    pub abilities: Abilities,
+    #[serde(default = "default_proficiency_bonus")]
+    pub proficiency_bonus: i32,
+    #[serde(default)]
+    pub hit_points: HitPoints,
+    #[serde(default)]
+    pub saving_throws: Vec<Ability>,
+    #[serde(default)]
+    pub skills: Vec<Skill>,
+    #[serde(default)]
+    pub attacks: Vec<Attack>,
+    /// Only ever set by uploading art to tyche-character.
+    #[serde(default)]
+    pub portrait: Option<AssetInfo>,
+    /// Top-down art for the tokens of the character.
+    #[serde(default)]
+    pub token_image: Option<AssetInfo>,
+}
+
+impl Character {
+    pub fn new(name: String) -> Self {
+        Self {
+            id: 0,
+            name,
+            abilities: Abilities::default(),
+            proficiency_bonus: default_proficiency_bonus(),
+            hit_points: HitPoints::default(),
+            saving_throws: Vec::new(),
+            skills: Vec::new(),
+            attacks: Vec::new(),
+            portrait: None,
+            token_image: None,
+        }
+    }
+
+    pub fn is_proficient(&self, skill: Skill) -> bool {
+        self.skills.contains(&skill)
+    }
+
+    pub fn save_bonus(&self, ability: Ability) -> i32 {
+        let proficiency = match self.saving_throws.contains(&ability) {
+            true => self.proficiency_bonus,
+            false => 0,
+        };
+        self.abilities.modifier(ability) + proficiency
+    }
+
+    /// What the host copies onto the token of this character.
+    pub fn info(&self) -> CharacterInfo {
+        CharacterInfo {
+            id: self.id,
+            name: self.name.clone(),
+            initiative_bonus: self.abilities.modifier(Ability::Dexterity),
+            saves: Ability::ALL.map(|ability| self.save_bonus(ability)),
+            health: Health {
+                current: self.hit_points.current,
+                max: self.hit_points.max,
+                temp: self.hit_points.temp,
+            },
+            token_image: self.token_image,
+        }
+    }
+}
+
+fn default_proficiency_bonus() -> i32 {
+    2
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct HitPoints {
+    pub current: i32,
+    pub max: i32,
+    pub temp: i32,
+}
+
+impl Default for HitPoints {
+    fn default() -> Self {
+        Self {
+            current: 10,
+            max: 10,
+            temp: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Abilities {
+    pub strength: i32,
+    pub dexterity: i32,
+    pub constitution: i32,
+    pub intelligence: i32,
+    pub wisdom: i32,
+    pub charisma: i32,
+}
+
+impl Abilities {
+    pub fn score(&self, ability: Ability) -> i32 {
+        match ability {
+            Ability::Strength => self.strength,
+            Ability::Dexterity => self.dexterity,
+            Ability::Constitution => self.constitution,
+            Ability::Intelligence => self.intelligence,
+            Ability::Wisdom => self.wisdom,
+            Ability::Charisma => self.charisma,
+        }
+    }
+
+    pub fn score_mut(&mut self, ability: Ability) -> &mut i32 {
+        match ability {
+            Ability::Strength => &mut self.strength,
+            Ability::Dexterity => &mut self.dexterity,
+            Ability::Constitution => &mut self.constitution,
+            Ability::Intelligence => &mut self.intelligence,
+            Ability::Wisdom => &mut self.wisdom,
+            Ability::Charisma => &mut self.charisma,
+        }
+    }
+
+    pub fn modifier(&self, ability: Ability) -> i32 {
+        (self.score(ability) - 10).div_euclid(2)
+    }
+}
+
+impl Default for Abilities {
+    fn default() -> Self {
+        Self {
+            strength: 10,
+            dexterity: 10,
+            constitution: 10,
+            intelligence: 10,
+            wisdom: 10,
+            charisma: 10,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Ability {
+    Strength,
+    Dexterity,
+    Constitution,
+    Intelligence,
+    Wisdom,
+    Charisma,
+}
+
+impl Ability {
+    pub const ALL: [Ability; 6] = [
+        Ability::Strength,
+        Ability::Dexterity,
+        Ability::Constitution,
+        Ability::Intelligence,
+        Ability::Wisdom,
+        Ability::Charisma,
+    ];
+
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            Ability::Strength => "STR",
+            Ability::Dexterity => "DEX",
+            Ability::Constitution => "CON",
+            Ability::Intelligence => "INT",
+            Ability::Wisdom => "WIS",
+            Ability::Charisma => "CHA",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Skill {
+    Acrobatics,
+    AnimalHandling,
+    Arcana,
+    Athletics,
+    Deception,
+    History,
+    Insight,
+    Intimidation,
+    Investigation,
+    Medicine,
+    Nature,
+    Perception,
+    Performance,
+    Persuasion,
+    Religion,
+    SleightOfHand,
+    Stealth,
+    Survival,
+}
+
+impl Skill {
+    pub const ALL: [Skill; 18] = [
+        Skill::Acrobatics,
+        Skill::AnimalHandling,
+        Skill::Arcana,
+        Skill::Athletics,
+        Skill::Deception,
+        Skill::History,
+        Skill::Insight,
+        Skill::Intimidation,
+        Skill::Investigation,
+        Skill::Medicine,
+        Skill::Nature,
+        Skill::Perception,
+        Skill::Performance,
+        Skill::Persuasion,
+        Skill::Religion,
+        Skill::SleightOfHand,
+        Skill::Stealth,
+        Skill::Survival,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Skill::Acrobatics => "Acrobatics",
+            Skill::AnimalHandling => "Animal Handling",
+            Skill::Arcana => "Arcana",
+            Skill::Athletics => "Athletics",
+            Skill::Deception => "Deception",
+            Skill::History => "History",
+            Skill::Insight => "Insight",
+            Skill::Intimidation => "Intimidation",
+            Skill::Investigation => "Investigation",
+            Skill::Medicine => "Medicine",
+            Skill::Nature => "Nature",
+            Skill::Perception => "Perception",
+            Skill::Performance => "Performance",
+            Skill::Persuasion => "Persuasion",
+            Skill::Religion => "Religion",
+            Skill::SleightOfHand => "Sleight of Hand",
+            Skill::Stealth => "Stealth",
+            Skill::Survival => "Survival",
+        }
+    }
+
+    pub fn ability(&self) -> Ability {
+        match self {
+            Skill::Athletics => Ability::Strength,
+            Skill::Acrobatics | Skill::SleightOfHand | Skill::Stealth => Ability::Dexterity,
+            Skill::Arcana
+            | Skill::History
+            | Skill::Investigation
+            | Skill::Nature
+            | Skill::Religion => Ability::Intelligence,
+            Skill::AnimalHandling
+            | Skill::Insight
+            | Skill::Medicine
+            | Skill::Perception
+            | Skill::Survival => Ability::Wisdom,
+            Skill::Deception | Skill::Intimidation | Skill::Performance | Skill::Persuasion => {
+                Ability::Charisma
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Attack {
+    pub name: String,
+    pub ability: Ability,
+    pub proficient: bool,
+    pub damage: String,
+}
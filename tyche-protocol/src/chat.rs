@@ -0,0 +1,40 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChatMessage {
+    pub room: String,
+    pub client_id: u64,
+    /// The user id of the sender, who can read their whispers again after
+    /// reconnecting.
+    pub user_id: String,
+    pub sender: String,
+    pub timestamp: u64,
+    pub kind: ChatKind,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ChatKind {
+    Say,
+    Emote,
+    /// Addressed to the user with the id `to_user`, `to` is their name at the
+    /// time.
+    Whisper {
+        to: String,
+        to_user: String,
+    },
+    Gm,
+}
+
+impl fmt::Display for ChatMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ChatKind::Say => write!(f, "{}: {}", self.sender, self.text),
+            ChatKind::Emote => write!(f, "* {} {}", self.sender, self.text),
+            ChatKind::Whisper { to, .. } => write!(f, "{} → {}: {}", self.sender, to, self.text),
+            ChatKind::Gm => write!(f, "{} → GM: {}", self.sender, self.text),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Roll {
+    pub client_id: u64,
+    /// The user id of whoever rolled, who can see their secret rolls again
+    /// after reconnecting. Empty for rolls made by the host.
+    pub user_id: String,
+    pub player: String,
+    pub character: Option<String>,
+    pub label: Option<String>,
+    pub secret: bool,
+    pub timestamp: u64,
+    pub result: RollResult,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RollResult {
+    pub expression: String,
+    pub terms: Vec<TermResult>,
+    pub total: i64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum TermResult {
+    Dice {
+        negative: bool,
+        sides: u32,
+        rolls: Vec<DieRoll>,
+    },
+    Modifier(i64),
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct DieRoll {
+    pub value: u32,
+    pub kept: bool,
+}
+
+impl fmt::Display for Roll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.secret {
+            write!(f, "(secret) ")?;
+        }
+
+        write!(f, "{}", self.player)?;
+        if let Some(character) = &self.character {
+            write!(f, " as {character}")?;
+        }
+        if let Some(label) = &self.label {
+            write!(f, ", {label}")?;
+        }
+
+        write!(f, ": {} → {}", self.result.expression, self.result)
+    }
+}
+
+impl fmt::Display for RollResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, term) in self.terms.iter().enumerate() {
+            match term {
+                TermResult::Dice {
+                    negative, rolls, ..
+                } => {
+                    if *negative {
+                        write!(f, " - ")?;
+                    } else if i > 0 {
+                        write!(f, " + ")?;
+                    }
+
+                    let rolls: Vec<String> = rolls
+                        .iter()
+                        .map(|roll| match roll.kept {
+                            true => roll.value.to_string(),
+                            false => format!("~{}~", roll.value),
+                        })
+                        .collect();
+                    write!(f, "[{}]", rolls.join(", "))?;
+                }
+                TermResult::Modifier(value) if i == 0 => write!(f, "{}", value)?,
+                TermResult::Modifier(value) if *value < 0 => write!(f, " - {}", -value)?,
+                TermResult::Modifier(value) => write!(f, " + {}", value)?,
+            }
+        }
+
+        write!(f, " = {}", self.total)
+    }
+}
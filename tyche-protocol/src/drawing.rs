@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+
+use crate::vision::Point;
+
+pub type DrawingId = u64;
+
+pub const MAX_PEN_POINTS: usize = 2000;
+pub const MAX_LABEL_LENGTH: usize = 200;
+/// How long temporary drawings stay on the map, in seconds.
+pub const TEMPORARY_DURATION: f32 = 4.0;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Shape {
+    Pen(Vec<Point>),
+    Line {
+        start: Point,
+        end: Point,
+    },
+    Rectangle {
+        start: Point,
+        end: Point,
+    },
+    Circle {
+        center: Point,
+        radius: f32,
+    },
+    /// A cone from `origin` that is as wide at `target` as it is long.
+    Cone {
+        origin: Point,
+        target: Point,
+    },
+    Text {
+        position: Point,
+        text: String,
+    },
+}
+
+impl Shape {
+    pub fn is_valid(&self) -> bool {
+        let points = match self {
+            Shape::Pen(points) => points.clone(),
+            Shape::Line { start, end }
+            | Shape::Rectangle { start, end }
+            | Shape::Cone {
+                origin: start,
+                target: end,
+            } => vec![*start, *end],
+            Shape::Circle { center, radius } if radius.is_finite() && *radius >= 0.0 => {
+                vec![*center]
+            }
+            Shape::Text { position, text }
+                if !text.trim().is_empty() && text.chars().count() <= MAX_LABEL_LENGTH =>
+            {
+                vec![*position]
+            }
+            Shape::Circle { .. } | Shape::Text { .. } => return false,
+        };
+
+        !points.is_empty()
+            && points.len() <= MAX_PEN_POINTS
+            && points.iter().flatten().all(|value| value.is_finite())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Drawing {
+    pub id: DrawingId,
+    /// The user id of whoever drew it.
+    pub owner: String,
+    pub color: [u8; 3],
+    pub temporary: bool,
+    pub shape: Shape,
+}
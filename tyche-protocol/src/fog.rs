@@ -0,0 +1,216 @@
+use std::io::{Read, Write};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::{map::CELL_SIZE, vision::Point};
+
+/// Size of a fog texel in world units.
+pub const FOG_TEXEL: f32 = CELL_SIZE / 2.0;
+/// Texels along each side of the fog mask, which is centered on the origin.
+pub const FOG_SIZE: usize = 1024;
+pub const MAX_STROKE_POINTS: usize = 4096;
+/// Largest freehand brush, in world units.
+pub const MAX_BRUSH_RADIUS: f32 = 20.0 * CELL_SIZE;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum FogBrush {
+    Reveal,
+    Hide,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum FogShape {
+    Rectangle {
+        start: Point,
+        end: Point,
+    },
+    Polygon(Vec<Point>),
+    /// A line through the points, `radius` world units thick on either side.
+    Freehand {
+        points: Vec<Point>,
+        radius: f32,
+    },
+    All,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FogStroke {
+    pub brush: FogBrush,
+    pub shape: FogShape,
+}
+
+impl FogStroke {
+    pub fn is_valid(&self) -> bool {
+        let points = match &self.shape {
+            FogShape::Rectangle { start, end } => vec![*start, *end],
+            FogShape::Polygon(points) => points.clone(),
+            FogShape::Freehand { points, radius }
+                if radius.is_finite() && *radius > 0.0 && *radius <= MAX_BRUSH_RADIUS =>
+            {
+                points.clone()
+            }
+            FogShape::Freehand { .. } => return false,
+            FogShape::All => return true,
+        };
+
+        !points.is_empty()
+            && points.len() <= MAX_STROKE_POINTS
+            && points.iter().flatten().all(|value| value.is_finite())
+    }
+}
+
+/// Which parts of a map the GM has hidden from the players, one bit per texel.
+#[derive(Clone, Debug)]
+pub struct FogMask {
+    hidden: Vec<u8>,
+}
+
+impl Default for FogMask {
+    fn default() -> Self {
+        Self {
+            hidden: vec![0; FOG_SIZE * FOG_SIZE / 8],
+        }
+    }
+}
+
+/// The texel a world position falls in, which may lie outside the mask.
+fn texel_of(point: Point) -> [i64; 2] {
+    let half = (FOG_SIZE / 2) as f32;
+    [
+        (point[0] / FOG_TEXEL + half).floor() as i64,
+        (point[1] / FOG_TEXEL + half).floor() as i64,
+    ]
+}
+
+/// The world position of the center of a texel.
+pub fn texel_center(x: usize, y: usize) -> Point {
+    let half = (FOG_SIZE / 2) as f32;
+    [
+        (x as f32 - half + 0.5) * FOG_TEXEL,
+        (y as f32 - half + 0.5) * FOG_TEXEL,
+    ]
+}
+
+fn distance_to_segment(point: Point, start: Point, end: Point) -> f32 {
+    let edge = [end[0] - start[0], end[1] - start[1]];
+    let to_point = [point[0] - start[0], point[1] - start[1]];
+    let length = edge[0] * edge[0] + edge[1] * edge[1];
+    let t = match length > 0.0 {
+        true => ((to_point[0] * edge[0] + to_point[1] * edge[1]) / length).clamp(0.0, 1.0),
+        false => 0.0,
+    };
+    (to_point[0] - edge[0] * t).hypot(to_point[1] - edge[1] * t)
+}
+
+fn inside_polygon(point: Point, polygon: &[Point]) -> bool {
+    let mut inside = false;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        if (a[1] > point[1]) != (b[1] > point[1])
+            && point[0] < a[0] + (point[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0])
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+impl FogMask {
+    pub fn is_hidden(&self, point: Point) -> bool {
+        let [x, y] = texel_of(point);
+        let size = FOG_SIZE as i64;
+        (0..size).contains(&x) && (0..size).contains(&y) && self.texel(x as usize, y as usize)
+    }
+
+    pub fn texel(&self, x: usize, y: usize) -> bool {
+        let index = y * FOG_SIZE + x;
+        self.hidden[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    fn set_texel(&mut self, x: usize, y: usize, hidden: bool) {
+        let index = y * FOG_SIZE + x;
+        match hidden {
+            true => self.hidden[index / 8] |= 1 << (index % 8),
+            false => self.hidden[index / 8] &= !(1 << (index % 8)),
+        }
+    }
+
+    /// Paints every texel within the bounds whose center is covered by the shape.
+    /// Bounds are clipped to the mask first, so shapes off the map cost nothing.
+    fn paint(&mut self, min: Point, max: Point, hidden: bool, covers: impl Fn(Point) -> bool) {
+        let last = FOG_SIZE as i64 - 1;
+        let ([min_x, min_y], [max_x, max_y]) = (texel_of(min), texel_of(max));
+        if max_x < 0 || max_y < 0 || min_x > last || min_y > last {
+            return;
+        }
+        let clamp = |value: i64| value.clamp(0, last) as usize;
+
+        for y in clamp(min_y)..=clamp(max_y) {
+            for x in clamp(min_x)..=clamp(max_x) {
+                if covers(texel_center(x, y)) {
+                    self.set_texel(x, y, hidden);
+                }
+            }
+        }
+    }
+
+    pub fn apply(&mut self, stroke: &FogStroke) {
+        let hidden = stroke.brush == FogBrush::Hide;
+        let bounds = |points: &[Point], margin: f32| {
+            let min = points.iter().fold([f32::INFINITY; 2], |min, point| {
+                [min[0].min(point[0]), min[1].min(point[1])]
+            });
+            let max = points.iter().fold([f32::NEG_INFINITY; 2], |max, point| {
+                [max[0].max(point[0]), max[1].max(point[1])]
+            });
+            (
+                [min[0] - margin, min[1] - margin],
+                [max[0] + margin, max[1] + margin],
+            )
+        };
+
+        match &stroke.shape {
+            FogShape::Rectangle { start, end } => {
+                let (min, max) = bounds(&[*start, *end], 0.0);
+                self.paint(min, max, hidden, |point| {
+                    (min[0]..=max[0]).contains(&point[0]) && (min[1]..=max[1]).contains(&point[1])
+                });
+            }
+            FogShape::Polygon(points) => {
+                let (min, max) = bounds(points, 0.0);
+                self.paint(min, max, hidden, |point| inside_polygon(point, points));
+            }
+            FogShape::Freehand { points, radius } => {
+                for (i, start) in points.iter().enumerate() {
+                    let end = points.get(i + 1).unwrap_or(start);
+                    let (min, max) = bounds(&[*start, *end], *radius);
+                    self.paint(min, max, hidden, |point| {
+                        distance_to_segment(point, *start, *end) <= *radius
+                    });
+                }
+            }
+            FogShape::All => self.hidden.fill(if hidden { u8::MAX } else { 0 }),
+        }
+    }
+
+    pub fn compress(&self) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&self.hidden)
+            .expect("compressing into memory can't fail");
+        encoder
+            .finish()
+            .expect("compressing into memory can't fail")
+    }
+
+    pub fn decompress(bytes: &[u8]) -> Option<Self> {
+        let mut hidden = Vec::with_capacity(FOG_SIZE * FOG_SIZE / 8);
+        DeflateDecoder::new(bytes)
+            .take(hidden.capacity() as u64 + 1)
+            .read_to_end(&mut hidden)
+            .ok()?;
+
+        (hidden.len() == FOG_SIZE * FOG_SIZE / 8).then_some(Self { hidden })
+    }
+}
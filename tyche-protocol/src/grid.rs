@@ -0,0 +1,298 @@
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{map::CELL_SIZE, template::FEET_PER_CELL, vision::Point};
+
+/// A grid cell, `[x, y]` for squares and axial `[q, r]` for hexes.
+pub type Cell = [i32; 2];
+
+const SQRT_3: f32 = 1.732_050_8;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum GridKind {
+    #[default]
+    Square,
+    /// Hexes with a flat edge at the top, in columns.
+    HexFlat,
+    /// Hexes with a corner at the top, in rows.
+    HexPointy,
+    Gridless,
+}
+
+impl GridKind {
+    pub const ALL: [GridKind; 4] = [
+        GridKind::Square,
+        GridKind::HexFlat,
+        GridKind::HexPointy,
+        GridKind::Gridless,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GridKind::Square => "Square",
+            GridKind::HexFlat => "Hex (flat top)",
+            GridKind::HexPointy => "Hex (pointy top)",
+            GridKind::Gridless => "Gridless",
+        }
+    }
+}
+
+/// How moving diagonally across squares is counted.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Diagonals {
+    /// Every other diagonal costs double: 5, 10, 5, 10 feet...
+    #[default]
+    Alternating,
+    /// Every diagonal costs five feet.
+    Uniform,
+}
+
+/// Cells are [`CELL_SIZE`] wide, measured between the centers of neighbours.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Grid {
+    pub kind: GridKind,
+    pub diagonals: Diagonals,
+}
+
+/// Distance from the center of a hex to its corners.
+pub fn hex_radius() -> f32 {
+    CELL_SIZE / SQRT_3
+}
+
+/// Rounds fractional axial coordinates to the hex they fall in.
+fn round_hex(q: f32, r: f32) -> Cell {
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+    [rq as i32, rr as i32]
+}
+
+impl Grid {
+    /// The cell a point is in, gridless maps have no cells.
+    pub fn cell_at(&self, point: Point) -> Option<Cell> {
+        let [x, y] = point;
+        let radius = hex_radius();
+
+        match self.kind {
+            GridKind::Square => Some([
+                (x / CELL_SIZE).floor() as i32,
+                (y / CELL_SIZE).floor() as i32,
+            ]),
+            GridKind::HexFlat => Some(round_hex(
+                2.0 / 3.0 * x / radius,
+                (-x / 3.0 + SQRT_3 / 3.0 * y) / radius,
+            )),
+            GridKind::HexPointy => Some(round_hex(
+                (SQRT_3 / 3.0 * x - y / 3.0) / radius,
+                2.0 / 3.0 * y / radius,
+            )),
+            GridKind::Gridless => None,
+        }
+    }
+
+    pub fn center(&self, [q, r]: Cell) -> Point {
+        let (q, r) = (q as f32, r as f32);
+        let radius = hex_radius();
+
+        match self.kind {
+            GridKind::Square | GridKind::Gridless => [(q + 0.5) * CELL_SIZE, (r + 0.5) * CELL_SIZE],
+            GridKind::HexFlat => [radius * 1.5 * q, radius * SQRT_3 * (r + q / 2.0)],
+            GridKind::HexPointy => [radius * SQRT_3 * (q + r / 2.0), radius * 1.5 * r],
+        }
+    }
+
+    /// The outline of a cell.
+    pub fn corners(&self, cell: Cell) -> Vec<Point> {
+        let [x, y] = self.center(cell);
+        let half = CELL_SIZE / 2.0;
+
+        let first_corner = match self.kind {
+            GridKind::Square | GridKind::Gridless => {
+                return vec![
+                    [x - half, y - half],
+                    [x + half, y - half],
+                    [x + half, y + half],
+                    [x - half, y + half],
+                ];
+            }
+            GridKind::HexFlat => 0.0,
+            GridKind::HexPointy => PI / 6.0,
+        };
+        (0..6)
+            .map(|i| {
+                let angle = first_corner + i as f32 * PI / 3.0;
+                [
+                    x + hex_radius() * angle.cos(),
+                    y + hex_radius() * angle.sin(),
+                ]
+            })
+            .collect()
+    }
+
+    /// Moves a point to the center of its cell.
+    pub fn snap(&self, point: Point) -> Point {
+        self.cell_at(point).map_or(point, |cell| self.center(cell))
+    }
+
+    /// Moves a point to the nearest corner of its cell.
+    pub fn snap_to_corner(&self, point: Point) -> Point {
+        let Some(cell) = self.cell_at(point) else {
+            return point;
+        };
+        let distance = |corner: &Point| (corner[0] - point[0]).hypot(corner[1] - point[1]);
+
+        self.corners(cell)
+            .into_iter()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .unwrap_or(point)
+    }
+
+    /// Distance in feet between the cells two points are in, or in a straight
+    /// line on gridless maps.
+    pub fn distance_feet(&self, from: Point, to: Point) -> f32 {
+        let (Some(a), Some(b)) = (self.cell_at(from), self.cell_at(to)) else {
+            let distance = (to[0] - from[0]).hypot(to[1] - from[1]);
+            return distance / CELL_SIZE * FEET_PER_CELL;
+        };
+        let (dx, dy) = ((b[0] - a[0]).abs(), (b[1] - a[1]).abs());
+
+        let cells = match (self.kind, self.diagonals) {
+            (GridKind::Square, Diagonals::Uniform) => dx.max(dy),
+            (GridKind::Square, Diagonals::Alternating) => dx.max(dy) + dx.min(dy) / 2,
+            _ => (dx + dy + (b[0] - a[0] + b[1] - a[1]).abs()) / 2,
+        };
+        cells as f32 * FEET_PER_CELL
+    }
+
+    /// The cells that have their center inside the bounds.
+    pub fn cells_within(&self, min: Point, max: Point) -> Vec<Cell> {
+        let corners = [min, max, [min[0], max[1]], [max[0], min[1]]];
+        let Some(cells) = corners
+            .iter()
+            .map(|corner| self.cell_at(*corner))
+            .collect::<Option<Vec<Cell>>>()
+        else {
+            return Vec::new();
+        };
+
+        let first = [0, 1].map(|axis| cells.iter().map(|c| c[axis]).min().unwrap_or(0) - 1);
+        let last = [0, 1].map(|axis| cells.iter().map(|c| c[axis]).max().unwrap_or(0) + 1);
+        let inside =
+            |[x, y]: Point| (min[0]..=max[0]).contains(&x) && (min[1]..=max[1]).contains(&y);
+
+        (first[1]..=last[1])
+            .flat_map(|r| (first[0]..=last[0]).map(move |q| [q, r]))
+            .filter(|cell| inside(self.center(*cell)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(kind: GridKind, diagonals: Diagonals) -> Grid {
+        Grid { kind, diagonals }
+    }
+
+    fn feet(grid: &Grid, from: Cell, to: Cell) -> f32 {
+        grid.distance_feet(grid.center(from), grid.center(to))
+    }
+
+    #[test]
+    fn alternating_diagonals_cost_five_then_ten() {
+        let grid = grid(GridKind::Square, Diagonals::Alternating);
+        assert_eq!(feet(&grid, [0, 0], [1, 1]), 5.0);
+        assert_eq!(feet(&grid, [0, 0], [2, 2]), 15.0);
+        assert_eq!(feet(&grid, [0, 0], [3, 3]), 20.0);
+        assert_eq!(feet(&grid, [0, 0], [4, -4]), 30.0);
+        assert_eq!(feet(&grid, [0, 0], [5, 2]), 30.0);
+    }
+
+    #[test]
+    fn uniform_diagonals_cost_five() {
+        let grid = grid(GridKind::Square, Diagonals::Uniform);
+        assert_eq!(feet(&grid, [0, 0], [3, 3]), 15.0);
+        assert_eq!(feet(&grid, [0, 0], [5, 2]), 25.0);
+    }
+
+    #[test]
+    fn hex_distance_is_axial() {
+        for kind in [GridKind::HexFlat, GridKind::HexPointy] {
+            let grid = grid(kind, Diagonals::default());
+            assert_eq!(feet(&grid, [0, 0], [0, 0]), 0.0);
+            assert_eq!(feet(&grid, [0, 0], [1, 0]), 5.0);
+            assert_eq!(feet(&grid, [0, 0], [1, -1]), 5.0);
+            assert_eq!(feet(&grid, [0, 0], [2, -1]), 10.0);
+            assert_eq!(feet(&grid, [0, 0], [1, 1]), 10.0);
+            assert_eq!(feet(&grid, [-2, 3], [3, -1]), 25.0);
+        }
+    }
+
+    #[test]
+    fn hex_centers_are_a_cell_apart() {
+        for kind in [GridKind::HexFlat, GridKind::HexPointy] {
+            let grid = grid(kind, Diagonals::default());
+            for neighbour in [[1, 0], [0, 1], [-1, 1], [-1, 0], [0, -1], [1, -1]] {
+                let [x, y] = grid.center(neighbour);
+                assert!((x.hypot(y) - CELL_SIZE).abs() < 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn cell_at_finds_the_cell_of_its_center() {
+        for kind in [GridKind::Square, GridKind::HexFlat, GridKind::HexPointy] {
+            let grid = grid(kind, Diagonals::default());
+            for cell in [[0, 0], [3, -2], [-4, 7], [10, 10]] {
+                assert_eq!(grid.cell_at(grid.center(cell)), Some(cell));
+            }
+        }
+    }
+
+    #[test]
+    fn gridless_measures_straight_lines() {
+        let grid = grid(GridKind::Gridless, Diagonals::default());
+        assert_eq!(grid.cell_at([10.0, 10.0]), None);
+        let far = [3.0 * CELL_SIZE, 4.0 * CELL_SIZE];
+        assert_eq!(grid.distance_feet([0.0, 0.0], far), 25.0);
+    }
+
+    #[test]
+    fn cells_within_squares() {
+        let grid = grid(GridKind::Square, Diagonals::default());
+        let size = 2.0 * CELL_SIZE;
+        let cells = grid.cells_within([0.0, 0.0], [size, size]);
+        assert_eq!(cells, [[0, 0], [1, 0], [0, 1], [1, 1]]);
+    }
+
+    #[test]
+    fn cells_within_hexes_have_their_center_inside() {
+        for kind in [GridKind::HexFlat, GridKind::HexPointy] {
+            let grid = grid(kind, Diagonals::default());
+            let (min, max) = ([-100.0, -100.0], [100.0, 100.0]);
+            let cells = grid.cells_within(min, max);
+
+            assert!(cells.contains(&[0, 0]));
+            for cell in &cells {
+                let [x, y] = grid.center(*cell);
+                assert!((min[0]..=max[0]).contains(&x) && (min[1]..=max[1]).contains(&y));
+            }
+            // Every neighbour of the origin is under 100 units away.
+            assert!(cells.len() >= 7);
+        }
+    }
+
+    #[test]
+    fn cells_within_is_empty_without_a_grid() {
+        let grid = grid(GridKind::Gridless, Diagonals::default());
+        assert!(grid.cells_within([0.0, 0.0], [500.0, 500.0]).is_empty());
+    }
+}
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+use crate::token::TokenId;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum TieBreak {
+    /// The combatant with the higher initiative bonus goes first.
+    #[default]
+    Bonus,
+    /// Player controlled combatants go before the GM's.
+    PlayersFirst,
+    Random,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Combatant {
+    pub token: TokenId,
+    pub name: String,
+    pub bonus: i32,
+    pub initiative: Option<i64>,
+    pub delayed: bool,
+    /// Rolled alongside the initiative, used by [`TieBreak::Random`].
+    pub tie: u32,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Initiative {
+    pub combatants: Vec<Combatant>,
+    pub turn: usize,
+    pub round: u32,
+    pub tie_break: TieBreak,
+}
+
+impl Initiative {
+    pub fn active(&self) -> Option<&Combatant> {
+        match self.round {
+            0 => None,
+            _ => self.combatants.get(self.turn),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum InitiativeCommand {
+    Add(TokenId),
+    Remove(TokenId),
+    Set { token: TokenId, initiative: i64 },
+    RollAll,
+    Sort,
+    SetTieBreak(TieBreak),
+    Start,
+    Next,
+    Delay(TokenId),
+    Resume(TokenId),
+    Clear,
+}
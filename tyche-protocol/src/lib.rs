@@ -0,0 +1,246 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+pub mod archive;
+pub mod asset;
+pub mod character;
+pub mod chat;
+pub mod dice;
+pub mod drawing;
+pub mod fog;
+pub mod grid;
+pub mod initiative;
+pub mod map;
+pub mod template;
+pub mod token;
+pub mod vision;
+
+use asset::{AssetHash, AssetInfo};
+use character::Ability;
+use chat::ChatMessage;
+use dice::Roll;
+use drawing::{Drawing, DrawingId, Shape};
+use fog::FogStroke;
+use initiative::{Initiative, InitiativeCommand};
+use map::{Calibration, MapId, MapInfo};
+use template::{Template, TemplateId, TemplateShape};
+use token::{CharacterId, Health, StatusChange, Token, TokenId};
+use vision::{Point, Wall, WallId, WallKind};
+
+pub const PROTOCOL_ID: u64 = 7;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ClientMessage {
+    JoinRoom {
+        room: String,
+        /// The token the player signed in with, the host takes their user id
+        /// from it and their name and color from their profile.
+        token: String,
+        character: Option<CharacterInfo>,
+    },
+    Roll {
+        expression: String,
+        label: Option<String>,
+        secret: bool,
+    },
+    Chat(String),
+    SpawnToken {
+        name: String,
+        x: f32,
+        y: f32,
+        initiative_bonus: i32,
+    },
+    RemoveToken(TokenId),
+    /// Spawns an unlinked copy of a token, for groups of minions.
+    DuplicateToken(TokenId),
+    /// Gives a token its own copy of the stats of the character it follows.
+    UnlinkToken(TokenId),
+    /// The player edited the sheet of the character they play.
+    UpdateCharacter(CharacterInfo),
+    MoveToken {
+        token: TokenId,
+        x: f32,
+        y: f32,
+        /// Numbers the moves of a client, so it can tell which of the moves
+        /// it predicted the host has answered.
+        seq: u32,
+    },
+    ChangeStatus {
+        token: TokenId,
+        change: StatusChange,
+    },
+    Initiative(InitiativeCommand),
+    BeginAssetUpload {
+        hash: AssetHash,
+        size: usize,
+    },
+    AssetChunk(Vec<u8>),
+    /// Assets the client has not cached yet.
+    RequestAssets(Vec<AssetHash>),
+    /// Asks for a new snapshot after missing a delta.
+    RequestSnapshot,
+    SetMap {
+        name: String,
+        asset: AssetHash,
+    },
+    CalibrateMap(Calibration),
+    AddWall {
+        kind: WallKind,
+        start: Point,
+        end: Point,
+    },
+    RemoveWall(WallId),
+    ToggleDoor(WallId),
+    SetFog(bool),
+    PaintFog(FogStroke),
+    Draw {
+        shape: Shape,
+        color: [u8; 3],
+        temporary: bool,
+    },
+    Erase(DrawingId),
+    /// Places a new template, or moves the one with the given id.
+    PlaceTemplate {
+        id: Option<TemplateId>,
+        shape: TemplateShape,
+        size_feet: u32,
+        origin: Point,
+        angle: f32,
+    },
+    RemoveTemplate(TemplateId),
+    RollSaves {
+        template: TemplateId,
+        ability: Ability,
+        dc: Option<i64>,
+    },
+    /// Asks for the current room as an archive.
+    ExportRoom,
+    /// Starts uploading an archive, to be restored as a new room.
+    BeginImport {
+        room: String,
+        size: usize,
+    },
+    ImportChunk(Vec<u8>),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum ServerMessage {
+    Joined {
+        room: String,
+        client_id: u64,
+        is_gm: bool,
+        players: Vec<PlayerInfo>,
+        /// The version of the room this snapshot is of.
+        version: u64,
+        /// The sequence number of the last delta it includes.
+        seq: u64,
+        rolls: Vec<Roll>,
+        chat: Vec<ChatMessage>,
+        tokens: Vec<Token>,
+        initiative: Initiative,
+        map: Option<MapInfo>,
+        walls: Vec<Wall>,
+        fog: bool,
+        drawings: Vec<Drawing>,
+        templates: Vec<Template>,
+    },
+    /// Everything that changed in the room during one tick of the host, in
+    /// order. Sequence numbers go up by one with every delta a client is
+    /// sent, so missing one means the client is out of sync.
+    Delta {
+        seq: u64,
+        version: u64,
+        changes: Vec<ServerMessage>,
+    },
+    /// Someone joined the room or switched characters.
+    PlayerChanged(PlayerInfo),
+    /// The client id of someone who left the room.
+    PlayerLeft(u64),
+    Rolled(Roll),
+    Chat(ChatMessage),
+    TokenSpawned(Token),
+    TokenRemoved(TokenId),
+    /// Where a token ended up after a client moved it, the mover also gets
+    /// one with the old position when the host refused the move.
+    TokenMoved {
+        token: TokenId,
+        x: f32,
+        y: f32,
+        client_id: u64,
+        seq: u32,
+    },
+    /// A token's health or conditions changed.
+    TokenChanged(Token),
+    Initiative(Initiative),
+    MapChanged(MapInfo),
+    MapCalibrated {
+        map: MapId,
+        calibration: Calibration,
+    },
+    WallChanged(Wall),
+    WallRemoved(WallId),
+    FogChanged(bool),
+    /// The whole fog mask of a map, compressed with [`fog::FogMask::compress`].
+    FogMask {
+        map: MapId,
+        mask: Vec<u8>,
+    },
+    FogPainted {
+        map: MapId,
+        stroke: FogStroke,
+    },
+    Drawn(Drawing),
+    Erased(DrawingId),
+    TemplateChanged(Template),
+    TemplateRemoved(TemplateId),
+    /// The host has the asset, either because it was just uploaded or
+    /// because it already had it.
+    AssetStored(AssetInfo),
+    AssetChunk {
+        asset: AssetHash,
+        index: usize,
+        bytes: Vec<u8>,
+    },
+    BeginExport {
+        size: usize,
+    },
+    ExportChunk(Vec<u8>),
+    /// An uploaded archive was restored into a new room.
+    Imported {
+        room: String,
+    },
+    Error(String),
+}
+
+/// What the host needs to know about the character a player joins with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CharacterInfo {
+    pub id: CharacterId,
+    pub name: String,
+    pub initiative_bonus: i32,
+    /// Saving throw modifiers, in the order of [`Ability::ALL`].
+    pub saves: [i32; 6],
+    pub health: Health,
+    /// Art for the tokens of the character, the host only uses it once it
+    /// has the asset.
+    pub token_image: Option<AssetInfo>,
+}
+
+/// Someone in a room, as the other players see them.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PlayerInfo {
+    pub client_id: u64,
+    pub user_id: String,
+    pub name: String,
+    pub character: Option<String>,
+    /// Their tokens and chat messages are shown in this color.
+    pub color: [u8; 3],
+    pub is_gm: bool,
+}
+
+pub fn encode<T: Serialize>(message: &T) -> Vec<u8> {
+    bincode::serialize(message).expect("protocol messages are always serializable")
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Option<T> {
+    bincode::deserialize(bytes).ok()
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{asset::AssetInfo, grid::Grid};
+
+pub type MapId = u64;
+
+/// Size of a grid cell in world units, tokens take up exactly one cell.
+pub const CELL_SIZE: f32 = 64.0;
+pub const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Lines the grid of a map image up with the token grid.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Calibration {
+    pub grid: Grid,
+    /// Size of one grid cell on the image, in pixels.
+    pub cell_size: f32,
+    /// Position on the image, in pixels, of the top left corner of a cell.
+    pub offset_x: f32,
+    pub offset_y: f32,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self {
+            grid: Grid::default(),
+            cell_size: CELL_SIZE,
+            offset_x: 0.0,
+            offset_y: 0.0,
+        }
+    }
+}
+
+impl Calibration {
+    /// How much the image has to be scaled for its cells to match [`CELL_SIZE`].
+    pub fn scale(&self) -> f32 {
+        CELL_SIZE / self.cell_size.max(1.0)
+    }
+
+    /// Cells must be at least a pixel wide and every field a real number.
+    pub fn is_valid(&self) -> bool {
+        self.cell_size.is_finite()
+            && self.cell_size >= 1.0
+            && self.offset_x.is_finite()
+            && self.offset_y.is_finite()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    WebP,
+}
+
+impl ImageFormat {
+    /// Detects the format from the file's magic bytes rather than trusting its name.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageFormat::Png)
+        } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(ImageFormat::Jpeg)
+        } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+            Some(ImageFormat::WebP)
+        } else {
+            None
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::WebP => "image/webp",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpeg",
+            ImageFormat::WebP => "webp",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MapInfo {
+    pub id: MapId,
+    pub name: String,
+    pub asset: AssetInfo,
+    pub calibration: Calibration,
+}
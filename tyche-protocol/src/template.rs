@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    grid::{Cell, Grid},
+    map::CELL_SIZE,
+    vision::Point,
+};
+
+pub type TemplateId = u64;
+
+pub const FEET_PER_CELL: f32 = 5.0;
+pub const MAX_TEMPLATE_FEET: u32 = 500;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum TemplateShape {
+    /// `size` is the radius.
+    Sphere,
+    /// `size` is the length of a side, starting at the origin.
+    Cube,
+    /// `size` is the length, the cone is as wide at its end.
+    Cone,
+    /// `size` is the length, lines are always five feet wide.
+    Line,
+}
+
+impl TemplateShape {
+    pub const ALL: [TemplateShape; 4] = [
+        TemplateShape::Sphere,
+        TemplateShape::Cube,
+        TemplateShape::Cone,
+        TemplateShape::Line,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TemplateShape::Sphere => "Sphere",
+            TemplateShape::Cube => "Cube",
+            TemplateShape::Cone => "Cone",
+            TemplateShape::Line => "Line",
+        }
+    }
+}
+
+/// An area of effect on the map.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Template {
+    pub id: TemplateId,
+    /// The user id of whoever placed it.
+    pub owner: String,
+    pub shape: TemplateShape,
+    pub size_feet: u32,
+    pub origin: Point,
+    /// Direction the template points in, in radians.
+    pub angle: f32,
+}
+
+impl Template {
+    /// The size of the template in world units.
+    pub fn size(&self) -> f32 {
+        self.size_feet as f32 / FEET_PER_CELL * CELL_SIZE
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (1..=MAX_TEMPLATE_FEET).contains(&self.size_feet)
+            && self.origin.iter().all(|value| value.is_finite())
+            && self.angle.is_finite()
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        let size = self.size();
+        let offset = [point[0] - self.origin[0], point[1] - self.origin[1]];
+        // Distance along the direction of the template, and to the side of it.
+        let (sin, cos) = self.angle.sin_cos();
+        let along = offset[0] * cos + offset[1] * sin;
+        let side = (offset[1] * cos - offset[0] * sin).abs();
+
+        match self.shape {
+            TemplateShape::Sphere => offset[0].hypot(offset[1]) <= size,
+            TemplateShape::Cube => (0.0..=size).contains(&along) && side <= size / 2.0,
+            TemplateShape::Cone => (0.0..=size).contains(&along) && side <= along / 2.0,
+            TemplateShape::Line => (0.0..=size).contains(&along) && side <= CELL_SIZE / 2.0,
+        }
+    }
+
+    /// The grid cells whose centers lie inside the template.
+    pub fn cells(&self, grid: &Grid) -> Vec<Cell> {
+        let reach = self.size() + CELL_SIZE;
+        let min = [self.origin[0] - reach, self.origin[1] - reach];
+        let max = [self.origin[0] + reach, self.origin[1] + reach];
+
+        grid.cells_within(min, max)
+            .into_iter()
+            .filter(|cell| self.contains(grid.center(*cell)))
+            .collect()
+    }
+}
@@ -0,0 +1,340 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{asset::AssetInfo, character::Ability};
+
+pub type TokenId = u64;
+/// The id of a character sheet in tyche-character.
+pub type CharacterId = u64;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Condition {
+    Blinded,
+    Charmed,
+    Concentrating,
+    Deafened,
+    Frightened,
+    Grappled,
+    Incapacitated,
+    Invisible,
+    Paralyzed,
+    Petrified,
+    Poisoned,
+    Prone,
+    Restrained,
+    Stunned,
+    Unconscious,
+}
+
+impl Condition {
+    pub const ALL: [Condition; 15] = [
+        Condition::Blinded,
+        Condition::Charmed,
+        Condition::Concentrating,
+        Condition::Deafened,
+        Condition::Frightened,
+        Condition::Grappled,
+        Condition::Incapacitated,
+        Condition::Invisible,
+        Condition::Paralyzed,
+        Condition::Petrified,
+        Condition::Poisoned,
+        Condition::Prone,
+        Condition::Restrained,
+        Condition::Stunned,
+        Condition::Unconscious,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Condition::Blinded => "Blinded",
+            Condition::Charmed => "Charmed",
+            Condition::Concentrating => "Concentrating",
+            Condition::Deafened => "Deafened",
+            Condition::Frightened => "Frightened",
+            Condition::Grappled => "Grappled",
+            Condition::Incapacitated => "Incapacitated",
+            Condition::Invisible => "Invisible",
+            Condition::Paralyzed => "Paralyzed",
+            Condition::Petrified => "Petrified",
+            Condition::Poisoned => "Poisoned",
+            Condition::Prone => "Prone",
+            Condition::Restrained => "Restrained",
+            Condition::Stunned => "Stunned",
+            Condition::Unconscious => "Unconscious",
+        }
+    }
+
+    /// Two letters shown on the token's status marker.
+    pub fn abbreviation(&self) -> &'static str {
+        match self {
+            Condition::Blinded => "Bl",
+            Condition::Charmed => "Ch",
+            Condition::Concentrating => "Co",
+            Condition::Deafened => "De",
+            Condition::Frightened => "Fr",
+            Condition::Grappled => "Gr",
+            Condition::Incapacitated => "In",
+            Condition::Invisible => "Iv",
+            Condition::Paralyzed => "Pa",
+            Condition::Petrified => "Pe",
+            Condition::Poisoned => "Po",
+            Condition::Prone => "Pr",
+            Condition::Restrained => "Re",
+            Condition::Stunned => "St",
+            Condition::Unconscious => "Un",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Status {
+    pub condition: Condition,
+    /// Initiative rounds left, conditions without a duration last until removed.
+    pub rounds: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Health {
+    pub current: i32,
+    pub max: i32,
+    pub temp: i32,
+}
+
+impl Health {
+    /// A positive maximum, current hit points within it and no negative
+    /// temporary ones.
+    pub fn is_valid(&self) -> bool {
+        self.max > 0 && (0..=self.max).contains(&self.current) && self.temp >= 0
+    }
+
+    pub fn bloodied(&self) -> bool {
+        i64::from(self.current) * 2 <= i64::from(self.max)
+    }
+
+    /// Takes damage out of temporary hit points first, negative damage heals
+    /// up to the maximum.
+    pub fn damage(&mut self, amount: i32) {
+        if amount < 0 {
+            self.current = self.current.saturating_sub(amount).min(self.max);
+            return;
+        }
+
+        let absorbed = amount.min(self.temp.max(0));
+        self.temp -= absorbed;
+        self.current = self.current.saturating_sub(amount - absorbed).max(0);
+    }
+}
+
+/// How much of a token's health players other than its owner get to see.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum HealthVisibility {
+    Everyone,
+    /// Only whether the token is bloodied, at half its hit points or below.
+    #[default]
+    Bloodied,
+    GmOnly,
+}
+
+impl HealthVisibility {
+    pub const ALL: [HealthVisibility; 3] = [
+        HealthVisibility::Everyone,
+        HealthVisibility::Bloodied,
+        HealthVisibility::GmOnly,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HealthVisibility::Everyone => "Everyone",
+            HealthVisibility::Bloodied => "Bloodied only",
+            HealthVisibility::GmOnly => "GM only",
+        }
+    }
+}
+
+/// The part of a token's health a client knows about.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum HealthView {
+    #[default]
+    Unknown,
+    Bloodied(bool),
+    Full(Health),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum StatusChange {
+    /// Starts or stops tracking hit points.
+    SetHealth(Option<Health>),
+    /// Negative damage heals.
+    Damage(i32),
+    SetHealthVisibility(HealthVisibility),
+    AddCondition(Status),
+    RemoveCondition(Condition),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Token {
+    pub id: TokenId,
+    pub name: String,
+    /// The `user_id` of the player controlling this token, if any.
+    pub owner: Option<String>,
+    /// The character sheet this token follows, tokens without one keep their
+    /// own copy of their stats.
+    pub character: Option<CharacterId>,
+    pub x: f32,
+    pub y: f32,
+    pub initiative_bonus: i32,
+    /// Saving throw modifiers, in the order of [`Ability::ALL`].
+    pub saves: [i32; 6],
+    pub health: HealthView,
+    pub health_visibility: HealthVisibility,
+    pub conditions: Vec<Status>,
+    /// Drawn inside the token instead of a plain fill.
+    #[serde(default)]
+    pub image: Option<AssetInfo>,
+}
+
+impl Token {
+    /// A token without an owner or stats, spawning it gives it an id.
+    pub fn new(name: String, x: f32, y: f32) -> Self {
+        Self {
+            id: 0,
+            name,
+            owner: None,
+            character: None,
+            x,
+            y,
+            initiative_bonus: 0,
+            saves: [0; 6],
+            health: HealthView::Unknown,
+            health_visibility: HealthVisibility::default(),
+            conditions: Vec::new(),
+            image: None,
+        }
+    }
+
+    /// What players other than the owner may know about this token.
+    pub fn redacted(&self) -> Token {
+        let health = match (self.health, self.health_visibility) {
+            (HealthView::Full(health), HealthVisibility::Bloodied) => {
+                HealthView::Bloodied(health.bloodied())
+            }
+            (_, HealthVisibility::GmOnly) => HealthView::Unknown,
+            (health, _) => health,
+        };
+
+        Token {
+            health,
+            ..self.clone()
+        }
+    }
+
+    pub fn has_condition(&self, condition: Condition) -> bool {
+        self.conditions
+            .iter()
+            .any(|status| status.condition == condition)
+    }
+
+    /// Applies a change to the token's health or conditions, returning whether
+    /// anything changed.
+    pub fn apply(&mut self, change: &StatusChange) -> bool {
+        match change {
+            StatusChange::SetHealth(health) => {
+                let health = health.map_or(HealthView::Unknown, HealthView::Full);
+                if self.health == health {
+                    return false;
+                }
+                self.health = health;
+            }
+            StatusChange::Damage(amount) => {
+                let HealthView::Full(health) = &mut self.health else {
+                    return false;
+                };
+                health.damage(*amount);
+            }
+            StatusChange::SetHealthVisibility(visibility) => {
+                self.health_visibility = *visibility;
+            }
+            StatusChange::AddCondition(status) => {
+                self.conditions.retain(|s| s.condition != status.condition);
+                self.conditions.push(*status);
+                self.conditions.sort_by_key(|s| s.condition);
+            }
+            StatusChange::RemoveCondition(condition) => {
+                if !self.has_condition(*condition) {
+                    return false;
+                }
+                self.conditions.retain(|s| s.condition != *condition);
+            }
+        }
+        true
+    }
+
+    /// Counts down condition durations at the start of a new round, dropping
+    /// the ones that ran out. Returns whether anything changed.
+    pub fn next_round(&mut self) -> bool {
+        if self.conditions.iter().all(|status| status.rounds.is_none()) {
+            return false;
+        }
+
+        for status in &mut self.conditions {
+            status.rounds = status.rounds.map(|rounds| rounds.saturating_sub(1));
+        }
+        self.conditions.retain(|status| status.rounds != Some(0));
+        true
+    }
+
+    pub fn save(&self, ability: Ability) -> i32 {
+        self.saves[ability as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health(current: i32, max: i32, temp: i32) -> Health {
+        Health { current, max, temp }
+    }
+
+    #[test]
+    fn damage_comes_out_of_temporary_hit_points_first() {
+        let mut hp = health(10, 20, 5);
+        hp.damage(8);
+        assert_eq!(hp, health(7, 20, 0));
+        hp.damage(100);
+        assert_eq!(hp, health(0, 20, 0));
+    }
+
+    #[test]
+    fn healing_stops_at_the_maximum() {
+        let mut hp = health(3, 20, 0);
+        hp.damage(-5);
+        assert_eq!(hp, health(8, 20, 0));
+        hp.damage(-100);
+        assert_eq!(hp, health(20, 20, 0));
+    }
+
+    #[test]
+    fn extreme_amounts_do_not_overflow() {
+        let mut hp = health(10, 20, 0);
+        hp.damage(i32::MIN);
+        assert_eq!(hp, health(20, 20, 0));
+        hp.damage(i32::MAX);
+        assert_eq!(hp, health(0, 20, 0));
+
+        let mut hp = health(i32::MAX, i32::MAX, 0);
+        assert!(!hp.bloodied());
+        hp.damage(i32::MIN);
+        assert_eq!(hp.current, i32::MAX);
+    }
+
+    #[test]
+    fn valid_health_stays_in_range() {
+        assert!(health(0, 1, 0).is_valid());
+        assert!(health(20, 20, 5).is_valid());
+        assert!(!health(0, 0, 0).is_valid());
+        assert!(!health(21, 20, 0).is_valid());
+        assert!(!health(-1, 20, 0).is_valid());
+        assert!(!health(10, 20, -1).is_valid());
+    }
+}
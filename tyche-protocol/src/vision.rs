@@ -0,0 +1,250 @@
+use std::f32::consts::TAU;
+
+use serde::{Deserialize, Serialize};
+
+use crate::map::CELL_SIZE;
+
+pub type WallId = u64;
+pub type Point = [f32; 2];
+
+/// How far tokens can see when nothing is in the way.
+pub const VISION_RANGE: f32 = 60.0 * CELL_SIZE;
+/// Rays cast around a token on top of those aimed at wall ends.
+const RANGE_RAYS: usize = 64;
+/// Rays aimed just past a wall end, so vision wraps around corners.
+const CORNER_OFFSET: f32 = 0.0001;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub enum WallKind {
+    Wall,
+    Door {
+        open: bool,
+    },
+    /// Can be seen through, but not walked through.
+    Window,
+}
+
+impl WallKind {
+    pub fn blocks_sight(&self) -> bool {
+        match self {
+            WallKind::Wall => true,
+            WallKind::Door { open } => !open,
+            WallKind::Window => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Wall {
+    pub id: WallId,
+    pub kind: WallKind,
+    pub start: Point,
+    pub end: Point,
+}
+
+fn sub(a: Point, b: Point) -> Point {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn cross(a: Point, b: Point) -> f32 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+fn length(a: Point) -> f32 {
+    a[0].hypot(a[1])
+}
+
+/// Where along `direction` a ray from `origin` hits the segment, if it does.
+fn ray_hit(origin: Point, direction: Point, start: Point, end: Point) -> Option<f32> {
+    let edge = sub(end, start);
+    let denominator = cross(direction, edge);
+    if denominator.abs() < f32::EPSILON {
+        return None;
+    }
+
+    let to_start = sub(start, origin);
+    let t = cross(to_start, edge) / denominator;
+    let u = cross(to_start, direction) / denominator;
+    (t >= 0.0 && (0.0..=1.0).contains(&u)).then_some(t)
+}
+
+/// Whether anything blocks the line of sight from one point to another. A
+/// wall that either point is on does not, so tokens in a doorway see out.
+pub fn can_see<'a>(walls: impl IntoIterator<Item = &'a Wall>, from: Point, to: Point) -> bool {
+    let direction = sub(to, from);
+    if length(direction) > VISION_RANGE {
+        return false;
+    }
+
+    !walls
+        .into_iter()
+        .filter(|wall| wall.kind.blocks_sight())
+        .any(|wall| {
+            ray_hit(from, direction, wall.start, wall.end).is_some_and(|t| t > 0.0 && t < 1.0)
+        })
+}
+
+/// The area visible from `origin`, as a polygon around it ordered by angle.
+pub fn vision_polygon<'a>(walls: impl IntoIterator<Item = &'a Wall>, origin: Point) -> Vec<Point> {
+    let walls: Vec<&Wall> = walls
+        .into_iter()
+        .filter(|wall| wall.kind.blocks_sight())
+        .collect();
+
+    let mut angles: Vec<f32> = (0..RANGE_RAYS)
+        .map(|i| i as f32 * TAU / RANGE_RAYS as f32)
+        .collect();
+    for point in walls.iter().flat_map(|wall| [wall.start, wall.end]) {
+        let to_point = sub(point, origin);
+        if length(to_point) > VISION_RANGE {
+            continue;
+        }
+
+        let angle = to_point[1].atan2(to_point[0]);
+        angles.extend([angle - CORNER_OFFSET, angle, angle + CORNER_OFFSET]);
+    }
+    angles.sort_by(f32::total_cmp);
+
+    angles
+        .into_iter()
+        .map(|angle| {
+            let direction = [angle.cos(), angle.sin()];
+            let distance = walls
+                .iter()
+                .filter_map(|wall| ray_hit(origin, direction, wall.start, wall.end))
+                .fold(VISION_RANGE, f32::min);
+            [
+                origin[0] + direction[0] * distance,
+                origin[1] + direction[1] * distance,
+            ]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wall(kind: WallKind, start: Point, end: Point) -> Wall {
+        Wall {
+            id: 0,
+            kind,
+            start,
+            end,
+        }
+    }
+
+    /// A wall across the x axis at x = 100.
+    fn across(kind: WallKind) -> Wall {
+        wall(kind, [100.0, -50.0], [100.0, 50.0])
+    }
+
+    #[test]
+    fn rays_hit_segments_in_front_of_them() {
+        let hit = ray_hit([0.0, 0.0], [1.0, 0.0], [100.0, -50.0], [100.0, 50.0]);
+        assert_eq!(hit, Some(100.0));
+        assert_eq!(
+            ray_hit([0.0, 0.0], [-1.0, 0.0], [100.0, -50.0], [100.0, 50.0]),
+            None
+        );
+        assert_eq!(
+            ray_hit([0.0, 0.0], [1.0, 0.0], [100.0, 10.0], [100.0, 50.0]),
+            None
+        );
+        // Parallel to the wall.
+        assert_eq!(
+            ray_hit([0.0, 0.0], [0.0, 1.0], [100.0, -50.0], [100.0, 50.0]),
+            None
+        );
+    }
+
+    #[test]
+    fn walls_and_closed_doors_block_sight() {
+        let (from, to) = ([0.0, 0.0], [200.0, 0.0]);
+        assert!(can_see([], from, to));
+        assert!(!can_see([&across(WallKind::Wall)], from, to));
+        assert!(!can_see(
+            [&across(WallKind::Door { open: false })],
+            from,
+            to
+        ));
+        assert!(can_see([&across(WallKind::Door { open: true })], from, to));
+        assert!(can_see([&across(WallKind::Window)], from, to));
+        // Both ways.
+        assert!(!can_see([&across(WallKind::Wall)], to, from));
+        // Around the end of it.
+        assert!(can_see([&across(WallKind::Wall)], from, [200.0, 120.0]));
+    }
+
+    #[test]
+    fn points_on_a_wall_see_and_are_seen() {
+        let wall = across(WallKind::Wall);
+        assert!(can_see([&wall], [0.0, 0.0], [100.0, 0.0]));
+        assert!(can_see([&wall], [100.0, 0.0], [0.0, 0.0]));
+        assert!(can_see([&wall], [100.0, 0.0], [200.0, 0.0]));
+        // The very end of a wall counts as on it.
+        assert!(can_see([&wall], [0.0, 0.0], [100.0, 50.0]));
+    }
+
+    #[test]
+    fn sight_through_where_walls_meet_is_blocked() {
+        let corner = [
+            wall(WallKind::Wall, [100.0, 0.0], [100.0, 100.0]),
+            wall(WallKind::Wall, [0.0, 100.0], [100.0, 100.0]),
+        ];
+        assert!(!can_see(&corner, [50.0, 50.0], [150.0, 150.0]));
+        assert!(!can_see(&corner[..1], [50.0, 50.0], [150.0, 150.0]));
+    }
+
+    #[test]
+    fn sight_ends_at_the_vision_range() {
+        let origin = [0.0, 0.0];
+        assert!(can_see([], origin, [VISION_RANGE, 0.0]));
+        assert!(can_see([], origin, [0.0, -VISION_RANGE]));
+        assert!(!can_see([], origin, [VISION_RANGE + 1.0, 0.0]));
+    }
+
+    #[test]
+    fn vision_without_walls_is_a_circle() {
+        let origin = [10.0, 20.0];
+        let polygon = vision_polygon([], origin);
+        assert_eq!(polygon.len(), RANGE_RAYS);
+        for point in polygon {
+            assert!((length(sub(point, origin)) - VISION_RANGE).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn vision_stops_at_walls() {
+        let room = [
+            wall(WallKind::Wall, [-100.0, -100.0], [100.0, -100.0]),
+            wall(WallKind::Wall, [100.0, -100.0], [100.0, 100.0]),
+            wall(WallKind::Wall, [100.0, 100.0], [-100.0, 100.0]),
+            wall(WallKind::Wall, [-100.0, 100.0], [-100.0, -100.0]),
+        ];
+        let polygon = vision_polygon(&room, [0.0, 0.0]);
+
+        // Each corner is looked at three times, next to the range rays.
+        assert_eq!(polygon.len(), RANGE_RAYS + 8 * 3);
+        for [x, y] in &polygon {
+            assert!(x.abs() <= 100.01 && y.abs() <= 100.01, "{x}, {y}");
+        }
+        // The rays aimed at the corners reach them.
+        for corner in [[100.0, 100.0], [-100.0, -100.0]] {
+            let closest = polygon
+                .iter()
+                .map(|point| length(sub(*point, corner)))
+                .fold(f32::MAX, f32::min);
+            assert!(closest < 0.01);
+        }
+    }
+
+    #[test]
+    fn windows_and_far_walls_do_not_shape_vision() {
+        let window = wall(WallKind::Window, [100.0, -50.0], [100.0, 50.0]);
+        let far = [VISION_RANGE * 2.0, 0.0];
+        let beyond = wall(WallKind::Wall, far, [far[0], 50.0]);
+        let polygon = vision_polygon([&window, &beyond], [0.0, 0.0]);
+        assert_eq!(polygon.len(), RANGE_RAYS);
+    }
+}